@@ -0,0 +1,30 @@
+ALTER TYPE Role ADD VALUE 'student';
+ALTER TYPE Role ADD VALUE 'parent';
+
+CREATE TABLE Announcements(
+    id SERIAL PRIMARY KEY,
+    author_id INTEGER NOT NULL REFERENCES Employees ON DELETE CASCADE,
+    title VARCHAR(128) NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    published_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ,
+
+    CHECK (expires_at IS NULL OR expires_at > published_at)
+);
+
+CREATE TYPE AudienceKind AS ENUM('everyone', 'role', 'class', 'class_parents');
+
+CREATE TABLE AnnouncementAudiences(
+    id SERIAL PRIMARY KEY,
+    announcement_id INTEGER NOT NULL REFERENCES Announcements ON DELETE CASCADE,
+    kind AudienceKind NOT NULL,
+    role Role,
+    class_id INTEGER REFERENCES Classes ON DELETE CASCADE,
+
+    CHECK ((kind = 'role') = (role IS NOT NULL)),
+    CHECK ((kind IN ('class', 'class_parents')) = (class_id IS NOT NULL))
+);
+
+CREATE INDEX ON AnnouncementAudiences(announcement_id);
+CREATE INDEX ON Announcements(published_at, expires_at);
@@ -0,0 +1,27 @@
+CREATE TABLE Conversations(
+    id SERIAL PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE ConversationParticipants(
+    conversation_id INTEGER NOT NULL REFERENCES Conversations ON DELETE CASCADE,
+    role Role NOT NULL,
+    user_id INTEGER NOT NULL,
+    last_read_message_id INTEGER NOT NULL DEFAULT 0,
+
+    PRIMARY KEY (conversation_id, role, user_id)
+);
+
+CREATE TABLE Messages(
+    id SERIAL PRIMARY KEY,
+    conversation_id INTEGER NOT NULL REFERENCES Conversations ON DELETE CASCADE,
+    sender_role Role NOT NULL,
+    sender_id INTEGER NOT NULL,
+    body TEXT NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    CHECK (length(body) > 0)
+);
+
+CREATE INDEX ON ConversationParticipants(role, user_id);
+CREATE INDEX ON Messages(conversation_id, id);
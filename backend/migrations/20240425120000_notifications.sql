@@ -0,0 +1,16 @@
+ALTER TABLE Employees ADD COLUMN email VARCHAR(128);
+ALTER TABLE Students ADD COLUMN email VARCHAR(128);
+ALTER TABLE Parents ADD COLUMN email VARCHAR(128);
+
+CREATE TYPE NotificationEvent AS ENUM('new_mark', 'absence', 'announcement', 'homework_due');
+CREATE TYPE NotificationChannel AS ENUM('email', 'sms', 'log');
+
+CREATE TABLE NotificationPreferences(
+    role Role NOT NULL,
+    user_id INTEGER NOT NULL,
+    event NotificationEvent NOT NULL,
+    channel NotificationChannel NOT NULL,
+    enabled BOOLEAN NOT NULL,
+
+    PRIMARY KEY (role, user_id, event, channel)
+);
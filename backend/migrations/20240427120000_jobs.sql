@@ -0,0 +1,15 @@
+CREATE TYPE JobStatus AS ENUM('pending', 'done', 'failed');
+
+CREATE TABLE Jobs(
+    id BIGSERIAL PRIMARY KEY,
+    payload JSONB NOT NULL,
+    status JobStatus NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 5,
+    run_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX ON Jobs(run_at, id) WHERE status = 'pending';
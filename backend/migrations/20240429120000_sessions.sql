@@ -0,0 +1,13 @@
+CREATE TABLE Sessions(
+    id VARCHAR(32) PRIMARY KEY,
+    role Role NOT NULL,
+    user_id INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    user_agent TEXT,
+    ip VARCHAR(45)
+);
+
+CREATE INDEX ON Sessions(role, user_id);
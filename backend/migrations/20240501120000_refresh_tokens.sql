@@ -0,0 +1,9 @@
+CREATE TABLE RefreshTokens(
+    token_hash VARCHAR(64) PRIMARY KEY,
+    session_id VARCHAR(32) NOT NULL REFERENCES Sessions ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ
+);
+
+CREATE INDEX ON RefreshTokens(session_id);
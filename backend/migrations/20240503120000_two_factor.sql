@@ -0,0 +1,25 @@
+ALTER TABLE Employees ADD COLUMN totp_secret VARCHAR(64);
+ALTER TABLE Employees ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE RecoveryCodes(
+    employee_id INTEGER NOT NULL REFERENCES Employees ON DELETE CASCADE,
+    code_hash VARCHAR(64) NOT NULL,
+    used_at TIMESTAMPTZ,
+
+    PRIMARY KEY (employee_id, code_hash)
+);
+
+CREATE TABLE LoginChallenges(
+    id VARCHAR(32) PRIMARY KEY,
+    employee_id INTEGER NOT NULL REFERENCES Employees ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0
+);
+
+-- Sessions of principals who have to enroll into 2FA before using anything but `/auth`
+ALTER TABLE Sessions ADD COLUMN totp_pending BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE Settings(
+    key VARCHAR(64) PRIMARY KEY,
+    value JSONB NOT NULL
+);
@@ -0,0 +1,20 @@
+-- Set for accounts whose password was chosen by somebody else
+ALTER TABLE Employees ADD COLUMN password_change_required BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE Students ADD COLUMN password_change_required BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE Parents ADD COLUMN password_change_required BOOLEAN NOT NULL DEFAULT false;
+
+-- Sessions which can only be used to change the password
+ALTER TABLE Sessions ADD COLUMN password_change_pending BOOLEAN NOT NULL DEFAULT false;
+
+ALTER TYPE NotificationEvent ADD VALUE 'password_reset';
+
+-- One-time codes issued by principals, a new code replaces the previous one
+CREATE TABLE PasswordResets(
+    role Role NOT NULL,
+    user_id INTEGER NOT NULL,
+    code_hash VARCHAR(64) NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+
+    PRIMARY KEY (role, user_id)
+);
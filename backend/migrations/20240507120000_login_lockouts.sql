@@ -0,0 +1,24 @@
+CREATE TYPE ThrottleScope AS ENUM('account', 'ip');
+
+-- Consecutive failed logins per phone number and per client address
+CREATE TABLE LoginThrottles(
+    scope ThrottleScope NOT NULL,
+    subject VARCHAR(64) NOT NULL,
+    failures INTEGER NOT NULL DEFAULT 0,
+    last_failure_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    locked_until TIMESTAMPTZ,
+
+    PRIMARY KEY (scope, subject)
+);
+
+CREATE TABLE FailedLogins(
+    id BIGSERIAL PRIMARY KEY,
+    phone VARCHAR(32) NOT NULL,
+    ip VARCHAR(64) NOT NULL,
+    user_agent TEXT,
+    -- The attempt was rejected without checking the password
+    locked BOOLEAN NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON FailedLogins(phone, created_at);
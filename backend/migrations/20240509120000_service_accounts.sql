@@ -0,0 +1,23 @@
+-- Every scope grants read-only access
+CREATE TYPE ApiScope AS ENUM('marks', 'students', 'teachers');
+
+-- Non-human clients such as reporting scripts, authenticated by API keys
+CREATE TABLE ServiceAccounts(
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(64) NOT NULL UNIQUE,
+    created_by INTEGER REFERENCES Employees ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE ApiKeys(
+    id SERIAL PRIMARY KEY,
+    service_account_id INTEGER NOT NULL REFERENCES ServiceAccounts ON DELETE CASCADE,
+    -- First characters of the key to tell keys apart, the key itself is only stored hashed
+    prefix VARCHAR(16) NOT NULL,
+    key_hash VARCHAR(64) NOT NULL UNIQUE,
+    scopes ApiScope[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ
+);
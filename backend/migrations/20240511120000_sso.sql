@@ -0,0 +1,18 @@
+-- Logins started with the identity provider, valid until the user returns to the callback
+CREATE TABLE SsoLogins(
+    state VARCHAR(64) PRIMARY KEY,
+    nonce VARCHAR(64) NOT NULL,
+    code_verifier VARCHAR(128) NOT NULL,
+    redirect_to TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+-- Subjects of the identity provider linked to employees
+CREATE TABLE EmployeeIdentities(
+    issuer TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    employee_id INTEGER NOT NULL REFERENCES Employees ON DELETE CASCADE,
+    linked_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (issuer, subject)
+);
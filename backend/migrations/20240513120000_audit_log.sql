@@ -0,0 +1,23 @@
+CREATE TYPE AuditAction AS ENUM('create', 'update', 'delete');
+CREATE TYPE AuditEntity AS ENUM(
+    'subject', 'class', 'room', 'student', 'teacher', 'principal', 'mark', 'announcement',
+    'security_policy', 'service_account', 'api_key'
+);
+
+CREATE TABLE AuditLog(
+    id BIGSERIAL PRIMARY KEY,
+    -- Not set for requests without authentication
+    actor_role Role,
+    actor_id INTEGER,
+    service_account_id INTEGER,
+    action AuditAction NOT NULL,
+    entity AuditEntity NOT NULL,
+    entity_id INTEGER,
+    before JSONB,
+    after JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON AuditLog(entity, entity_id);
+CREATE INDEX ON AuditLog(actor_role, actor_id);
+CREATE INDEX ON AuditLog(created_at);
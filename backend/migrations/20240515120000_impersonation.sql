@@ -0,0 +1,4 @@
+-- Set for sessions of a principal acting as another user
+ALTER TABLE Sessions ADD COLUMN impersonator_id INTEGER REFERENCES Employees ON DELETE CASCADE;
+ALTER TABLE AuditLog ADD COLUMN impersonator_id INTEGER;
+ALTER TYPE AuditEntity ADD VALUE 'impersonation';
@@ -0,0 +1,25 @@
+ALTER TYPE Role ADD VALUE 'staff';
+ALTER TYPE AuditEntity ADD VALUE 'staff';
+ALTER TYPE AuditEntity ADD VALUE 'access_role';
+ALTER TYPE AuditEntity ADD VALUE 'role_assignment';
+
+CREATE TYPE Permission AS ENUM(
+    'classes.write', 'subjects.write', 'rooms.write', 'students.write', 'teachers.write',
+    'staff.write', 'principals.write', 'marks.write.all', 'announcements.manage', 'audit.read',
+    'security.manage', 'passwords.reset', 'service_accounts.manage', 'impersonate', 'roles.manage'
+);
+
+CREATE TABLE AccessRoles(
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(64) NOT NULL UNIQUE,
+    permissions Permission[] NOT NULL
+);
+
+CREATE TABLE EmployeeAccessRoles(
+    employee_id INTEGER NOT NULL REFERENCES Employees ON DELETE CASCADE,
+    access_role_id INTEGER NOT NULL REFERENCES AccessRoles ON DELETE CASCADE,
+
+    PRIMARY KEY (employee_id, access_role_id)
+);
+
+CREATE INDEX ON EmployeeAccessRoles(access_role_id);
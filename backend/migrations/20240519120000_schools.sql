@@ -0,0 +1,116 @@
+CREATE TABLE Schools(
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(64) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Everything created before schools were introduced belongs to the first one
+INSERT INTO Schools(id, name) VALUES (1, 'Школа №1');
+SELECT setval('schools_id_seq', 1);
+
+-- District administrators manage schools and belong to none of them
+ALTER TYPE Role ADD VALUE 'admin';
+ALTER TYPE AuditEntity ADD VALUE 'school';
+ALTER TYPE AuditEntity ADD VALUE 'admin';
+
+ALTER TABLE Subjects ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1 REFERENCES Schools;
+ALTER TABLE Rooms ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1 REFERENCES Schools;
+ALTER TABLE Classes ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1 REFERENCES Schools;
+ALTER TABLE Employees ADD COLUMN school_id INTEGER DEFAULT 1 REFERENCES Schools;
+ALTER TABLE Teachers ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE Students ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1 REFERENCES Schools;
+ALTER TABLE Parents ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1 REFERENCES Schools;
+ALTER TABLE Marks ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE Announcements ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1 REFERENCES Schools;
+ALTER TABLE AnnouncementAudiences ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE Conversations ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1 REFERENCES Schools;
+ALTER TABLE ServiceAccounts ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1 REFERENCES Schools;
+ALTER TABLE AccessRoles ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1 REFERENCES Schools;
+ALTER TABLE EmployeeAccessRoles ADD COLUMN school_id INTEGER NOT NULL DEFAULT 1;
+-- Not set for sessions of district administrators and for district-level audit entries
+ALTER TABLE Sessions ADD COLUMN school_id INTEGER DEFAULT 1 REFERENCES Schools;
+ALTER TABLE AuditLog ADD COLUMN school_id INTEGER DEFAULT 1 REFERENCES Schools;
+
+ALTER TABLE Subjects ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE Rooms ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE Classes ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE Employees ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE Teachers ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE Students ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE Parents ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE Marks ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE Announcements ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE AnnouncementAudiences ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE Conversations ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE ServiceAccounts ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE AccessRoles ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE EmployeeAccessRoles ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE Sessions ALTER COLUMN school_id DROP DEFAULT;
+ALTER TABLE AuditLog ALTER COLUMN school_id DROP DEFAULT;
+
+-- Names are unique within a school. Phones stay globally unique as they are used to log in
+ALTER TABLE Subjects DROP CONSTRAINT subjects_subject_key;
+ALTER TABLE Subjects ADD CONSTRAINT subjects_subject_key UNIQUE (school_id, subject);
+ALTER TABLE Rooms DROP CONSTRAINT rooms_room_key;
+ALTER TABLE Rooms ADD CONSTRAINT rooms_room_key UNIQUE (school_id, room);
+ALTER TABLE Classes DROP CONSTRAINT classes_class_key;
+ALTER TABLE Classes ADD CONSTRAINT classes_class_key UNIQUE (school_id, class);
+ALTER TABLE ServiceAccounts DROP CONSTRAINT serviceaccounts_name_key;
+ALTER TABLE ServiceAccounts ADD CONSTRAINT serviceaccounts_name_key UNIQUE (school_id, name);
+ALTER TABLE AccessRoles DROP CONSTRAINT accessroles_name_key;
+ALTER TABLE AccessRoles ADD CONSTRAINT accessroles_name_key UNIQUE (school_id, name);
+
+-- References include the school, so rows of one school cannot point to another one.
+-- Constraint names are kept as the handlers tell the violated references apart by them
+ALTER TABLE Subjects ADD UNIQUE (school_id, id);
+ALTER TABLE Rooms ADD UNIQUE (school_id, id);
+ALTER TABLE Classes ADD UNIQUE (school_id, id);
+ALTER TABLE Employees ADD UNIQUE (school_id, id);
+ALTER TABLE Teachers ADD UNIQUE (school_id, employee_id);
+ALTER TABLE Students ADD UNIQUE (school_id, id);
+ALTER TABLE AccessRoles ADD UNIQUE (school_id, id);
+
+ALTER TABLE Rooms DROP CONSTRAINT rooms_subject_id_fkey;
+ALTER TABLE Rooms ADD CONSTRAINT rooms_subject_id_fkey
+    FOREIGN KEY (school_id, subject_id) REFERENCES Subjects(school_id, id);
+
+ALTER TABLE Teachers DROP CONSTRAINT teachers_employee_id_fkey;
+ALTER TABLE Teachers ADD CONSTRAINT teachers_employee_id_fkey
+    FOREIGN KEY (school_id, employee_id) REFERENCES Employees(school_id, id) ON DELETE CASCADE;
+ALTER TABLE Teachers DROP CONSTRAINT teachers_room_id_fkey;
+ALTER TABLE Teachers ADD CONSTRAINT teachers_room_id_fkey
+    FOREIGN KEY (school_id, room_id) REFERENCES Rooms(school_id, id);
+ALTER TABLE Teachers DROP CONSTRAINT teachers_subject_id_fkey;
+ALTER TABLE Teachers ADD CONSTRAINT teachers_subject_id_fkey
+    FOREIGN KEY (school_id, subject_id) REFERENCES Subjects(school_id, id);
+
+ALTER TABLE Students DROP CONSTRAINT students_class_id_fkey;
+ALTER TABLE Students ADD CONSTRAINT students_class_id_fkey
+    FOREIGN KEY (school_id, class_id) REFERENCES Classes(school_id, id);
+
+ALTER TABLE Marks DROP CONSTRAINT marks_student_id_fkey;
+ALTER TABLE Marks ADD CONSTRAINT marks_student_id_fkey
+    FOREIGN KEY (school_id, student_id) REFERENCES Students(school_id, id);
+ALTER TABLE Marks DROP CONSTRAINT marks_subject_id_fkey;
+ALTER TABLE Marks ADD CONSTRAINT marks_subject_id_fkey
+    FOREIGN KEY (school_id, subject_id) REFERENCES Subjects(school_id, id);
+ALTER TABLE Marks DROP CONSTRAINT marks_teacher_id_fkey;
+ALTER TABLE Marks ADD CONSTRAINT marks_teacher_id_fkey
+    FOREIGN KEY (school_id, teacher_id) REFERENCES Teachers(school_id, employee_id);
+
+ALTER TABLE AnnouncementAudiences DROP CONSTRAINT announcementaudiences_class_id_fkey;
+ALTER TABLE AnnouncementAudiences ADD CONSTRAINT announcementaudiences_class_id_fkey
+    FOREIGN KEY (school_id, class_id) REFERENCES Classes(school_id, id) ON DELETE CASCADE;
+
+ALTER TABLE EmployeeAccessRoles DROP CONSTRAINT employeeaccessroles_employee_id_fkey;
+ALTER TABLE EmployeeAccessRoles ADD CONSTRAINT employeeaccessroles_employee_id_fkey
+    FOREIGN KEY (school_id, employee_id) REFERENCES Employees(school_id, id) ON DELETE CASCADE;
+ALTER TABLE EmployeeAccessRoles DROP CONSTRAINT employeeaccessroles_access_role_id_fkey;
+ALTER TABLE EmployeeAccessRoles ADD CONSTRAINT employeeaccessroles_access_role_id_fkey
+    FOREIGN KEY (school_id, access_role_id) REFERENCES AccessRoles(school_id, id) ON DELETE CASCADE;
+
+CREATE INDEX ON Employees(school_id);
+CREATE INDEX ON Students(school_id);
+CREATE INDEX ON Parents(school_id);
+CREATE INDEX ON Announcements(school_id, published_at);
+CREATE INDEX ON AuditLog(school_id);
@@ -0,0 +1,9 @@
+-- Archived rows are kept for the history, e.g. marks of students who left the school
+ALTER TABLE Students ADD COLUMN archived_at TIMESTAMPTZ;
+ALTER TABLE Employees ADD COLUMN archived_at TIMESTAMPTZ;
+ALTER TABLE Classes ADD COLUMN archived_at TIMESTAMPTZ;
+ALTER TABLE Subjects ADD COLUMN archived_at TIMESTAMPTZ;
+ALTER TABLE Rooms ADD COLUMN archived_at TIMESTAMPTZ;
+
+ALTER TYPE AuditAction ADD VALUE 'archive';
+ALTER TYPE AuditAction ADD VALUE 'restore';
@@ -0,0 +1,18 @@
+-- Trigram matching needs a database with a UTF-8 locale to see Cyrillic letters as letters
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Lowercased text with ё folded into е, names and queries are compared in this form
+CREATE FUNCTION search_text(value TEXT) RETURNS TEXT
+    LANGUAGE SQL IMMUTABLE PARALLEL SAFE
+    AS $$ SELECT translate(lower(value), 'ё', 'е') $$;
+
+CREATE FUNCTION search_name(first_name TEXT, last_name TEXT, middle_name TEXT) RETURNS TEXT
+    LANGUAGE SQL IMMUTABLE PARALLEL SAFE
+    AS $$ SELECT search_text(last_name || ' ' || first_name || coalesce(' ' || middle_name, '')) $$;
+
+CREATE INDEX ON Students USING GIN (search_name(first_name, last_name, middle_name) gin_trgm_ops);
+CREATE INDEX ON Students USING GIN (to_tsvector('simple', search_name(first_name, last_name, middle_name)));
+CREATE INDEX ON Employees USING GIN (search_name(first_name, last_name, middle_name) gin_trgm_ops);
+CREATE INDEX ON Employees USING GIN (to_tsvector('simple', search_name(first_name, last_name, middle_name)));
+CREATE INDEX ON Parents USING GIN (search_name(first_name, last_name, middle_name) gin_trgm_ops);
+CREATE INDEX ON Parents USING GIN (to_tsvector('simple', search_name(first_name, last_name, middle_name)));
@@ -0,0 +1,28 @@
+-- Versions for optimistic concurrency, clients send the version they have seen in `If-Match`
+ALTER TABLE Students ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE Employees ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE Classes ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE Subjects ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE Rooms ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+
+-- Every update of a row bumps its version, whichever query makes it
+CREATE FUNCTION bump_version() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+BEGIN
+    NEW.version := OLD.version + 1;
+    RETURN NEW;
+END $$;
+
+CREATE TRIGGER bump_version BEFORE UPDATE ON Students FOR EACH ROW EXECUTE FUNCTION bump_version();
+CREATE TRIGGER bump_version BEFORE UPDATE ON Employees FOR EACH ROW EXECUTE FUNCTION bump_version();
+CREATE TRIGGER bump_version BEFORE UPDATE ON Classes FOR EACH ROW EXECUTE FUNCTION bump_version();
+CREATE TRIGGER bump_version BEFORE UPDATE ON Subjects FOR EACH ROW EXECUTE FUNCTION bump_version();
+CREATE TRIGGER bump_version BEFORE UPDATE ON Rooms FOR EACH ROW EXECUTE FUNCTION bump_version();
+
+-- Room and subject are part of the teacher, so they bump the version of the employee
+CREATE FUNCTION bump_teacher_version() RETURNS TRIGGER LANGUAGE plpgsql AS $$
+BEGIN
+    UPDATE Employees SET version = version + 1 WHERE id = NEW.employee_id;
+    RETURN NEW;
+END $$;
+
+CREATE TRIGGER bump_teacher_version AFTER UPDATE ON Teachers FOR EACH ROW EXECUTE FUNCTION bump_teacher_version();
@@ -0,0 +1,17 @@
+-- Responses to POST requests sent with `Idempotency-Key`, retries of a request get the stored
+-- response instead of repeating it
+CREATE TABLE IdempotencyKeys(
+    role Role NOT NULL,
+    user_id INTEGER NOT NULL,
+    key VARCHAR(255) NOT NULL,
+    request_hash VARCHAR(64) NOT NULL,
+    -- Not set while the request is in progress
+    status SMALLINT,
+    -- Pairs of header names and values
+    headers JSONB,
+    body BYTEA,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    PRIMARY KEY (role, user_id, key)
+);
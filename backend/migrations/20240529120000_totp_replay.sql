@@ -0,0 +1,6 @@
+-- Time step of the last accepted one-time password, codes of this or earlier steps are rejected
+ALTER TABLE Employees ADD COLUMN totp_last_step BIGINT;
+
+-- Single sign-on sessions, the identity provider is responsible for the second factor
+-- so enabling the 2FA policy does not restrict them
+ALTER TABLE Sessions ADD COLUMN totp_exempt BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,7 @@
+-- Security-relevant operations on accounts
+ALTER TYPE AuditEntity ADD VALUE 'password';
+ALTER TYPE AuditEntity ADD VALUE 'password_reset_code';
+ALTER TYPE AuditEntity ADD VALUE 'lockout';
+ALTER TYPE AuditEntity ADD VALUE 'two_factor';
+ALTER TYPE AuditEntity ADD VALUE 'sso_identity';
+ALTER TYPE AuditEntity ADD VALUE 'session';
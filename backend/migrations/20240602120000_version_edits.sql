@@ -0,0 +1,22 @@
+-- Only edits bump the version, so logins, password changes and 2FA changes of a person
+-- do not fail a concurrent edit of them with a stale `If-Match`
+DROP TRIGGER bump_version ON Students;
+DROP TRIGGER bump_version ON Employees;
+
+CREATE TRIGGER bump_version BEFORE UPDATE ON Students FOR EACH ROW
+WHEN (
+    (OLD.first_name, OLD.last_name, OLD.middle_name, OLD.class_id, OLD.phone, OLD.email,
+        OLD.enrolled_at, OLD.school_id, OLD.archived_at) IS DISTINCT FROM
+    (NEW.first_name, NEW.last_name, NEW.middle_name, NEW.class_id, NEW.phone, NEW.email,
+        NEW.enrolled_at, NEW.school_id, NEW.archived_at)
+)
+EXECUTE FUNCTION bump_version();
+
+CREATE TRIGGER bump_version BEFORE UPDATE ON Employees FOR EACH ROW
+WHEN (
+    (OLD.first_name, OLD.last_name, OLD.middle_name, OLD.phone, OLD.email, OLD.role,
+        OLD.employed_at, OLD.school_id, OLD.archived_at) IS DISTINCT FROM
+    (NEW.first_name, NEW.last_name, NEW.middle_name, NEW.phone, NEW.email, NEW.role,
+        NEW.employed_at, NEW.school_id, NEW.archived_at)
+)
+EXECUTE FUNCTION bump_version();
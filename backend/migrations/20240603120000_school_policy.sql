@@ -0,0 +1,3 @@
+-- Schools can require two-factor authentication of their principals even if the district
+-- policy does not
+ALTER TABLE Schools ADD COLUMN require_principal_2fa BOOLEAN NOT NULL DEFAULT false;
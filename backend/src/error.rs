@@ -3,6 +3,7 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
+use jsonwebtoken::errors::ErrorKind;
 use serde_json::json;
 use std::{borrow::Cow, io};
 use thiserror::Error;
@@ -15,6 +16,14 @@ pub enum Error {
     Jwt(#[from] jsonwebtoken::errors::Error),
     #[error("Io: {0}")]
     Io(#[from] io::Error),
+    #[error("Smtp: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("Email: {0}")]
+    Email(#[from] lettre::error::Error),
+    #[error("Email address: {0}")]
+    EmailAddress(#[from] lettre::address::AddressError),
+    #[error("Http: {0}")]
+    Http(#[from] reqwest::Error),
 
     #[error("{}", .0.body_text())]
     JsonRejection(#[from] axum::extract::rejection::JsonRejection),
@@ -29,17 +38,39 @@ pub enum Error {
         message: Cow<'static, str>,
         status: StatusCode,
     },
+    /// The request conflicts with the current state, `details` describe the conflict
+    #[error("{message}")]
+    Conflict {
+        message: Cow<'static, str>,
+        details: serde_json::Value,
+    },
 }
 
 impl Error {
     fn status(&self) -> StatusCode {
         match self {
-            Error::Io(_) | Error::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Io(_)
+            | Error::Sqlx(_)
+            | Error::Smtp(_)
+            | Error::Email(_)
+            | Error::EmailAddress(_)
+            | Error::Http(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            // Expired and forged access tokens are answered the same way as missing ones,
+            // so clients refresh the token
+            Error::Jwt(err)
+                if matches!(
+                    err.kind(),
+                    ErrorKind::ExpiredSignature
+                        | ErrorKind::InvalidToken
+                        | ErrorKind::InvalidSignature
+                ) =>
+                StatusCode::UNAUTHORIZED,
             Error::Jwt(_)
             | Error::PathRejection(_)
             | Error::JsonRejection(_)
             | Error::QueryRejection(_) => StatusCode::BAD_REQUEST,
             Error::Custom { status, .. } => *status,
+            Error::Conflict { .. } => StatusCode::CONFLICT,
         }
     }
 }
@@ -55,16 +86,20 @@ impl IntoResponse for Error {
             format!("{self}")
         };
 
-        let field = match self {
-            Error::Custom { field, .. } => field.map(ToOwned::to_owned),
-            _ => None,
+        let (field, details) = match self {
+            Error::Custom { field, .. } => (field.map(ToOwned::to_owned), None),
+            Error::Conflict { details, .. } => (None, Some(details)),
+            _ => (None, None),
         };
 
-        let value = json! {{
+        let mut value = json! {{
             "message": message,
             "field": field,
             "success": false
         }};
+        if let Some(details) = details {
+            value["details"] = details;
+        }
         (status, Json(value)).into_response()
     }
 }
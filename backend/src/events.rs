@@ -0,0 +1,37 @@
+use crate::models::Mark;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkEventKind {
+    Created,
+}
+
+#[derive(Serialize)]
+pub struct MarkEvent {
+    pub kind: MarkEventKind,
+    pub mark: Mark,
+}
+
+/// In-process fan-out of mark changes to the live subscribers
+#[derive(Clone)]
+pub struct MarkEvents(broadcast::Sender<Arc<MarkEvent>>);
+
+impl Default for MarkEvents {
+    fn default() -> Self {
+        Self(broadcast::channel(256).0)
+    }
+}
+
+impl MarkEvents {
+    pub fn publish(&self, kind: MarkEventKind, mark: Mark) {
+        // Error only means there are no subscribers at the moment
+        _ = self.0.send(Arc::new(MarkEvent { kind, mark }));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<MarkEvent>> {
+        self.0.subscribe()
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{error::Error, fail, middleware::Claims, AppState};
+use axum::{
+    body::{to_bytes, Body},
+    extract::{FromRequestParts, Request, State},
+    http::{
+        header::{CACHE_CONTROL, SET_COOKIE},
+        HeaderName, HeaderValue, Method, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use sqlx::{types::Json, PgPool};
+
+const HEADER: &str = "idempotency-key";
+/// Set on responses which were stored by an earlier request with the same key
+const REPLAYED: &str = "idempotent-replayed";
+/// Largest request body which is read for hashing, same as the default limit of axum
+const BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Request hash, status, headers and body stored for the key. Only the hash is set while the
+/// first request is in progress
+type Stored = (
+    String,
+    Option<i16>,
+    Option<Json<Vec<(String, String)>>>,
+    Option<Vec<u8>>,
+);
+
+/// Makes POST requests with an `Idempotency-Key` header safe to retry. The first request with
+/// the key runs as usual and its response is stored for a day, retries get the stored response.
+/// Keys are scoped by user, so anonymous requests like logins are not deduplicated
+pub async fn layer(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if request.method() != Method::POST || !request.headers().contains_key(HEADER) {
+        return next.run(request).await;
+    }
+
+    match run(&state, request, next).await {
+        Ok(response) => response,
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn run(state: &AppState, request: Request, next: Next) -> Result<Response, Error> {
+    let (mut parts, body) = request.into_parts();
+    let key = parts.headers[HEADER]
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= 255)
+        .ok_or(fail!(BAD_REQUEST, "Недействительный ключ идемпотентности"))?
+        .to_owned();
+    let Ok(claims) = Claims::from_request_parts(&mut parts, state).await else {
+        return Ok(next.run(Request::from_parts(parts, body)).await);
+    };
+
+    let body = to_bytes(body, BODY_LIMIT)
+        .await
+        .map_err(|_| fail!(PAYLOAD_TOO_LARGE, "Слишком большой запрос"))?;
+    let hash = format!(
+        "{:x}",
+        Sha256::new()
+            .chain_update(parts.uri.to_string())
+            .chain_update(&body)
+            .finalize()
+    );
+
+    // Expired keys are reused, as well as keys of requests which never finished, such as ones
+    // cancelled by a dropped connection
+    let claimed = sqlx::query(
+        "
+            INSERT INTO IdempotencyKeys(role, user_id, key, request_hash, expires_at)
+            VALUES ($1, $2, $3, $4, now() + interval '1 day')
+            ON CONFLICT (role, user_id, key) DO UPDATE SET
+                request_hash = EXCLUDED.request_hash,
+                status = NULL,
+                headers = NULL,
+                body = NULL,
+                created_at = now(),
+                expires_at = EXCLUDED.expires_at
+            WHERE
+                IdempotencyKeys.expires_at <= now() OR
+                (IdempotencyKeys.status IS NULL AND IdempotencyKeys.created_at < now() - interval '1 minute')
+        ",
+    )
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .bind(&key)
+    .bind(&hash)
+    .execute(&state.db)
+    .await?
+    .rows_affected()
+        == 1;
+
+    if !claimed {
+        return replay(state, &claims, &key, &hash).await;
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    // Server errors are worth retrying, and sessions in cookies as well as other secrets must
+    // not be stored
+    let status = response.status();
+    let no_store = response
+        .headers()
+        .get(CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("no-store"));
+    if status.is_server_error() || no_store || response.headers().contains_key(SET_COOKIE) {
+        release(state, &claims, &key).await?;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => {
+            release(state, &claims, &key).await?;
+            tracing::error!("Idempotency: failed to read the response body: {err}");
+            return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+    let headers = parts
+        .headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+        .collect::<Vec<_>>();
+
+    sqlx::query(
+        "
+            UPDATE IdempotencyKeys SET status = $4, headers = $5, body = $6
+            WHERE role = $1 AND user_id = $2 AND key = $3
+        ",
+    )
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .bind(&key)
+    .bind(status.as_u16() as i16)
+    .bind(Json(headers))
+    .bind(body.as_ref())
+    .execute(&state.db)
+    .await?;
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// Responds to a retry with the stored response of the first request
+async fn replay(
+    state: &AppState,
+    claims: &Claims,
+    key: &str,
+    hash: &str,
+) -> Result<Response, Error> {
+    let stored = sqlx::query_as::<_, Stored>(
+        "
+            SELECT request_hash, status, headers, body FROM IdempotencyKeys
+            WHERE role = $1 AND user_id = $2 AND key = $3
+        ",
+    )
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .bind(key)
+    .fetch_optional(&state.db)
+    .await?;
+
+    let (status, headers, body) = match stored {
+        Some((request_hash, ..)) if request_hash != hash => fail!(
+            !UNPROCESSABLE_ENTITY,
+            "Ключ идемпотентности уже использован для другого запроса"
+        ),
+        Some((_, Some(status), headers, body)) => (status, headers, body),
+        // Released by a failed request in the meantime
+        None => fail!(
+            !CONFLICT,
+            "Запрос с этим ключом не завершился, повторите его"
+        ),
+        Some(_) => fail!(!CONFLICT, "Запрос с этим ключом ещё выполняется"),
+    };
+
+    let mut response = Response::new(Body::from(body.unwrap_or_default()));
+    *response.status_mut() = StatusCode::from_u16(status as u16).unwrap_or(StatusCode::OK);
+    let map = response.headers_mut();
+    for (name, value) in headers.map(|Json(headers)| headers).unwrap_or_default() {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            map.append(name, value);
+        }
+    }
+    map.insert(REPLAYED, HeaderValue::from_static("true"));
+
+    Ok(response)
+}
+
+/// Forgets the key so the request can be retried
+async fn release(state: &AppState, claims: &Claims, key: &str) -> Result<(), Error> {
+    sqlx::query("DELETE FROM IdempotencyKeys WHERE role = $1 AND user_id = $2 AND key = $3")
+        .bind(claims.role)
+        .bind(claims.user_id)
+        .bind(key)
+        .execute(&state.db)
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes expired keys along with their stored responses
+pub async fn purge(db: &PgPool) -> Result<(), Error> {
+    sqlx::query("DELETE FROM IdempotencyKeys WHERE expires_at <= now()")
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,302 @@
+use crate::{
+    error::Error,
+    idempotency,
+    models::{Mark, Participant},
+    notifications::{Notification, NotificationChannel, NotificationEvent},
+    AppState,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, PgExecutor, PgPool};
+use std::time::Duration as StdDuration;
+use time::{Duration, OffsetDateTime};
+use tokio::{sync::watch, task::JoinHandle, time::sleep};
+
+/// How long an idle worker waits before polling the queue again
+const POLL_INTERVAL: StdDuration = StdDuration::from_secs(1);
+/// How long a taken job is hidden from other workers. If the worker crashes,
+/// the job is taken again once the lease expires
+const LEASE: Duration = Duration::minutes(10);
+/// How often expired rows such as idempotency keys are deleted
+const PURGE_INTERVAL: StdDuration = StdDuration::from_secs(60 * 60);
+
+/// Background work persisted in the `Jobs` table
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Job {
+    /// Notifies the student and their parents about a new mark
+    NewMark { mark_id: i32 },
+    /// Notifies the audience of an announcement once it is published
+    AnnouncementPublished { announcement_id: i32 },
+    /// Sends the notification to one recipient through one channel
+    Deliver {
+        channel: NotificationChannel,
+        recipient: Participant,
+        notification: Notification,
+    },
+}
+
+/// Adds the job to the queue. Jobs with `run_at` in the future are delayed until then.
+/// Can be called inside of a transaction so the job is only queued if the transaction commits
+pub async fn enqueue<'e>(
+    db: impl PgExecutor<'e>,
+    job: Job,
+    run_at: Option<OffsetDateTime>,
+) -> Result<(), Error> {
+    sqlx::query("INSERT INTO Jobs(payload, run_at) VALUES ($1, coalesce($2, now()))")
+        .bind(Json(job))
+        .bind(run_at)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Spawns `count` workers and a task purging expired rows, all of which stop after `shutdown`
+/// resolves. Awaiting the returned handles waits for the jobs in progress to finish
+pub fn spawn_workers(
+    state: AppState,
+    count: usize,
+    shutdown: watch::Receiver<()>,
+) -> Vec<JoinHandle<()>> {
+    let mut handles = (0..count)
+        .map(|_| tokio::spawn(worker(state.clone(), shutdown.clone())))
+        .collect::<Vec<_>>();
+    handles.push(tokio::spawn(purge(state.db, shutdown)));
+    handles
+}
+
+async fn purge(db: PgPool, mut shutdown: watch::Receiver<()>) {
+    loop {
+        if let Err(e) = idempotency::purge(&db).await {
+            tracing::error!("Purge: {e}");
+        }
+
+        tokio::select! {
+            _ = sleep(PURGE_INTERVAL) => {},
+            _ = shutdown.changed() => break,
+        }
+    }
+}
+
+async fn worker(state: AppState, mut shutdown: watch::Receiver<()>) {
+    loop {
+        let idle = match run_next(&state).await {
+            Ok(ran) => !ran,
+            Err(e) => {
+                tracing::error!("Job queue: {e}");
+                true
+            }
+        };
+
+        if idle {
+            tokio::select! {
+                _ = sleep(POLL_INTERVAL) => {},
+                _ = shutdown.changed() => break,
+            }
+        } else if shutdown.has_changed().unwrap_or(true) {
+            break;
+        }
+    }
+}
+
+/// Takes a due job and runs it. The job is leased by moving its `run_at` forward rather than
+/// by holding the row lock, so no transaction stays open while it runs.
+/// Returns `false` if there was nothing to do
+async fn run_next(state: &AppState) -> Result<bool, Error> {
+    let Some((id, Json(job), attempts, max_attempts)) =
+        sqlx::query_as::<_, (i64, Json<Job>, i32, i32)>(
+            "
+                UPDATE Jobs SET attempts = attempts + 1, run_at = now() + $1
+                WHERE id = (
+                    SELECT id FROM Jobs
+                    WHERE status = 'pending' AND run_at <= now()
+                    ORDER BY run_at, id
+                    LIMIT 1
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING id, payload, attempts, max_attempts
+            ",
+        )
+        .bind(LEASE)
+        .fetch_optional(&state.db)
+        .await?
+    else {
+        return Ok(false);
+    };
+
+    match run(state, &job).await {
+        Ok(()) => {
+            sqlx::query("UPDATE Jobs SET status = 'done', finished_at = now() WHERE id = $1")
+                .bind(id)
+                .execute(&state.db)
+                .await?;
+        }
+        Err(e) if attempts < max_attempts => {
+            tracing::warn!("Job #{id} {job:?} failed on attempt {attempts}: {e}");
+
+            let backoff = Duration::seconds(10 * 2i64.pow(attempts as u32)).min(Duration::HOUR);
+            sqlx::query("UPDATE Jobs SET run_at = $2, last_error = $3 WHERE id = $1")
+                .bind(id)
+                .bind(OffsetDateTime::now_utc() + backoff)
+                .bind(e.to_string())
+                .execute(&state.db)
+                .await?;
+        }
+        Err(e) => {
+            tracing::error!("Job #{id} {job:?} failed permanently: {e}");
+
+            sqlx::query(
+                "
+                    UPDATE Jobs SET status = 'failed', last_error = $2, finished_at = now()
+                    WHERE id = $1
+                ",
+            )
+            .bind(id)
+            .bind(e.to_string())
+            .execute(&state.db)
+            .await?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// Queues a delivery job per recipient and channel, so a failed delivery
+/// is retried without repeating the successful ones
+async fn fan_out(
+    state: &AppState,
+    recipients: &[Participant],
+    notification: Notification,
+) -> Result<(), Error> {
+    let deliveries = state
+        .notifier
+        .deliveries(recipients, notification.event)
+        .await?;
+
+    let mut tx = state.db.begin().await?;
+    for (channel, recipient) in deliveries {
+        let job = Job::Deliver {
+            channel,
+            recipient,
+            notification: notification.clone(),
+        };
+        enqueue(&mut *tx, job, None).await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn run(state: &AppState, job: &Job) -> Result<(), Error> {
+    match job {
+        Job::NewMark { mark_id } => {
+            let mark = sqlx::query_as::<_, Mark>("SELECT * FROM Marks WHERE id = $1")
+                .bind(*mark_id)
+                .fetch_one(&state.db)
+                .await?;
+            let subject =
+                sqlx::query_scalar::<_, String>("SELECT subject FROM Subjects WHERE id = $1")
+                    .bind(mark.subject_id)
+                    .fetch_one(&state.db)
+                    .await?;
+            let recipients = sqlx::query_as::<_, Participant>(
+                "
+                    SELECT 'student'::Role AS role, $1 AS user_id
+                    UNION ALL
+                    SELECT 'parent'::Role, parent_id FROM ParentStudent WHERE student_id = $1
+                ",
+            )
+            .bind(mark.student_id)
+            .fetch_all(&state.db)
+            .await?;
+
+            let notification = Notification {
+                event: NotificationEvent::NewMark,
+                title: "Новая оценка".into(),
+                body: format!("Получена оценка {} по предмету «{subject}»", mark.mark),
+            };
+            fan_out(state, &recipients, notification).await
+        }
+        Job::AnnouncementPublished { announcement_id } => {
+            let Some((title, body)) = sqlx::query_as::<_, (String, String)>(
+                "SELECT title, body FROM Announcements WHERE id = $1",
+            )
+            .bind(*announcement_id)
+            .fetch_optional(&state.db)
+            .await?
+            else {
+                // Deleted before it was published
+                return Ok(());
+            };
+            let recipients = announcement_recipients(&state.db, *announcement_id).await?;
+
+            let notification = Notification {
+                event: NotificationEvent::Announcement,
+                title,
+                body,
+            };
+            fan_out(state, &recipients, notification).await
+        }
+        Job::Deliver {
+            channel,
+            recipient,
+            notification,
+        } =>
+            state
+                .notifier
+                .deliver(*channel, recipient, notification)
+                .await,
+    }
+}
+
+/// Resolves audience of the announcement into the list of users
+async fn announcement_recipients(
+    db: &PgPool,
+    announcement_id: i32,
+) -> Result<Vec<Participant>, Error> {
+    let recipients = sqlx::query_as::<_, Participant>(
+        "
+            WITH Audience AS (
+                SELECT * FROM AnnouncementAudiences WHERE announcement_id = $1
+            )
+            SELECT role, id AS user_id FROM Employees E
+            WHERE E.archived_at IS NULL AND EXISTS (
+                SELECT 1 FROM Audience
+                WHERE
+                    school_id = E.school_id AND
+                    (kind = 'everyone' OR (kind = 'role' AND role = E.role))
+            )
+            UNION ALL
+            SELECT 'student'::Role, id FROM Students S
+            WHERE S.archived_at IS NULL AND EXISTS (
+                SELECT 1 FROM Audience
+                WHERE
+                    school_id = S.school_id AND (
+                        kind = 'everyone' OR
+                        (kind = 'role' AND role = 'student') OR
+                        (kind = 'class' AND class_id = S.class_id)
+                    )
+            )
+            UNION ALL
+            SELECT 'parent'::Role, id FROM Parents P
+            WHERE EXISTS (
+                SELECT 1 FROM Audience
+                WHERE
+                    school_id = P.school_id AND (
+                        kind = 'everyone' OR
+                        (kind = 'role' AND role = 'parent') OR
+                        (kind = 'class_parents' AND class_id IN (
+                            SELECT class_id FROM Students
+                            JOIN ParentStudent ON student_id = id
+                            WHERE parent_id = P.id
+                        ))
+                    )
+            )
+        ",
+    )
+    .bind(announcement_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(recipients)
+}
@@ -0,0 +1,255 @@
+use crate::{error::Error, fail};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use rsa::{
+    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey},
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    traits::PublicKeyParts,
+    RsaPrivateKey, RsaPublicKey,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs;
+
+/// Id of the key configured by `JWT_KEY`, also used for tokens without `kid`
+const DEFAULT_KID: &str = "default";
+
+/// Key for signing and verifying access tokens
+struct Key {
+    kid: String,
+    algorithm: Algorithm,
+    /// Missing for keys which are only accepted for verification
+    encoding: Option<EncodingKey>,
+    decoding: DecodingKey,
+    /// Public part of asymmetric keys, published in JWKS
+    jwk: Option<Jwk>,
+}
+
+impl Key {
+    fn hmac(kid: &str, secret: &str) -> Self {
+        let encoding = EncodingKey::from_base64_secret(secret)
+            .unwrap_or_else(|_| panic!("Secret of JWT key `{kid}` must be BASE64 encoded"));
+        let decoding = DecodingKey::from_base64_secret(secret).unwrap();
+
+        Self {
+            kid: kid.to_owned(),
+            algorithm: Algorithm::HS256,
+            encoding: Some(encoding),
+            decoding,
+            jwk: None,
+        }
+    }
+
+    /// Loads RSA key from PKCS#1 or PKCS#8 PEM file, either private or public
+    fn rsa(kid: &str, pem: &str) -> Self {
+        let invalid = || -> ! { panic!("JWT key `{kid}` is not a valid RSA key") };
+
+        let (encoding, public) = if pem.contains("PRIVATE KEY") {
+            let private = RsaPrivateKey::from_pkcs8_pem(pem)
+                .or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem))
+                .unwrap_or_else(|_| invalid());
+            let encoding = EncodingKey::from_rsa_pem(pem.as_bytes()).unwrap_or_else(|_| invalid());
+
+            (Some(encoding), private.to_public_key())
+        } else {
+            let public = RsaPublicKey::from_public_key_pem(pem)
+                .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+                .unwrap_or_else(|_| invalid());
+
+            (None, public)
+        };
+
+        let (n, e) = (public.n().to_bytes_be(), public.e().to_bytes_be());
+        let jwk = AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: URL_SAFE_NO_PAD.encode(&n),
+            e: URL_SAFE_NO_PAD.encode(&e),
+        });
+
+        Self::asymmetric(
+            kid,
+            Algorithm::RS256,
+            encoding,
+            DecodingKey::from_rsa_raw_components(&n, &e),
+            jwk,
+        )
+    }
+
+    /// Loads Ed25519 key from PKCS#8 PEM file, either private or public
+    fn ed25519(kid: &str, pem: &str) -> Self {
+        let invalid = || -> ! { panic!("JWT key `{kid}` is not a valid Ed25519 key") };
+
+        let pem = pem::parse(pem).unwrap_or_else(|_| invalid());
+        let (encoding, public) = match pem.tag() {
+            "PRIVATE KEY" => {
+                let pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(pem.contents())
+                    .unwrap_or_else(|_| invalid());
+
+                (
+                    Some(EncodingKey::from_ed_der(pem.contents())),
+                    pair.public_key().as_ref().to_vec(),
+                )
+            }
+            // SubjectPublicKeyInfo ends with the raw 32 byte key
+            "PUBLIC KEY" if pem.contents().len() == 44 => (None, pem.contents()[12..].to_vec()),
+            _ => invalid(),
+        };
+
+        let jwk = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: URL_SAFE_NO_PAD.encode(&public),
+        });
+
+        Self::asymmetric(
+            kid,
+            Algorithm::EdDSA,
+            encoding,
+            DecodingKey::from_ed_der(&public),
+            jwk,
+        )
+    }
+
+    fn asymmetric(
+        kid: &str,
+        algorithm: Algorithm,
+        encoding: Option<EncodingKey>,
+        decoding: DecodingKey,
+        jwk: AlgorithmParameters,
+    ) -> Self {
+        let key_algorithm = match algorithm {
+            Algorithm::RS256 => KeyAlgorithm::RS256,
+            _ => KeyAlgorithm::EdDSA,
+        };
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm),
+                key_id: Some(kid.to_owned()),
+                ..Default::default()
+            },
+            algorithm: jwk,
+        };
+
+        Self {
+            kid: kid.to_owned(),
+            algorithm,
+            encoding,
+            decoding,
+            jwk: Some(jwk),
+        }
+    }
+}
+
+/// Keys accepted for access tokens and the one new tokens are signed with
+pub struct Keys {
+    keys: Vec<Key>,
+    signing: usize,
+}
+
+impl Keys {
+    /// Configures keys from the environment.
+    /// `JWT_KEYS` is a comma separated list of `kid:algorithm:source` entries where the algorithm
+    /// is `HS256` with BASE64 encoded secret as the source, or `RS256` or `EdDSA` with a path to
+    /// PEM file. Keys with only the public part in the file are used just for verification.
+    /// `JWT_KEY` adds HS256 key with `default` id. New tokens are signed by the key with
+    /// `JWT_SIGNING_KEY` id, the first of `JWT_KEYS` by default
+    pub fn from_env() -> Self {
+        use dotenvy::var;
+
+        let mut keys = vec![];
+        if let Ok(secret) = var("JWT_KEY") {
+            keys.push(Key::hmac(DEFAULT_KID, &secret));
+        }
+        let first_listed = keys.len();
+        for entry in var("JWT_KEYS").unwrap_or_default().split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+
+            let mut parts = entry.splitn(3, ':');
+            let (Some(kid), Some(algorithm), Some(source)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                panic!("Invalid JWT key `{entry}`, expected `kid:algorithm:source`");
+            };
+            let read = || {
+                fs::read_to_string(source)
+                    .unwrap_or_else(|e| panic!("Failed to read JWT key `{kid}` from {source}: {e}"))
+            };
+
+            let key = match algorithm {
+                "HS256" => Key::hmac(kid, source),
+                "RS256" => Key::rsa(kid, &read()),
+                "EdDSA" => Key::ed25519(kid, &read()),
+                _ => panic!("Unsupported algorithm `{algorithm}` of JWT key `{kid}`"),
+            };
+            if keys.iter().any(|k: &Key| k.kid == key.kid) {
+                panic!("Duplicate JWT key `{kid}`");
+            }
+            keys.push(key);
+        }
+
+        if keys.is_empty() {
+            panic!("Env `JWT_KEY` or `JWT_KEYS` is required");
+        }
+        let signing = match var("JWT_SIGNING_KEY") {
+            Ok(kid) => keys
+                .iter()
+                .position(|k| k.kid == kid)
+                .unwrap_or_else(|| panic!("Signing JWT key `{kid}` is not configured")),
+            Err(_) if keys.len() > first_listed => first_listed,
+            Err(_) => 0,
+        };
+        if keys[signing].encoding.is_none() {
+            panic!(
+                "Signing JWT key `{}` must be a private key",
+                keys[signing].kid
+            );
+        }
+
+        Self { keys, signing }
+    }
+
+    /// Signs the claims with the current signing key
+    pub fn encode(&self, claims: &impl Serialize) -> Result<String, Error> {
+        let key = &self.keys[self.signing];
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(key.kid.clone());
+
+        Ok(jsonwebtoken::encode(
+            &header,
+            claims,
+            key.encoding.as_ref().unwrap(),
+        )?)
+    }
+
+    /// Verifies the token with the key it was signed by
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T, Error> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let kid = header.kid.as_deref().unwrap_or(DEFAULT_KID);
+        let key = self
+            .keys
+            .iter()
+            .find(|k| k.kid == kid && k.algorithm == header.alg)
+            .ok_or(fail!(UNAUTHORIZED, "Необходима авторизация"))?;
+
+        let data = jsonwebtoken::decode(token, &key.decoding, &Validation::new(key.algorithm))?;
+
+        Ok(data.claims)
+    }
+
+    /// Public keys for verification of the tokens by other services
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().filter_map(|k| k.jwk.clone()).collect(),
+        }
+    }
+}
@@ -1,24 +1,40 @@
 use axum::{extract::FromRef, Router};
 use dotenvy::var;
-use jsonwebtoken::{DecodingKey, EncodingKey};
 use sqlx::{migrate, PgPool};
-use std::{panic, sync::OnceLock};
+use std::{
+    net::SocketAddr,
+    panic,
+    sync::{Arc, OnceLock},
+};
 use tokio::{
     net::TcpListener,
     signal::unix::{signal, SignalKind},
+    sync::watch,
 };
 use tracing::{info, Level};
 use tracing_subscriber::{fmt, prelude::*, registry, EnvFilter};
 use utoipa_swagger_ui::SwaggerUi;
 
 mod error;
+mod events;
+mod idempotency;
+mod jobs;
+mod keys;
 mod middleware;
 mod models;
+mod notifications;
+mod oidc;
 mod routes;
 
 #[derive(Clone, FromRef)]
 struct AppState {
     db: PgPool,
+    marks: events::MarkEvents,
+    notifier: notifications::Notifier,
+    /// Identity provider for single sign-on of employees, if configured
+    oidc: Option<Arc<oidc::Oidc>>,
+    /// Resolves once the server starts shutting down, used to close long-lived streams
+    shutdown: watch::Receiver<()>,
 }
 
 fn init_tracing() {
@@ -35,20 +51,20 @@ fn init_tracing() {
     panic::set_hook(Box::new(|pi| tracing::error!("{pi}")));
 }
 
-static ENCODING_KEY: OnceLock<EncodingKey> = OnceLock::new();
-static DECODING_KEY: OnceLock<DecodingKey> = OnceLock::new();
+static KEYS: OnceLock<keys::Keys> = OnceLock::new();
+/// Number of reverse proxies in front of the server, each appends to `X-Forwarded-For`
+static TRUST_PROXY: OnceLock<usize> = OnceLock::new();
 
 #[tokio::main]
 async fn main() -> Result<(), error::Error> {
     init_tracing();
 
-    let jwtkey = var("JWT_KEY").expect("Env `JWT_KEY` is required");
-    let ek =
-        EncodingKey::from_base64_secret(&jwtkey).expect("Env `JWT_KEY` must be BASE64 encoded");
-    let dk = DecodingKey::from_base64_secret(&jwtkey).unwrap();
-
-    _ = ENCODING_KEY.set(ek);
-    _ = DECODING_KEY.set(dk);
+    _ = KEYS.set(keys::Keys::from_env());
+    _ = TRUST_PROXY.set(match var("TRUST_PROXY").as_deref() {
+        Ok("true") => 1,
+        Ok(hops) => hops.parse().unwrap_or(0),
+        Err(_) => 0,
+    });
 
     let port = var("PORT")
         .map(|s| s.parse::<u16>())
@@ -64,7 +80,21 @@ async fn main() -> Result<(), error::Error> {
         .await
         .expect("Failed to migrate database schema");
 
-    let state = AppState { db };
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    let notifier = notifications::Notifier::from_env(db.clone()).await?;
+    let state = AppState {
+        db,
+        marks: events::MarkEvents::default(),
+        notifier,
+        oidc: oidc::Oidc::from_env().map(Arc::new),
+        shutdown: shutdown_rx,
+    };
+
+    let workers = var("JOB_WORKERS")
+        .map(|s| s.parse::<usize>())
+        .unwrap_or(Ok(2))
+        .expect("Invalid value of `JOB_WORKERS`");
+    let workers = jobs::spawn_workers(state.clone(), workers, state.shutdown.clone());
 
     let listener = TcpListener::bind(format!("{host}:{port}")).await?;
     info!("Listening on {}", listener.local_addr()?);
@@ -78,6 +108,7 @@ async fn main() -> Result<(), error::Error> {
             _ = s1.recv() => {},
             _ = s2.recv() => {}
         }
+        drop(shutdown_tx);
     };
 
     let mut openapi = routes::subjects::openapi();
@@ -87,6 +118,22 @@ async fn main() -> Result<(), error::Error> {
     openapi.merge(routes::teachers::openapi());
     openapi.merge(routes::marks::openapi());
     openapi.merge(routes::auth::openapi());
+    openapi.merge(routes::announcements::openapi());
+    openapi.merge(routes::conversations::openapi());
+    openapi.merge(routes::notifications::openapi());
+    openapi.merge(routes::two_factor::openapi());
+    openapi.merge(routes::passwords::openapi());
+    openapi.merge(routes::lockouts::openapi());
+    openapi.merge(routes::policy::openapi());
+    openapi.merge(routes::jwks::openapi());
+    openapi.merge(routes::service_accounts::openapi());
+    openapi.merge(routes::sso::openapi());
+    openapi.merge(routes::audit::openapi());
+    openapi.merge(routes::impersonation::openapi());
+    openapi.merge(routes::roles::openapi());
+    openapi.merge(routes::staff::openapi());
+    openapi.merge(routes::schools::openapi());
+    openapi.merge(routes::search::openapi());
 
     let app = Router::new()
         .nest("/subjects", routes::subjects::router())
@@ -95,14 +142,37 @@ async fn main() -> Result<(), error::Error> {
         .nest("/students", routes::students::router())
         .nest("/teachers", routes::teachers::router())
         .nest("/principals", routes::principals::router())
+        .nest("/staff", routes::staff::router())
         .nest("/marks", routes::marks::router())
         .nest("/auth", routes::auth::router())
+        .nest("/announcements", routes::announcements::router())
+        .nest("/conversations", routes::conversations::router())
+        .nest("/notifications", routes::notifications::router())
+        .nest("/policy", routes::policy::router())
+        .nest("/service-accounts", routes::service_accounts::router())
+        .nest("/audit", routes::audit::router())
+        .nest("/roles", routes::roles::router())
+        .nest("/schools", routes::schools::router())
+        .nest("/search", routes::search::router())
+        .nest("/.well-known", routes::jwks::router())
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", openapi))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            idempotency::layer,
+        ))
         .with_state(state);
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown)
-        .await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown)
+    .await?;
+
+    info!("Waiting for background jobs to finish");
+    for worker in workers {
+        _ = worker.await;
+    }
 
     Ok(())
 }
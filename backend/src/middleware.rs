@@ -1,8 +1,18 @@
-use crate::{error::Error, fail, models::Role, DECODING_KEY};
-use axum::{extract::FromRequestParts, http::request::Parts, RequestPartsExt};
+use crate::{
+    error::Error,
+    fail,
+    models::{ApiScope, Role},
+    routes::auth::hash_token,
+    AppState, KEYS, TRUST_PROXY,
+};
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts, OriginalUri},
+    http::{header::AUTHORIZATION, request::Parts, Method},
+    RequestPartsExt,
+};
 use axum_extra::extract::CookieJar;
-use jsonwebtoken::Validation;
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use time::OffsetDateTime;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -11,27 +21,186 @@ pub struct Claims {
     pub expires_at: OffsetDateTime,
     #[serde(rename = "xrl")]
     pub role: Role,
+    /// Id of the employee, student or parent, depending on the `role`
     #[serde(rename = "eid")]
-    pub employee_id: i32,
+    pub user_id: i32,
+    /// Id of the server-side session, see `Sessions` table
+    #[serde(rename = "jti")]
+    pub session_id: String,
+    /// School the user belongs to, not set for district administrators
+    #[serde(rename = "sch", default, skip_serializing_if = "Option::is_none")]
+    pub school_id: Option<i32>,
+    /// Id of the principal acting as the user, see `/auth/impersonate`
+    #[serde(rename = "imp", default, skip_serializing_if = "Option::is_none")]
+    pub impersonator_id: Option<i32>,
+    /// Set when authenticated by an API key of the service account. Such requests have
+    /// the read access of a principal, limited to the routes allowed by the key scopes
+    #[serde(skip)]
+    pub service_account_id: Option<i32>,
+}
+
+impl Claims {
+    /// School whose data the request works with. District administrators act within a school
+    /// by impersonating one of its principals
+    pub fn school(&self) -> Result<i32, Error> {
+        self.school_id
+            .ok_or(fail!(FORBIDDEN, "Действие доступно только в рамках школы"))
+    }
+
+    async fn from_api_key(
+        state: &AppState,
+        key: &str,
+        method: &Method,
+        path: &str,
+    ) -> Result<Self, Error> {
+        let (service_account_id, school_id, scopes, expires_at) =
+            sqlx::query_as::<_, (i32, i32, Vec<ApiScope>, Option<OffsetDateTime>)>(
+                "
+                    UPDATE ApiKeys K SET last_used_at = now()
+                    FROM ServiceAccounts A
+                    WHERE
+                        A.id = K.service_account_id AND
+                        key_hash = $1 AND
+                        revoked_at IS NULL AND
+                        (expires_at IS NULL OR expires_at > now())
+                    RETURNING service_account_id, A.school_id, scopes, expires_at
+                ",
+            )
+            .bind(hash_token(key))
+            .fetch_optional(&state.db)
+            .await?
+            .ok_or(fail!(UNAUTHORIZED, "Недействительный ключ API"))?;
+
+        if !scopes.iter().any(|&scope| allows(scope, method, path)) {
+            fail!(!FORBIDDEN, "Ключ API не даёт доступа к этому ресурсу");
+        }
+
+        Ok(Self {
+            expires_at: expires_at.unwrap_or_else(OffsetDateTime::now_utc),
+            role: Role::Principal,
+            user_id: 0,
+            session_id: String::new(),
+            school_id: Some(school_id),
+            impersonator_id: None,
+            service_account_id: Some(service_account_id),
+        })
+    }
+}
+
+/// Whether the scope of an API key grants access to the route
+fn allows(scope: ApiScope, method: &Method, path: &str) -> bool {
+    let route = match scope {
+        ApiScope::Marks => "/marks",
+        ApiScope::Students => "/students",
+        ApiScope::Teachers => "/teachers",
+    };
+
+    method == Method::GET && path.trim_end_matches('/') == route
 }
 
 #[axum::async_trait]
-impl<S> FromRequestParts<S> for Claims {
+impl<S> FromRequestParts<S> for Claims
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
     type Rejection = Error;
 
-    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        let path = parts
+            .extensions
+            .get::<OriginalUri>()
+            .map(|OriginalUri(uri)| uri.path())
+            .unwrap_or(parts.uri.path())
+            .to_owned();
+
+        if let Some(header) = parts.headers.get(AUTHORIZATION) {
+            let key = header
+                .to_str()
+                .ok()
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .ok_or(fail!(UNAUTHORIZED, "Недействительный ключ API"))?;
+
+            return Self::from_api_key(&state, key.trim(), &parts.method, &path).await;
+        }
+
         let jar = parts.extract::<CookieJar>().await.unwrap();
 
         let token = jar
             .get("token")
             .ok_or(fail!(UNAUTHORIZED, "Необходима авторизация"))?
             .value();
-        let token = jsonwebtoken::decode::<Self>(
-            token,
-            DECODING_KEY.get().unwrap(),
-            &Validation::default(),
-        )?;
+        let claims = KEYS.get().unwrap().decode::<Self>(token)?;
+
+        let (totp_pending, password_change_pending) = sqlx::query_as::<_, (bool, bool)>(
+            "
+                UPDATE Sessions SET last_seen_at = now()
+                WHERE id = $1 AND revoked_at IS NULL AND expires_at > now()
+                RETURNING totp_pending, password_change_pending
+            ",
+        )
+        .bind(&claims.session_id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or(fail!(
+            UNAUTHORIZED,
+            "Сессия завершена, необходима авторизация"
+        ))?;
+
+        // Until 2FA is set up and the password is changed only the auth endpoints are reachable
+        if totp_pending || password_change_pending {
+            if path.starts_with("/auth/") {
+                return Ok(claims);
+            }
+
+            if totp_pending {
+                fail!(
+                    !FORBIDDEN,
+                    "Необходимо настроить двухфакторную аутентификацию"
+                );
+            }
+            fail!(!FORBIDDEN, "Необходимо сменить пароль");
+        }
+
+        Ok(claims)
+    }
+}
+
+/// Address of the client. `X-Forwarded-For` header is only trusted if `TRUST_PROXY` is set
+/// to the number of proxies in front of the server. The client can put anything into the
+/// header, so the address appended by the outermost trusted proxy is taken, counting from
+/// the right
+pub struct ClientIp(pub IpAddr);
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let hops = TRUST_PROXY.get().copied().unwrap_or(0);
+        // Proxies may also add the header again instead of appending to it
+        let header = parts
+            .headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        let forwarded = (hops > 0)
+            .then(|| header.rsplit(',').nth(hops - 1))
+            .flatten()
+            .and_then(|ip| ip.trim().parse().ok());
+
+        let ip = forwarded
+            .or_else(|| {
+                parts
+                    .extensions
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(addr)| addr.ip())
+            })
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
 
-        Ok(token.claims)
+        Ok(Self(ip))
     }
 }
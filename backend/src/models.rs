@@ -1,13 +1,33 @@
 use serde::{Deserialize, Serialize};
-use sqlx::{prelude::FromRow, Type};
+use sqlx::{
+    postgres::{PgHasArrayType, PgTypeInfo},
+    prelude::FromRow,
+    Type,
+};
 use time::OffsetDateTime;
 use utoipa::ToSchema;
 
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct School {
+    pub id: i32,
+    pub name: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
 #[derive(Serialize, FromRow, ToSchema)]
 pub struct Subject {
     pub id: i32,
     #[serde(rename = "name")]
     pub subject: String,
+    /// Set once archived, archived rows are hidden from lists by default
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub archived_at: Option<OffsetDateTime>,
+    /// Bumped on every change, the `If-Match` header of changes must carry it
+    pub version: i32,
 }
 
 #[derive(Serialize, FromRow, ToSchema)]
@@ -16,14 +36,44 @@ pub struct Room {
     #[serde(rename = "name")]
     pub room: String,
     pub subject_id: Option<i32>,
+    /// Set once archived, archived rows are hidden from lists by default
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub archived_at: Option<OffsetDateTime>,
+    /// Bumped on every change, the `If-Match` header of changes must carry it
+    pub version: i32,
 }
 
-#[derive(Debug, Type, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize, ToSchema)]
 #[sqlx(rename_all = "lowercase")]
 pub enum Role {
     Teacher,
     Principal,
+    /// Employee without teaching duties, such as a secretary. Their access is defined entirely
+    /// by the assigned access roles
+    Staff,
     Student,
+    Parent,
+    /// District administrator, manages schools and does not belong to any of them
+    Admin,
+}
+
+impl Role {
+    /// Users of the role are stored in the `Employees` table
+    pub fn is_employee(self) -> bool {
+        matches!(
+            self,
+            Role::Teacher | Role::Principal | Role::Staff | Role::Admin
+        )
+    }
+}
+
+impl PgHasArrayType for Role {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_Role")
+    }
 }
 
 #[derive(Serialize, FromRow, ToSchema)]
@@ -36,7 +86,20 @@ pub struct Employee {
     #[serde(with = "time::serde::rfc3339")]
     pub employed_at: OffsetDateTime,
     pub phone: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
     pub role: Role,
+    /// Not set for district administrators
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub school_id: Option<i32>,
+    /// Set once archived, archived rows are hidden from lists by default
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub archived_at: Option<OffsetDateTime>,
+    /// Bumped on every change, the `If-Match` header of changes must carry it
+    pub version: i32,
 }
 
 #[derive(Serialize, FromRow, ToSchema)]
@@ -54,6 +117,14 @@ pub struct Class {
     pub id: i32,
     #[serde(rename = "name")]
     pub class: String,
+    /// Set once archived, archived rows are hidden from lists by default
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub archived_at: Option<OffsetDateTime>,
+    /// Bumped on every change, the `If-Match` header of changes must carry it
+    pub version: i32,
 }
 
 #[derive(Serialize, FromRow, ToSchema)]
@@ -65,10 +136,20 @@ pub struct Student {
     #[serde(with = "time::serde::rfc3339")]
     pub enrolled_at: OffsetDateTime,
     pub phone: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
     pub class_id: i32,
+    /// Set once archived, archived rows are hidden from lists by default
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub archived_at: Option<OffsetDateTime>,
+    /// Bumped on every change, the `If-Match` header of changes must carry it
+    pub version: i32,
 }
 
-#[derive(Serialize, FromRow, ToSchema)]
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
 pub struct Mark {
     pub id: i32,
     pub mark: i16,
@@ -77,4 +158,280 @@ pub struct Mark {
     pub teacher_id: i32,
     #[serde(with = "time::serde::rfc3339")]
     pub time: OffsetDateTime,
+    #[serde(skip)]
+    pub school_id: i32,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct Parent {
+    pub id: i32,
+    pub first_name: String,
+    pub last_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub middle_name: Option<String>,
+    pub phone: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AudienceKind {
+    Everyone,
+    Role,
+    Class,
+    ClassParents,
+}
+
+#[derive(Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Audience {
+    pub kind: AudienceKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class_id: Option<i32>,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct Announcement {
+    pub id: i32,
+    pub author_id: i32,
+    pub title: String,
+    pub body: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub published_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    #[sqlx(skip)]
+    pub audience: Vec<Audience>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Participant {
+    pub role: Role,
+    pub user_id: i32,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct Message {
+    pub id: i32,
+    pub conversation_id: i32,
+    pub sender_role: Role,
+    pub sender_id: i32,
+    pub body: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub sent_at: OffsetDateTime,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct Conversation {
+    pub id: i32,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub unread: i64,
+    #[sqlx(skip)]
+    pub participants: Vec<Participant>,
+    #[sqlx(skip)]
+    pub last_message: Option<Message>,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct Session {
+    pub id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_seen_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// Set if a principal acts as the user in this session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub impersonator_id: Option<i32>,
+    /// Whether this is the session of the request
+    pub current: bool,
+}
+
+/// Access granted to an API key, each scope allows reading the listed route
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ApiScope {
+    /// `GET /marks`
+    Marks,
+    /// `GET /students`
+    Students,
+    /// `GET /teachers`
+    Teachers,
+}
+
+impl PgHasArrayType for ApiScope {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_ApiScope")
+    }
+}
+
+/// Named permission granted through access roles. Principals have all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Type, Serialize, Deserialize, ToSchema)]
+pub enum Permission {
+    #[sqlx(rename = "classes.write")]
+    #[serde(rename = "classes.write")]
+    ClassesWrite,
+    #[sqlx(rename = "subjects.write")]
+    #[serde(rename = "subjects.write")]
+    SubjectsWrite,
+    #[sqlx(rename = "rooms.write")]
+    #[serde(rename = "rooms.write")]
+    RoomsWrite,
+    #[sqlx(rename = "students.write")]
+    #[serde(rename = "students.write")]
+    StudentsWrite,
+    #[sqlx(rename = "teachers.write")]
+    #[serde(rename = "teachers.write")]
+    TeachersWrite,
+    #[sqlx(rename = "staff.write")]
+    #[serde(rename = "staff.write")]
+    StaffWrite,
+    #[sqlx(rename = "principals.write")]
+    #[serde(rename = "principals.write")]
+    PrincipalsWrite,
+    /// Receive events of all marks and give marks on behalf of any teacher
+    #[sqlx(rename = "marks.write.all")]
+    #[serde(rename = "marks.write.all")]
+    MarksWriteAll,
+    /// View and delete announcements of all authors
+    #[sqlx(rename = "announcements.manage")]
+    #[serde(rename = "announcements.manage")]
+    AnnouncementsManage,
+    #[sqlx(rename = "audit.read")]
+    #[serde(rename = "audit.read")]
+    AuditRead,
+    /// Security policy and login lockouts
+    #[sqlx(rename = "security.manage")]
+    #[serde(rename = "security.manage")]
+    SecurityManage,
+    #[sqlx(rename = "passwords.reset")]
+    #[serde(rename = "passwords.reset")]
+    PasswordsReset,
+    #[sqlx(rename = "service_accounts.manage")]
+    #[serde(rename = "service_accounts.manage")]
+    ServiceAccountsManage,
+    /// Act as users other than principals
+    #[sqlx(rename = "impersonate")]
+    #[serde(rename = "impersonate")]
+    Impersonate,
+    /// Manage access roles and assign them to employees
+    #[sqlx(rename = "roles.manage")]
+    #[serde(rename = "roles.manage")]
+    RolesManage,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 15] = [
+        Permission::ClassesWrite,
+        Permission::SubjectsWrite,
+        Permission::RoomsWrite,
+        Permission::StudentsWrite,
+        Permission::TeachersWrite,
+        Permission::StaffWrite,
+        Permission::PrincipalsWrite,
+        Permission::MarksWriteAll,
+        Permission::AnnouncementsManage,
+        Permission::AuditRead,
+        Permission::SecurityManage,
+        Permission::PasswordsReset,
+        Permission::ServiceAccountsManage,
+        Permission::Impersonate,
+        Permission::RolesManage,
+    ];
+}
+
+impl PgHasArrayType for Permission {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_Permission")
+    }
+}
+
+/// Named set of permissions assignable to employees
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct AccessRole {
+    pub id: i32,
+    pub name: String,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct ApiKey {
+    pub id: i32,
+    pub service_account_id: i32,
+    /// First characters of the key
+    pub prefix: String,
+    pub scopes: Vec<ApiScope>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub revoked_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct ServiceAccount {
+    pub id: i32,
+    pub name: String,
+    pub created_by: Option<i32>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[sqlx(skip)]
+    pub keys: Vec<ApiKey>,
+}
+
+/// Security settings shared by all schools, managed by district administrators. Schools can
+/// tighten them with [`SchoolSecurityPolicy`]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityPolicy {
+    /// Principals and district administrators must have two-factor authentication enabled
+    pub require_principal_2fa: bool,
+    /// Minimal number of characters in a password
+    pub password_min_length: usize,
+    /// Passwords must contain at least one digit
+    pub password_require_digit: bool,
+    /// Passwords must contain both lowercase and uppercase letters
+    pub password_require_mixed_case: bool,
+    /// Consecutive failed logins into an account before it is locked
+    pub login_max_failures: i32,
+    /// Consecutive failed logins from an address before it is locked
+    pub login_max_failures_per_ip: i32,
+    /// Duration of the first lockout in seconds, doubled by every further failure
+    pub login_lockout_seconds: i64,
+}
+
+impl Default for SecurityPolicy {
+    fn default() -> Self {
+        Self {
+            require_principal_2fa: false,
+            password_min_length: 8,
+            password_require_digit: true,
+            password_require_mixed_case: false,
+            login_max_failures: 5,
+            login_max_failures_per_ip: 20,
+            login_lockout_seconds: 60,
+        }
+    }
+}
+
+/// Security settings of a school on top of the district policy, managed by the school
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SchoolSecurityPolicy {
+    /// Principals of the school must have two-factor authentication enabled, even if the
+    /// district policy does not require it
+    pub require_principal_2fa: bool,
 }
@@ -0,0 +1,225 @@
+use crate::{
+    error::Error,
+    fail,
+    models::{Participant, Role},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, PgPool, Type};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+mod email;
+mod log;
+mod sms;
+
+pub use email::EmailChannel;
+pub use log::LogChannel;
+pub use sms::SmsChannel;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    NewMark,
+    Absence,
+    Announcement,
+    HomeworkDue,
+    /// Security messages, delivered regardless of the preferences
+    PasswordReset,
+}
+
+impl NotificationEvent {
+    /// Events the user can configure preferences for
+    pub const ALL: [Self; 4] = [
+        Self::NewMark,
+        Self::Absence,
+        Self::Announcement,
+        Self::HomeworkDue,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    Email,
+    Sms,
+    Log,
+}
+
+impl NotificationChannel {
+    pub const ALL: [Self; 3] = [Self::Email, Self::Sms, Self::Log];
+
+    /// Whether the channel is used when the user has not configured it explicitly
+    pub fn enabled_by_default(self) -> bool {
+        match self {
+            Self::Email | Self::Log => true,
+            Self::Sms => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub event: NotificationEvent,
+    pub title: String,
+    pub body: String,
+}
+
+/// Contact details of the user a notification is delivered to
+#[derive(Debug, FromRow)]
+pub struct Recipient {
+    pub role: Role,
+    pub user_id: i32,
+    pub first_name: String,
+    pub last_name: String,
+    pub phone: String,
+    pub email: Option<String>,
+}
+
+impl Recipient {
+    pub fn name(&self) -> String {
+        format!("{} {}", self.first_name, self.last_name)
+    }
+}
+
+/// Delivery channel of notifications
+#[axum::async_trait]
+pub trait Channel: Send + Sync {
+    fn kind(&self) -> NotificationChannel;
+
+    async fn send(&self, recipient: &Recipient, notification: &Notification) -> Result<(), Error>;
+}
+
+#[derive(Clone)]
+pub struct Notifier {
+    db: PgPool,
+    channels: Arc<[Box<dyn Channel>]>,
+}
+
+impl Notifier {
+    pub fn new(db: PgPool, channels: Vec<Box<dyn Channel>>) -> Self {
+        Self {
+            db,
+            channels: channels.into(),
+        }
+    }
+
+    /// Configures channels from the environment.
+    /// Email is enabled by `SMTP_URL` and `SMTP_FROM`, SMS by `SMS_GATEWAY_URL` and optional
+    /// `SMS_GATEWAY_TOKEN`, log sink writes to `NOTIFICATIONS_LOG` file or to the tracing output
+    pub async fn from_env(db: PgPool) -> Result<Self, Error> {
+        use dotenvy::var;
+
+        let mut channels: Vec<Box<dyn Channel>> = vec![];
+
+        if let (Ok(url), Ok(from)) = (var("SMTP_URL"), var("SMTP_FROM")) {
+            channels.push(Box::new(EmailChannel::new(&url, &from)?));
+        }
+        if let Ok(url) = var("SMS_GATEWAY_URL") {
+            channels.push(Box::new(SmsChannel::new(
+                url,
+                var("SMS_GATEWAY_TOKEN").ok(),
+            )));
+        }
+        channels.push(Box::new(match var("NOTIFICATIONS_LOG") {
+            Ok(path) => LogChannel::file(path).await?,
+            Err(_) => LogChannel::tracing(),
+        }));
+
+        Ok(Self::new(db, channels))
+    }
+
+    /// Resolves the channels every recipient has enabled for the event into separate
+    /// deliveries, so each of them can be retried on its own with [`Notifier::deliver`]
+    pub async fn deliveries(
+        &self,
+        recipients: &[Participant],
+        event: NotificationEvent,
+    ) -> Result<Vec<(NotificationChannel, Participant)>, Error> {
+        let preferences = sqlx::query_as::<_, (Role, i32, NotificationChannel, bool)>(
+            "
+                SELECT role, user_id, channel, enabled FROM NotificationPreferences
+                WHERE event = $1 AND user_id = any($2)
+            ",
+        )
+        .bind(event)
+        .bind(recipients.iter().map(|p| p.user_id).collect::<Vec<_>>())
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut deliveries = vec![];
+        for recipient in recipients {
+            for kind in self.channels.iter().map(|c| c.kind()) {
+                let enabled = preferences
+                    .iter()
+                    .find(|(role, user_id, channel, _)| {
+                        *role == recipient.role && *user_id == recipient.user_id && *channel == kind
+                    })
+                    .map(|(.., enabled)| *enabled)
+                    .unwrap_or(kind.enabled_by_default());
+                if enabled {
+                    deliveries.push((kind, *recipient));
+                }
+            }
+        }
+
+        Ok(deliveries)
+    }
+
+    /// Delivers the notification through the given channel, ignoring the preferences.
+    /// Fails if the channel is not configured or the delivery fails
+    pub async fn deliver(
+        &self,
+        channel: NotificationChannel,
+        recipient: &Participant,
+        notification: &Notification,
+    ) -> Result<(), Error> {
+        let channel = self
+            .channels
+            .iter()
+            .find(|c| c.kind() == channel)
+            .ok_or(fail!(
+                BAD_REQUEST,
+                "Канал уведомлений не настроен",
+                "channel"
+            ))?;
+        let contact = self
+            .contacts(std::slice::from_ref(recipient))
+            .await?
+            .pop()
+            .ok_or(fail!(NOT_FOUND, "Пользователь не найден"))?;
+
+        channel.send(&contact, notification).await
+    }
+
+    async fn contacts(&self, recipients: &[Participant]) -> Result<Vec<Recipient>, Error> {
+        let ids_of = |matches: fn(Role) -> bool| {
+            recipients
+                .iter()
+                .filter(|p| matches(p.role))
+                .map(|p| p.user_id)
+                .collect::<Vec<_>>()
+        };
+
+        let contacts = sqlx::query_as::<_, Recipient>(
+            "
+                SELECT role, id AS user_id, first_name, last_name, phone, email
+                FROM Employees WHERE id = any($1)
+                UNION ALL
+                SELECT 'student'::Role, id, first_name, last_name, phone, email
+                FROM Students WHERE id = any($2)
+                UNION ALL
+                SELECT 'parent'::Role, id, first_name, last_name, phone, email
+                FROM Parents WHERE id = any($3)
+            ",
+        )
+        .bind(ids_of(Role::is_employee))
+        .bind(ids_of(|role| role == Role::Student))
+        .bind(ids_of(|role| role == Role::Parent))
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(contacts)
+    }
+}
@@ -0,0 +1,63 @@
+use super::{Channel, Notification, NotificationChannel, Recipient};
+use crate::error::Error;
+use std::path::Path;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+
+/// Writes notifications to a file or to the log, intended for development and tests
+pub struct LogChannel {
+    file: Option<Mutex<File>>,
+}
+
+impl LogChannel {
+    pub fn tracing() -> Self {
+        Self { file: None }
+    }
+
+    pub async fn file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        Ok(Self {
+            file: Some(Mutex::new(file)),
+        })
+    }
+}
+
+#[axum::async_trait]
+impl Channel for LogChannel {
+    fn kind(&self) -> NotificationChannel {
+        NotificationChannel::Log
+    }
+
+    async fn send(&self, recipient: &Recipient, notification: &Notification) -> Result<(), Error> {
+        let line = format!(
+            "{:?} to {:?}#{} ({}): {} - {}",
+            notification.event,
+            recipient.role,
+            recipient.user_id,
+            recipient.phone,
+            notification.title,
+            notification.body
+        );
+
+        match &self.file {
+            Some(file) => {
+                let now = OffsetDateTime::now_utc().format(&Rfc3339).unwrap();
+                let mut file = file.lock().await;
+                file.write_all(format!("{now} {line}\n").as_bytes()).await?;
+                file.flush().await?;
+            }
+            None => tracing::info!("Notification {line}"),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+use super::{Channel, Notification, NotificationChannel, Recipient};
+use crate::error::Error;
+use serde_json::json;
+
+/// Delivers notifications as text messages through an HTTP SMS gateway.
+/// The gateway receives `POST` requests with `{"to": <phone>, "text": <text>}` body
+pub struct SmsChannel {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl SmsChannel {
+    pub fn new(url: String, token: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            token,
+        }
+    }
+}
+
+#[axum::async_trait]
+impl Channel for SmsChannel {
+    fn kind(&self) -> NotificationChannel {
+        NotificationChannel::Sms
+    }
+
+    async fn send(&self, recipient: &Recipient, notification: &Notification) -> Result<(), Error> {
+        let mut request = self.client.post(&self.url).json(&json! {{
+            "to": recipient.phone,
+            "text": format!("{}\n{}", notification.title, notification.body),
+        }});
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        request.send().await?.error_for_status()?;
+
+        Ok(())
+    }
+}
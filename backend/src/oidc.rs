@@ -0,0 +1,492 @@
+use crate::{error::Error, fail};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::{OnceCell, RwLock};
+
+/// Endpoints of the identity provider from its discovery document
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Claims of a validated ID token
+#[derive(Debug, Deserialize)]
+pub struct IdToken {
+    pub iss: String,
+    pub sub: String,
+    nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+}
+
+/// Client of an OpenID Connect provider using the authorization code flow with PKCE
+pub struct Oidc {
+    issuer: String,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_uri: String,
+    http: Client,
+    /// Fetched on the first use, so the provider being down does not prevent startup
+    metadata: OnceCell<Metadata>,
+    jwks: RwLock<JwkSet>,
+}
+
+impl Oidc {
+    /// Configures the provider from the environment, if `OIDC_ISSUER` is set.
+    /// `OIDC_CLIENT_ID` and `OIDC_REDIRECT_URI`, the public URL of `/auth/sso/callback`, are
+    /// required then. `OIDC_CLIENT_SECRET` is only needed for confidential clients
+    pub fn from_env() -> Option<Self> {
+        use dotenvy::var;
+
+        let issuer = var("OIDC_ISSUER").ok()?;
+        let client_id = var("OIDC_CLIENT_ID").expect("Env `OIDC_CLIENT_ID` is required for SSO");
+        let redirect_uri =
+            var("OIDC_REDIRECT_URI").expect("Env `OIDC_REDIRECT_URI` is required for SSO");
+
+        Some(Self::new(
+            &issuer,
+            client_id,
+            var("OIDC_CLIENT_SECRET").ok(),
+            redirect_uri,
+        ))
+    }
+
+    fn new(
+        issuer: &str,
+        client_id: String,
+        client_secret: Option<String>,
+        redirect_uri: String,
+    ) -> Self {
+        Self {
+            issuer: issuer.trim_end_matches('/').to_owned(),
+            client_id,
+            client_secret,
+            redirect_uri,
+            http: Client::new(),
+            metadata: OnceCell::new(),
+            jwks: RwLock::new(JwkSet { keys: vec![] }),
+        }
+    }
+
+    async fn metadata(&self) -> Result<&Metadata, Error> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.issuer);
+                let metadata = self
+                    .http
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<Metadata>()
+                    .await?;
+
+                if metadata.issuer.trim_end_matches('/') != self.issuer {
+                    tracing::error!("OIDC discovery returned issuer {}", metadata.issuer);
+                    fail!(!INTERNAL_SERVER_ERROR, "Неправильная настройка SSO");
+                }
+
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// URL of the provider to send the user to
+    pub async fn authorization_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str,
+    ) -> Result<String, Error> {
+        let metadata = self.metadata().await?;
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            [
+                ("response_type", "code"),
+                ("client_id", &self.client_id),
+                ("redirect_uri", &self.redirect_uri),
+                ("scope", "openid email profile"),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", &challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|_| fail!(INTERNAL_SERVER_ERROR, "Неправильная настройка SSO"))?;
+
+        Ok(url.into())
+    }
+
+    /// Exchanges the authorization code for the ID token and validates it
+    pub async fn exchange(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdToken, Error> {
+        let metadata = self.metadata().await?;
+
+        let mut request = self.http.post(&metadata.token_endpoint);
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.redirect_uri),
+            ("code_verifier", code_verifier),
+        ];
+        match &self.client_secret {
+            Some(secret) => request = request.basic_auth(&self.client_id, Some(secret)),
+            None => form.push(("client_id", &self.client_id)),
+        }
+
+        let response = request.form(&form).send().await?;
+        if !response.status().is_success() {
+            tracing::warn!(
+                "OIDC token endpoint responded with {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            );
+            fail!(!UNAUTHORIZED, "Не удалось войти через SSO");
+        }
+        let TokenResponse { id_token } = response.json().await?;
+
+        let token = self.validate(&id_token).await?;
+        if token.nonce.as_deref() != Some(nonce) {
+            fail!(!UNAUTHORIZED, "Не удалось войти через SSO");
+        }
+
+        Ok(token)
+    }
+
+    /// Checks signature, issuer, audience and expiry of the ID token
+    async fn validate(&self, id_token: &str) -> Result<IdToken, Error> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            fail!(!UNAUTHORIZED, "Не удалось войти через SSO");
+        }
+
+        let key = match self.key(header.kid.as_deref()).await? {
+            Some(key) => key,
+            // The provider may have rotated its keys
+            None => {
+                self.refresh_jwks().await?;
+                self.key(header.kid.as_deref())
+                    .await?
+                    .ok_or(fail!(UNAUTHORIZED, "Не удалось войти через SSO"))?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.metadata().await?.issuer]);
+        validation.set_audience(&[&self.client_id]);
+
+        Ok(jsonwebtoken::decode::<IdToken>(id_token, &key, &validation)?.claims)
+    }
+
+    async fn key(&self, kid: Option<&str>) -> Result<Option<DecodingKey>, Error> {
+        let jwks = self.jwks.read().await;
+        let jwk = match kid {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        };
+
+        Ok(jwk.map(DecodingKey::from_jwk).transpose()?)
+    }
+
+    async fn refresh_jwks(&self) -> Result<(), Error> {
+        let jwks = self
+            .http
+            .get(&self.metadata().await?.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<JwkSet>()
+            .await?;
+
+        *self.jwks.write().await = jwks;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        extract::State,
+        http::StatusCode,
+        routing::{get, post},
+        Form, Json, Router,
+    };
+    use jsonwebtoken::{
+        jwk::{
+            AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, OctetKeyPairParameters,
+            OctetKeyPairType,
+        },
+        EncodingKey, Header,
+    };
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+    use serde_json::{json, Value};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+    use time::OffsetDateTime;
+    use tokio::net::TcpListener;
+
+    const CLIENT_ID: &str = "snowy-owl";
+    const REDIRECT_URI: &str = "https://school.test/auth/sso/callback";
+
+    struct SigningKey {
+        kid: String,
+        encoding: EncodingKey,
+        jwk: Jwk,
+    }
+
+    impl SigningKey {
+        fn generate(kid: &str) -> Self {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+            let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+            let jwk = Jwk {
+                common: CommonParameters {
+                    key_id: Some(kid.to_owned()),
+                    ..Default::default()
+                },
+                algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(pair.public_key()),
+                }),
+            };
+
+            Self {
+                kid: kid.to_owned(),
+                encoding: EncodingKey::from_ed_der(pkcs8.as_ref()),
+                jwk,
+            }
+        }
+    }
+
+    /// Authorization granted by the user, waiting to be exchanged for the ID token
+    struct Grant {
+        redirect_uri: String,
+        challenge: String,
+        nonce: String,
+    }
+
+    struct Provider {
+        key: SigningKey,
+        grants: HashMap<String, Grant>,
+        /// Audience of issued ID tokens
+        audience: String,
+    }
+
+    /// Identity provider serving discovery, JWKS and token endpoints on a local port
+    struct MockIdp {
+        issuer: String,
+        provider: Mutex<Provider>,
+    }
+
+    impl MockIdp {
+        async fn start() -> Arc<Self> {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let idp = Arc::new(Self {
+                issuer: format!("http://{}", listener.local_addr().unwrap()),
+                provider: Mutex::new(Provider {
+                    key: SigningKey::generate("first"),
+                    grants: HashMap::new(),
+                    audience: CLIENT_ID.to_owned(),
+                }),
+            });
+
+            let app = Router::new()
+                .route("/.well-known/openid-configuration", get(discovery))
+                .route("/jwks", get(jwks))
+                .route("/token", post(token))
+                .with_state(idp.clone());
+            tokio::spawn(async move { axum::serve(listener, app).await });
+
+            idp
+        }
+
+        fn client(&self) -> Oidc {
+            Oidc::new(
+                &self.issuer,
+                CLIENT_ID.to_owned(),
+                None,
+                REDIRECT_URI.to_owned(),
+            )
+        }
+
+        /// Plays the user signing in at the authorization endpoint, returns the issued code
+        fn authorize(&self, url: &str) -> String {
+            let url = Url::parse(url).unwrap();
+            assert_eq!(
+                url.as_str().split('?').next(),
+                Some(&*format!("{}/authorize", self.issuer))
+            );
+            let params = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+            assert_eq!(params["response_type"], "code");
+            assert_eq!(params["client_id"], CLIENT_ID);
+            assert_eq!(params["code_challenge_method"], "S256");
+            assert!(params["scope"].split(' ').any(|s| s == "openid"));
+
+            let code = format!("code-{}", params["state"]);
+            self.provider.lock().unwrap().grants.insert(
+                code.clone(),
+                Grant {
+                    redirect_uri: params["redirect_uri"].clone(),
+                    challenge: params["code_challenge"].clone(),
+                    nonce: params["nonce"].clone(),
+                },
+            );
+
+            code
+        }
+    }
+
+    async fn discovery(State(idp): State<Arc<MockIdp>>) -> Json<Value> {
+        Json(json!({
+            "issuer": idp.issuer,
+            "authorization_endpoint": format!("{}/authorize", idp.issuer),
+            "token_endpoint": format!("{}/token", idp.issuer),
+            "jwks_uri": format!("{}/jwks", idp.issuer),
+        }))
+    }
+
+    async fn jwks(State(idp): State<Arc<MockIdp>>) -> Json<JwkSet> {
+        let provider = idp.provider.lock().unwrap();
+        Json(JwkSet {
+            keys: vec![provider.key.jwk.clone()],
+        })
+    }
+
+    async fn token(
+        State(idp): State<Arc<MockIdp>>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+        let mut provider = idp.provider.lock().unwrap();
+        let invalid = || {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "invalid_grant" })),
+            )
+        };
+
+        // Codes are single use
+        let grant = provider.grants.remove(&form["code"]).ok_or_else(invalid)?;
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes()));
+        if form["grant_type"] != "authorization_code"
+            || form["client_id"] != CLIENT_ID
+            || form["redirect_uri"] != grant.redirect_uri
+            || challenge != grant.challenge
+        {
+            return Err(invalid());
+        }
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let claims = json!({
+            "iss": idp.issuer,
+            "sub": "alice",
+            "aud": provider.audience,
+            "iat": now,
+            "exp": now + 300,
+            "nonce": grant.nonce,
+            "email": "alice@school.test",
+            "email_verified": true,
+        });
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(provider.key.kid.clone());
+        let id_token = jsonwebtoken::encode(&header, &claims, &provider.key.encoding).unwrap();
+
+        Ok(Json(
+            json!({ "id_token": id_token, "token_type": "Bearer" }),
+        ))
+    }
+
+    const VERIFIER: &str = "pkce-code-verifier-which-is-long-enough-for-the-provider-to-accept";
+
+    /// Goes through the login as the browser of the user would
+    async fn login(idp: &MockIdp, oidc: &Oidc, nonce: &str) -> Result<IdToken, Error> {
+        let url = oidc.authorization_url(nonce, nonce, VERIFIER).await?;
+        let code = idp.authorize(&url);
+
+        oidc.exchange(&code, VERIFIER, nonce).await
+    }
+
+    #[tokio::test]
+    async fn logs_in() {
+        let idp = MockIdp::start().await;
+
+        let token = login(&idp, &idp.client(), "nonce").await.unwrap();
+        assert_eq!(token.iss, idp.issuer);
+        assert_eq!(token.sub, "alice");
+        assert_eq!(token.email.as_deref(), Some("alice@school.test"));
+        assert!(token.email_verified);
+    }
+
+    #[tokio::test]
+    async fn rejects_other_nonce() {
+        let idp = MockIdp::start().await;
+        let oidc = idp.client();
+
+        let url = oidc
+            .authorization_url("state", "nonce", VERIFIER)
+            .await
+            .unwrap();
+        let code = idp.authorize(&url);
+        assert!(oidc.exchange(&code, VERIFIER, "other").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_other_code_verifier() {
+        let idp = MockIdp::start().await;
+        let oidc = idp.client();
+
+        let url = oidc
+            .authorization_url("state", "nonce", VERIFIER)
+            .await
+            .unwrap();
+        let code = idp.authorize(&url);
+        assert!(oidc.exchange(&code, "other", "nonce").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_token_for_other_client() {
+        let idp = MockIdp::start().await;
+        idp.provider.lock().unwrap().audience = "other-client".to_owned();
+
+        assert!(login(&idp, &idp.client(), "nonce").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn follows_key_rotation() {
+        let idp = MockIdp::start().await;
+        let oidc = idp.client();
+        login(&idp, &oidc, "first").await.unwrap();
+
+        idp.provider.lock().unwrap().key = SigningKey::generate("second");
+        let token = login(&idp, &oidc, "second").await.unwrap();
+        assert_eq!(token.sub, "alice");
+    }
+}
@@ -1,19 +1,39 @@
-use crate::error::Error;
+use crate::{error::Error, fail};
 use axum::{
     extract::{FromRequest, FromRequestParts, State},
+    http::header::CACHE_CONTROL,
     response::{IntoResponse, Response},
 };
-use serde::Serialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::json;
 
+pub mod announcements;
+pub mod audit;
 pub mod auth;
 pub mod classes;
+pub mod conversations;
+pub mod dependencies;
+pub mod impersonation;
+pub mod jwks;
+pub mod lockouts;
 pub mod marks;
+pub mod notifications;
+pub mod pagination;
+pub mod passwords;
+pub mod policy;
 pub mod principals;
+pub mod roles;
 pub mod rooms;
+pub mod schools;
+pub mod search;
+pub mod service_accounts;
+pub mod sso;
+pub mod staff;
 pub mod students;
 pub mod subjects;
 pub mod teachers;
+pub mod two_factor;
+pub mod versions;
 
 #[derive(FromRequest)]
 #[from_request(via(axum::Json), rejection(Error))]
@@ -29,6 +49,16 @@ impl<T: Serialize> IntoResponse for Json<T> {
     }
 }
 
+/// Responds with secrets such as API keys or recovery codes. The response is marked
+/// `Cache-Control: no-store`, so neither caches nor idempotent retries keep it
+pub struct Secret<T>(pub T);
+
+impl<T: Serialize> IntoResponse for Secret<T> {
+    fn into_response(self) -> Response {
+        ([(CACHE_CONTROL, "no-store")], Json(self.0)).into_response()
+    }
+}
+
 #[derive(FromRequestParts)]
 #[from_request(via(axum_extra::extract::Query), rejection(Error))]
 pub struct Query<T>(pub T);
@@ -37,5 +67,23 @@ pub struct Query<T>(pub T);
 #[from_request(via(axum::extract::Path), rejection(Error))]
 pub struct Path<T>(pub T);
 
+/// Tells an explicit `null` from an absent field of PATCH requests. Use with
+/// `#[serde(default, deserialize_with = "nullable")]` on `Option<Option<T>>` fields
+pub fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// Rejects an explicit `null` of a `nullable` PATCH field which cannot be cleared
+pub fn not_null<T>(value: Option<Option<T>>, field: &'static str) -> RouteResult<Option<T>> {
+    match value {
+        Some(None) => fail!(!BAD_REQUEST, "Поле не может быть пустым", field),
+        value => Ok(value.flatten()),
+    }
+}
+
 pub type RouteState = State<crate::AppState>;
 pub type RouteResult<T = ()> = Result<T, Error>;
@@ -0,0 +1,418 @@
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    pagination::{AnnouncementPage, Order, Page, Paged, Pagination},
+    roles, Json, Path, Query, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    jobs::{self, Job},
+    middleware::Claims,
+    models::{Announcement, Audience, AudienceKind, Permission, Role},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use serde::Deserialize;
+use sqlx::PgExecutor;
+use std::slice;
+use time::OffsetDateTime;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+/// Loads audiences of the announcements in place
+async fn attach_audience<'e>(
+    db: impl PgExecutor<'e>,
+    announcements: &mut [Announcement],
+) -> RouteResult {
+    let ids = announcements.iter().map(|a| a.id).collect::<Vec<_>>();
+
+    let rows = sqlx::query_as::<_, (i32, AudienceKind, Option<Role>, Option<i32>)>(
+        "
+            SELECT announcement_id, kind, role, class_id FROM AnnouncementAudiences
+            WHERE announcement_id = any($1)
+            ORDER BY id
+        ",
+    )
+    .bind(ids)
+    .fetch_all(db)
+    .await?;
+
+    for (announcement_id, kind, role, class_id) in rows {
+        if let Some(a) = announcements.iter_mut().find(|a| a.id == announcement_id) {
+            a.audience.push(Audience {
+                kind,
+                role,
+                class_id,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+struct Feed {
+    count: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Fetches published announcements addressed to the current user
+#[utoipa::path(
+    get,
+    path = "/announcements/feed",
+    tag = "Announcements",
+    params(Feed),
+    responses((status = 200, body = AnnouncementPage))
+)]
+async fn feed(
+    State(state): RouteState,
+    claims: Claims,
+    Query(query): Query<Feed>,
+) -> RouteResult<Json<Page<Announcement>>> {
+    let Feed { count, cursor } = query;
+
+    let school_id = claims.school()?;
+    let pagination = Pagination::new(
+        count,
+        cursor,
+        Order::by("published_at", "TIMESTAMPTZ").desc(true),
+    )?;
+
+    let (class_ids, parent_class_ids) = match claims.role {
+        Role::Student => {
+            let class_ids =
+                sqlx::query_scalar::<_, i32>("SELECT class_id FROM Students WHERE id = $1")
+                    .bind(claims.user_id)
+                    .fetch_all(&state.db)
+                    .await?;
+
+            (class_ids, vec![])
+        }
+        Role::Parent => {
+            let class_ids = sqlx::query_scalar::<_, i32>(
+                "
+                    SELECT DISTINCT class_id FROM Students
+                    JOIN ParentStudent ON student_id = id
+                    WHERE parent_id = $1
+                ",
+            )
+            .bind(claims.user_id)
+            .fetch_all(&state.db)
+            .await?;
+
+            (vec![], class_ids)
+        }
+        Role::Teacher | Role::Principal | Role::Staff | Role::Admin => (vec![], vec![]),
+    };
+
+    let query = pagination.query(
+        r#"
+            SELECT * FROM Announcements A
+            WHERE
+                school_id = $7 AND
+                published_at <= now() AND
+                coalesce(expires_at > now(), true) AND
+                EXISTS (
+                    SELECT 1 FROM AnnouncementAudiences T
+                    WHERE T.announcement_id = A.id AND (
+                        kind = 'everyone' OR
+                        (kind = 'role' AND role = $4) OR
+                        (kind = 'class' AND class_id = any($5)) OR
+                        (kind = 'class_parents' AND class_id = any($6))
+                    )
+                )
+        "#,
+    );
+    let announcements = pagination
+        .bind(sqlx::query_as::<_, Paged<Announcement>>(&query))
+        .bind(claims.role)
+        .bind(class_ids)
+        .bind(parent_class_ids)
+        .bind(school_id)
+        .fetch_all(&state.db)
+        .await?;
+
+    let mut page = pagination.finish(announcements);
+    attach_audience(&state.db, &mut page.items).await?;
+
+    Ok(Json(page))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+struct Fetch {
+    author_id: Option<i32>,
+    count: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Fetches all announcements including scheduled and expired ones.
+/// Teachers only see their own announcements
+#[utoipa::path(
+    get,
+    path = "/announcements",
+    tag = "Announcements",
+    params(Fetch),
+    responses((status = 200, body = AnnouncementPage))
+)]
+async fn fetch(
+    State(state): RouteState,
+    claims: Claims,
+    Query(query): Query<Fetch>,
+) -> RouteResult<Json<Page<Announcement>>> {
+    let Fetch {
+        author_id,
+        count,
+        cursor,
+    } = query;
+
+    let school_id = claims.school()?;
+    let author_id = if roles::has(&state.db, &claims, Permission::AnnouncementsManage).await? {
+        author_id
+    } else if claims.role.is_employee() {
+        Some(claims.user_id)
+    } else {
+        fail!(!FORBIDDEN, "Недостаточно прав");
+    };
+
+    let pagination = Pagination::new(
+        count,
+        cursor,
+        Order::by("published_at", "TIMESTAMPTZ").desc(true),
+    )?;
+
+    let query = pagination.query(
+        "
+            SELECT * FROM Announcements
+            WHERE school_id = $5 AND coalesce(author_id = $4, true)
+        ",
+    );
+    let announcements = pagination
+        .bind(sqlx::query_as::<_, Paged<Announcement>>(&query))
+        .bind(author_id)
+        .bind(school_id)
+        .fetch_all(&state.db)
+        .await?;
+
+    let mut page = pagination.finish(announcements);
+    attach_audience(&state.db, &mut page.items).await?;
+
+    Ok(Json(page))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct CreateAnnouncementRequest {
+    title: String,
+    body: String,
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    published_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    expires_at: Option<OffsetDateTime>,
+    audience: Vec<Audience>,
+}
+
+/// Creates new announcement.
+/// If `published_at` is omitted the announcement is published immediately
+#[utoipa::path(
+    post,
+    path = "/announcements",
+    tag = "Announcements",
+    request_body = CreateAnnouncementRequest,
+    responses((status = 200, body = Announcement))
+)]
+async fn create(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CreateAnnouncementRequest>,
+) -> RouteResult<Json<Announcement>> {
+    let CreateAnnouncementRequest {
+        title,
+        body,
+        published_at,
+        expires_at,
+        audience,
+    } = data;
+
+    if !claims.role.is_employee() {
+        fail!(!FORBIDDEN, "Объявления могут публиковать только сотрудники");
+    }
+    let school_id = claims.school()?;
+
+    if audience.is_empty() {
+        fail!(!BAD_REQUEST, "Необходимо указать получателей", "audience");
+    }
+    for Audience {
+        kind,
+        role,
+        class_id,
+    } in &audience
+    {
+        let valid = match kind {
+            AudienceKind::Everyone => role.is_none() && class_id.is_none(),
+            AudienceKind::Role => role.is_some() && class_id.is_none(),
+            AudienceKind::Class | AudienceKind::ClassParents =>
+                role.is_none() && class_id.is_some(),
+        };
+        if !valid {
+            fail!(!BAD_REQUEST, "Неправильно указаны получатели", "audience");
+        }
+    }
+
+    let published_at = published_at.unwrap_or_else(OffsetDateTime::now_utc);
+    if matches!(expires_at, Some(expires_at) if expires_at <= published_at) {
+        fail!(
+            !BAD_REQUEST,
+            "Срок действия должен истекать после публикации",
+            "expires_at"
+        );
+    }
+
+    let mut tx = state.db.begin().await?;
+    let mut announcement = sqlx::query_as::<_, Announcement>(
+        "
+            INSERT INTO Announcements(author_id, title, body, published_at, expires_at, school_id)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+        ",
+    )
+    .bind(claims.user_id)
+    .bind(title)
+    .bind(body)
+    .bind(published_at)
+    .bind(expires_at)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    for Audience {
+        kind,
+        role,
+        class_id,
+    } in &audience
+    {
+        let result = sqlx::query(
+            "
+                INSERT INTO AnnouncementAudiences(announcement_id, kind, role, class_id, school_id)
+                VALUES ($1, $2, $3, $4, $5)
+            ",
+        )
+        .bind(announcement.id)
+        .bind(kind)
+        .bind(role)
+        .bind(class_id)
+        .bind(school_id)
+        .execute(&mut *tx)
+        .await;
+
+        match result {
+            Ok(_) => {}
+            Err(err) if matches!(err.as_database_error(), Some(err) if err.is_foreign_key_violation()) =>
+                fail!(!BAD_REQUEST, "Класс с таким ИД не существует", "audience"),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    jobs::enqueue(
+        &mut *tx,
+        Job::AnnouncementPublished {
+            announcement_id: announcement.id,
+        },
+        Some(announcement.published_at),
+    )
+    .await?;
+
+    announcement.audience = audience;
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::Announcement,
+        Some(announcement.id),
+        None,
+        Some(&announcement),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(announcement))
+}
+
+/// Deletes an announcement by id.
+/// Teachers can only delete their own announcements
+#[utoipa::path(
+    delete,
+    path = "/announcements/{id}",
+    tag = "Announcements",
+    params(("id" = i32, Path, description = "Id of the announcement to delete")),
+    responses((status = 200))
+)]
+async fn remove(Path(id): Path<i32>, State(state): RouteState, claims: Claims) -> RouteResult {
+    let school_id = claims.school()?;
+    let author_id = if roles::has(&state.db, &claims, Permission::AnnouncementsManage).await? {
+        None
+    } else if claims.role.is_employee() {
+        Some(claims.user_id)
+    } else {
+        fail!(!FORBIDDEN, "Недостаточно прав");
+    };
+
+    let mut tx = state.db.begin().await?;
+    let Some(mut announcement) = sqlx::query_as::<_, Announcement>(
+        "
+            SELECT * FROM Announcements
+            WHERE id = $1 AND school_id = $3 AND coalesce(author_id = $2, true)
+            FOR UPDATE
+        ",
+    )
+    .bind(id)
+    .bind(author_id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!BAD_REQUEST, "Объявления с таким ИД не существует");
+    };
+    attach_audience(&mut *tx, slice::from_mut(&mut announcement)).await?;
+
+    sqlx::query("DELETE FROM Announcements WHERE id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Delete,
+        AuditEntity::Announcement,
+        Some(id),
+        Some(&announcement),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(feed, fetch, create, remove),
+        components(schemas(
+            Announcement,
+            AnnouncementPage,
+            Audience,
+            AudienceKind,
+            Role,
+            CreateAnnouncementRequest
+        ))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(fetch).post(create))
+        .route("/feed", get(feed))
+        .route("/:id", delete(remove))
+}
@@ -0,0 +1,266 @@
+use super::{
+    pagination::{AuditEntryPage, Order, Page, Paged, Pagination},
+    roles, Json, Query, RouteResult, RouteState,
+};
+use crate::{
+    middleware::Claims,
+    models::{Participant, Permission, Role},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{prelude::FromRow, types::Json as SqlJson, PgConnection, Type};
+use time::OffsetDateTime;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    Archive,
+    Restore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEntity {
+    Subject,
+    Class,
+    Room,
+    Student,
+    Teacher,
+    Principal,
+    Mark,
+    Announcement,
+    SecurityPolicy,
+    ServiceAccount,
+    ApiKey,
+    Impersonation,
+    Staff,
+    AccessRole,
+    RoleAssignment,
+    School,
+    Admin,
+    Password,
+    PasswordResetCode,
+    Lockout,
+    TwoFactor,
+    SsoIdentity,
+    Session,
+}
+
+/// Who performed the audited operation
+struct Actor {
+    role: Option<Role>,
+    id: Option<i32>,
+    impersonator_id: Option<i32>,
+    service_account_id: Option<i32>,
+    school_id: Option<i32>,
+}
+
+/// Records a write operation in the audit log. `before` is not set for created, archived and
+/// restored entities and `after` is not set for deleted ones.
+/// Logins and logouts are not recorded, the `Sessions` and `FailedLogins` tables keep them.
+/// Neither are messages and notification preferences, they are the users' own data
+pub(super) async fn record<T: Serialize + Sync>(
+    db: &mut PgConnection,
+    actor: Option<&Claims>,
+    action: AuditAction,
+    entity: AuditEntity,
+    entity_id: Option<i32>,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> RouteResult {
+    // Requests authenticated by an API key have no user behind them
+    let (role, id) = match actor {
+        Some(claims) if claims.service_account_id.is_none() =>
+            (Some(claims.role), Some(claims.user_id)),
+        _ => (None, None),
+    };
+    let actor = Actor {
+        role,
+        id,
+        impersonator_id: actor.and_then(|c| c.impersonator_id),
+        service_account_id: actor.and_then(|c| c.service_account_id),
+        school_id: actor.and_then(|c| c.school_id),
+    };
+
+    insert(db, actor, action, entity, entity_id, before, after).await
+}
+
+/// Records an operation of a user who is not signed in, e.g. a password reset by code
+/// or the first single sign-on
+pub(super) async fn record_user<T: Serialize + Sync>(
+    db: &mut PgConnection,
+    user: Participant,
+    school_id: Option<i32>,
+    action: AuditAction,
+    entity: AuditEntity,
+    entity_id: Option<i32>,
+    after: Option<&T>,
+) -> RouteResult {
+    let actor = Actor {
+        role: Some(user.role),
+        id: Some(user.user_id),
+        impersonator_id: None,
+        service_account_id: None,
+        school_id,
+    };
+
+    insert(db, actor, action, entity, entity_id, None, after).await
+}
+
+async fn insert<T: Serialize + Sync>(
+    db: &mut PgConnection,
+    actor: Actor,
+    action: AuditAction,
+    entity: AuditEntity,
+    entity_id: Option<i32>,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> RouteResult {
+    sqlx::query(
+        "
+            INSERT INTO AuditLog(
+                actor_role, actor_id, impersonator_id, service_account_id, action, entity,
+                entity_id, before, after, school_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ",
+    )
+    .bind(actor.role)
+    .bind(actor.id)
+    .bind(actor.impersonator_id)
+    .bind(actor.service_account_id)
+    .bind(action)
+    .bind(entity)
+    .bind(entity_id)
+    .bind(before.map(SqlJson))
+    .bind(after.map(SqlJson))
+    .bind(actor.school_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct AuditEntry {
+    id: i64,
+    /// Not set for requests without authentication or made by a service account
+    actor_role: Option<Role>,
+    actor_id: Option<i32>,
+    /// Set if the principal acted as the actor
+    impersonator_id: Option<i32>,
+    service_account_id: Option<i32>,
+    /// Not set for district-level operations
+    school_id: Option<i32>,
+    action: AuditAction,
+    entity: AuditEntity,
+    entity_id: Option<i32>,
+    #[schema(value_type = Option<Object>)]
+    before: Option<Value>,
+    #[schema(value_type = Option<Object>)]
+    after: Option<Value>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+struct Fetch {
+    /// Only used by district administrators, others see the log of their school
+    school_id: Option<i32>,
+    actor_role: Option<Role>,
+    actor_id: Option<i32>,
+    entity: Option<AuditEntity>,
+    entity_id: Option<i32>,
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    after: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    before: Option<OffsetDateTime>,
+    count: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Fetches the audit log, newest entries first
+#[utoipa::path(
+    get,
+    path = "/audit",
+    tag = "Audit log",
+    params(Fetch),
+    responses((status = 200, body = AuditEntryPage), (status = 403))
+)]
+async fn fetch(
+    State(state): RouteState,
+    claims: Claims,
+    Query(query): Query<Fetch>,
+) -> RouteResult<Json<Page<AuditEntry>>> {
+    let Fetch {
+        school_id,
+        actor_role,
+        actor_id,
+        entity,
+        entity_id,
+        after,
+        before,
+        count,
+        cursor,
+    } = query;
+
+    roles::ensure(&state.db, &claims, Permission::AuditRead).await?;
+    let school_id = match claims.role {
+        Role::Admin => school_id,
+        _ => Some(claims.school()?),
+    };
+
+    let pagination = Pagination::new(count, cursor, Order::ID.desc(true))?;
+
+    let query = pagination.query(
+        "
+            SELECT * FROM AuditLog
+            WHERE
+                coalesce(actor_role = $4, true) AND
+                coalesce(actor_id = $5, true) AND
+                coalesce(entity = $6, true) AND
+                coalesce(entity_id = $7, true) AND
+                created_at BETWEEN
+                    coalesce($8, '-infinity'::timestamptz) AND
+                    coalesce($9, 'infinity'::timestamptz) AND
+                ($10::INTEGER IS NULL OR school_id = $10)
+        ",
+    );
+    let entries = pagination
+        .bind(sqlx::query_as::<_, Paged<AuditEntry>>(&query))
+        .bind(actor_role)
+        .bind(actor_id)
+        .bind(entity)
+        .bind(entity_id)
+        .bind(after)
+        .bind(before)
+        .bind(school_id)
+        .fetch_all(&state.db)
+        .await?;
+
+    Ok(Json(pagination.finish(entries)))
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(fetch),
+        components(schemas(AuditEntry, AuditEntryPage, AuditAction, AuditEntity, Role))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(fetch))
+}
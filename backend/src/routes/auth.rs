@@ -1,27 +1,54 @@
+use super::audit::{self, AuditAction, AuditEntity};
 use super::Json;
+use super::Path;
 use super::RouteResult;
 use super::RouteState;
 use crate::models::Employee;
+use crate::models::Parent;
+use crate::models::Participant;
 use crate::models::Role;
+use crate::models::Session;
+use crate::models::Student;
 use crate::models::Teacher;
 use crate::AppState;
-use crate::{error::Error, fail, middleware::Claims, ENCODING_KEY};
+use crate::{
+    error::Error,
+    fail,
+    middleware::{Claims, ClientIp},
+    KEYS,
+};
 use argon2::Argon2;
 use argon2::PasswordVerifier;
 use axum::{extract::State, routing::*};
-use axum_extra::either::Either;
+use axum_extra::either::Either4;
 use axum_extra::extract::{cookie::Cookie, CookieJar};
-use jsonwebtoken::Header;
-use serde::Deserialize;
-use sqlx::Row;
+use axum_extra::headers::UserAgent;
+use axum_extra::TypedHeader;
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgExecutor, Row};
+use std::net::IpAddr;
 use time::{Duration, OffsetDateTime};
 use utoipa::OpenApi;
 use utoipa::ToSchema;
 
+#[derive(Serialize)]
+struct Me<T> {
+    #[serde(flatten)]
+    user: T,
+    /// Set if a principal acts as the user, see `/auth/impersonate`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    impersonator_id: Option<i32>,
+}
+
 async fn me(
     State(state): RouteState,
     claims: Claims,
-) -> RouteResult<Either<Json<Teacher>, Json<Employee>>> {
+) -> RouteResult<Either4<Json<Me<Teacher>>, Json<Me<Employee>>, Json<Me<Student>>, Json<Me<Parent>>>>
+{
+    let impersonator_id = claims.impersonator_id;
+
     let resp = match claims.role {
         Role::Teacher => {
             let tch = sqlx::query_as::<_, Teacher>(
@@ -31,100 +58,662 @@ async fn me(
                 WHERE role = 'teacher' AND id = $1
             ",
             )
-            .bind(claims.employee_id)
+            .bind(claims.user_id)
             .fetch_one(&state.db)
             .await?;
 
-            Either::E1(Json(tch))
+            Either4::E1(Json(Me {
+                user: tch,
+                impersonator_id,
+            }))
         }
-        Role::Principal => {
+        Role::Principal | Role::Staff | Role::Admin => {
             let emp = sqlx::query_as::<_, Employee>(
                 "
                     SELECT * FROM Employees
-                    WHERE role = 'principal' AND id = $1
+                    WHERE role = $2 AND id = $1
                 ",
             )
-            .bind(claims.employee_id)
+            .bind(claims.user_id)
+            .bind(claims.role)
             .fetch_one(&state.db)
             .await?;
 
-            Either::E2(Json(emp))
+            Either4::E2(Json(Me {
+                user: emp,
+                impersonator_id,
+            }))
+        }
+        Role::Student => {
+            let student = sqlx::query_as::<_, Student>("SELECT * FROM Students WHERE id = $1")
+                .bind(claims.user_id)
+                .fetch_one(&state.db)
+                .await?;
+
+            Either4::E3(Json(Me {
+                user: student,
+                impersonator_id,
+            }))
+        }
+        Role::Parent => {
+            let parent = sqlx::query_as::<_, Parent>("SELECT * FROM Parents WHERE id = $1")
+                .bind(claims.user_id)
+                .fetch_one(&state.db)
+                .await?;
+
+            Either4::E4(Json(Me {
+                user: parent,
+                impersonator_id,
+            }))
         }
-        Role::Student => todo!(),
     };
 
     Ok(resp)
 }
 
+const ACCESS_TOKEN_TTL: Duration = Duration::minutes(15);
+const REFRESH_TOKEN_TTL: Duration = Duration::days(14);
+
 #[derive(Deserialize, ToSchema)]
 #[serde(deny_unknown_fields)]
 struct LoginRequest {
     phone: String,
     password: String,
+    /// Required when the phone and the password match several accounts, e.g. a teacher
+    /// who is also a parent of a student
+    #[serde(default)]
+    account: Option<Account>,
+}
+
+/// Table the login account is stored in
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum Account {
+    Employee,
+    Student,
+    Parent,
+}
+
+impl Account {
+    fn of(role: Role) -> Self {
+        match role {
+            Role::Student => Self::Student,
+            Role::Parent => Self::Parent,
+            _ => Self::Employee,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct LoginResponse {
+    /// Set when the second step is required, pass it to `/auth/login/2fa`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    challenge: Option<String>,
+    /// The session can only be used to enroll into two-factor authentication
+    totp_enrollment_required: bool,
+    /// The session can only be used to change the password
+    password_change_required: bool,
 }
 
-/// Login as an employee
+/// Limits of a new session, until they are lifted only `/auth` endpoints are available
+#[derive(Clone, Copy, Default)]
+pub(super) struct Restrictions {
+    pub totp_pending: bool,
+    pub password_change_pending: bool,
+    /// Set for single sign-on, the session is never restricted by the 2FA policy
+    pub totp_exempt: bool,
+}
+
+/// Login as an employee, a student or a parent.
+/// Employees with two-factor authentication enabled receive a challenge instead of the session
 #[utoipa::path(
     post,
     path = "/auth/login",
     tag = "Authentication",
     request_body = LoginRequest,
-    responses((status = 200), (status = 400))
+    responses(
+        (status = 200, body = LoginResponse),
+        (status = 400),
+        (status = 409, description = "Several accounts match, repeat with the `account` set")
+    )
 )]
 async fn login(
     State(state): RouteState,
-    mut jar: CookieJar,
+    jar: CookieJar,
+    ClientIp(ip): ClientIp,
+    user_agent: Option<TypedHeader<UserAgent>>,
     Json(data): Json<LoginRequest>,
-) -> Result<CookieJar, Error> {
-    let LoginRequest { phone, password } = data;
+) -> Result<(CookieJar, Json<LoginResponse>), Error> {
+    let LoginRequest {
+        phone,
+        password,
+        account,
+    } = data;
+    let user_agent_str = user_agent.as_ref().map(|TypedHeader(ua)| ua.as_str());
 
-    let row = sqlx::query("SELECT id, password_hash, role FROM Employees WHERE phone = $1")
-        .bind(phone)
-        .fetch_optional(&state.db)
-        .await?
-        .ok_or(fail!(BAD_REQUEST, "Неправильный телефон или пароль"))?;
-    let password_hash = row.get::<String, _>("password_hash");
+    super::lockouts::check(&state.db, &phone, ip, user_agent_str).await?;
+
+    // The phone is only unique within a table, so a teacher who is also a parent has two
+    // accounts. Only accounts the password matches are offered to choose from
+    let rows = sqlx::query(
+        "
+            SELECT id, password_hash, role, totp_enabled, password_change_required, school_id
+            FROM Employees WHERE phone = $1 AND archived_at IS NULL
+            UNION ALL
+            SELECT id, password_hash, 'student'::Role, false, password_change_required, school_id
+            FROM Students WHERE phone = $1 AND archived_at IS NULL
+            UNION ALL
+            SELECT id, password_hash, 'parent'::Role, false, password_change_required, school_id
+            FROM Parents WHERE phone = $1
+        ",
+    )
+    .bind(&phone)
+    .fetch_all(&state.db)
+    .await?;
+    let mut rows = rows
+        .into_iter()
+        .filter(|row| account.is_none_or(|a| a == Account::of(row.get("role"))))
+        .filter(|row| {
+            let password_hash = row.get::<String, _>("password_hash");
+
+            Argon2::default()
+                .verify_password(
+                    password.as_bytes(),
+                    &password_hash.as_str().try_into().unwrap(),
+                )
+                .is_ok()
+        })
+        .collect::<Vec<_>>();
+    if rows.len() > 1 {
+        let accounts = rows
+            .iter()
+            .map(|row| Account::of(row.get("role")))
+            .collect::<Vec<_>>();
+        return Err(Error::Conflict {
+            message: "Выберите учётную запись для входа".into(),
+            details: serde_json::json!({ "accounts": accounts }),
+        });
+    }
+    let Some(row) = rows.pop() else {
+        super::lockouts::record_failure(&state.db, &phone, ip, user_agent_str).await?;
+        fail!(!BAD_REQUEST, "Неправильный телефон или пароль");
+    };
+
+    let role = row.get("role");
+    let user_id = row.get("id");
+
+    // Failed logins are only forgotten once the second factor is passed as well, otherwise
+    // restarting the login would allow guessing codes without ever being locked out
+    if row.get::<bool, _>("totp_enabled") {
+        let challenge = random_token(32);
+        sqlx::query(
+            "
+                INSERT INTO LoginChallenges(id, employee_id, expires_at)
+                VALUES ($1, $2, now() + interval '5 minutes')
+            ",
+        )
+        .bind(&challenge)
+        .bind(user_id)
+        .execute(&state.db)
+        .await?;
+
+        let response = LoginResponse {
+            challenge: Some(challenge),
+            totp_enrollment_required: false,
+            password_change_required: false,
+        };
+        return Ok((jar, Json(response)));
+    }
+
+    super::lockouts::reset(&state.db, &phone).await?;
+
+    let restrictions = Restrictions {
+        totp_pending: matches!(role, Role::Principal | Role::Admin)
+            && super::policy::requires_2fa(&state.db, row.get("school_id")).await?,
+        password_change_pending: row.get("password_change_required"),
+        totp_exempt: false,
+    };
 
-    Argon2::default()
-        .verify_password(
-            password.as_bytes(),
-            &password_hash.as_str().try_into().unwrap(),
+    let mut tx = state.db.begin().await?;
+    let jar = start_session(&mut tx, jar, role, user_id, user_agent, ip, restrictions).await?;
+    tx.commit().await?;
+
+    let response = LoginResponse {
+        challenge: None,
+        totp_enrollment_required: restrictions.totp_pending,
+        password_change_required: restrictions.password_change_pending,
+    };
+    Ok((jar, Json(response)))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct SecondFactorRequest {
+    challenge: String,
+    /// One-time password from the authenticator app or one of the recovery codes
+    code: String,
+}
+
+/// Completes the login of an employee with two-factor authentication enabled
+#[utoipa::path(
+    post,
+    path = "/auth/login/2fa",
+    tag = "Authentication",
+    request_body = SecondFactorRequest,
+    responses((status = 200, body = LoginResponse), (status = 400))
+)]
+async fn login_second_factor(
+    State(state): RouteState,
+    jar: CookieJar,
+    ClientIp(ip): ClientIp,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    Json(data): Json<SecondFactorRequest>,
+) -> RouteResult<(CookieJar, Json<LoginResponse>)> {
+    let SecondFactorRequest { challenge, code } = data;
+    let user_agent_str = user_agent.as_ref().map(|TypedHeader(ua)| ua.as_str());
+
+    let mut tx = state.db.begin().await?;
+    let Some((employee_id, role, password_change_required, phone)) =
+        sqlx::query_as::<_, (i32, Role, bool, String)>(
+            "
+            UPDATE LoginChallenges C SET attempts = attempts + 1
+            FROM Employees E
+            WHERE
+                C.id = $1 AND E.id = C.employee_id AND E.archived_at IS NULL AND
+                expires_at > now() AND attempts < 5
+            RETURNING E.id, E.role, E.password_change_required, E.phone
+        ",
         )
-        .map_err(|_| fail!(BAD_REQUEST, "Неправильный телефон или пароль"))?;
+        .bind(&challenge)
+        .fetch_optional(&mut *tx)
+        .await?
+    else {
+        fail!(!BAD_REQUEST, "Время входа истекло, войдите снова");
+    };
+    super::lockouts::check(&state.db, &phone, ip, user_agent_str).await?;
+
+    if !super::two_factor::verify(&mut tx, employee_id, &code).await? {
+        // Keep the incremented attempts counter
+        tx.commit().await?;
+        super::lockouts::record_failure(&state.db, &phone, ip, user_agent_str).await?;
+        fail!(!BAD_REQUEST, "Неправильный код", "code");
+    }
+
+    sqlx::query("DELETE FROM LoginChallenges WHERE id = $1")
+        .bind(&challenge)
+        .execute(&mut *tx)
+        .await?;
+    let restrictions = Restrictions {
+        totp_pending: false,
+        password_change_pending: password_change_required,
+        totp_exempt: false,
+    };
+    let jar = start_session(
+        &mut tx,
+        jar,
+        role,
+        employee_id,
+        user_agent,
+        ip,
+        restrictions,
+    )
+    .await?;
+    tx.commit().await?;
+    super::lockouts::reset(&state.db, &phone).await?;
+
+    let response = LoginResponse {
+        challenge: None,
+        totp_enrollment_required: false,
+        password_change_required,
+    };
+    Ok((jar, Json(response)))
+}
+
+/// Creates a new session and issues its tokens
+pub(super) async fn start_session(
+    db: &mut PgConnection,
+    jar: CookieJar,
+    role: Role,
+    user_id: i32,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    ip: IpAddr,
+    restrictions: Restrictions,
+) -> RouteResult<CookieJar> {
+    let session_id = random_token(32);
+    let school_id = sqlx::query_scalar::<_, Option<i32>>(&format!(
+        "SELECT school_id FROM {} WHERE id = $1",
+        super::passwords::table(role)
+    ))
+    .bind(user_id)
+    .fetch_one(&mut *db)
+    .await?;
+
+    sqlx::query(
+        "
+            INSERT INTO Sessions(
+                id, role, user_id, expires_at, user_agent, ip, totp_pending, password_change_pending,
+                totp_exempt, school_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ",
+    )
+    .bind(&session_id)
+    .bind(role)
+    .bind(user_id)
+    .bind(OffsetDateTime::now_utc() + REFRESH_TOKEN_TTL)
+    .bind(user_agent.map(|TypedHeader(ua)| ua.to_string()))
+    .bind(ip.to_string())
+    .bind(restrictions.totp_pending)
+    .bind(restrictions.password_change_pending)
+    .bind(restrictions.totp_exempt)
+    .bind(school_id)
+    .execute(&mut *db)
+    .await?;
+
+    issue_tokens(db, jar, role, user_id, session_id).await
+}
+
+/// Exchanges the refresh token cookie for a new access token and refresh token.
+/// Presenting an already used refresh token revokes the whole session
+#[utoipa::path(
+    post,
+    path = "/auth/refresh",
+    tag = "Authentication",
+    responses((status = 200), (status = 401))
+)]
+async fn refresh(State(state): RouteState, mut jar: CookieJar) -> RouteResult<CookieJar> {
+    let token_hash = hash_token(
+        jar.get("refresh_token")
+            .ok_or(fail!(UNAUTHORIZED, "Необходима авторизация"))?
+            .value(),
+    );
+
+    let mut tx = state.db.begin().await?;
+    let Some(row) = sqlx::query(
+        "
+            SELECT
+                R.session_id,
+                R.used_at IS NOT NULL AS used,
+                R.expires_at > now() AND S.revoked_at IS NULL AS active,
+                S.role,
+                S.user_id
+            FROM RefreshTokens R
+            JOIN Sessions S ON S.id = R.session_id
+            WHERE R.token_hash = $1
+            FOR UPDATE OF R
+        ",
+    )
+    .bind(&token_hash)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!UNAUTHORIZED, "Необходима авторизация");
+    };
+    let session_id = row.get::<String, _>("session_id");
+
+    if row.get::<bool, _>("used") {
+        tracing::warn!("Refresh token reuse detected, revoking session {session_id}");
+
+        sqlx::query("UPDATE Sessions SET revoked_at = now() WHERE id = $1")
+            .bind(&session_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        fail!(!UNAUTHORIZED, "Сессия завершена, необходима авторизация");
+    }
+    if !row.get::<bool, _>("active") {
+        fail!(!UNAUTHORIZED, "Сессия завершена, необходима авторизация");
+    }
+
+    sqlx::query("UPDATE RefreshTokens SET used_at = now() WHERE token_hash = $1")
+        .bind(&token_hash)
+        .execute(&mut *tx)
+        .await?;
+
+    jar = issue_tokens(
+        &mut tx,
+        jar,
+        row.get("role"),
+        row.get("user_id"),
+        session_id,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(jar)
+}
+
+fn remove_tokens(jar: CookieJar) -> CookieJar {
+    jar.remove(Cookie::build("token").path("/"))
+        .remove(Cookie::build("refresh_token").path("/"))
+}
+
+pub(super) fn random_token(len: usize) -> String {
+    (&mut OsRng)
+        .sample_iter(Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+pub(crate) fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Signs a short-lived access token for the session and issues a new refresh token of the same
+/// session, extending its lifetime
+async fn issue_tokens(
+    db: &mut PgConnection,
+    jar: CookieJar,
+    role: Role,
+    user_id: i32,
+    session_id: String,
+) -> RouteResult<CookieJar> {
+    let now = OffsetDateTime::now_utc();
+    let refresh_token = random_token(48);
+    let refresh_expires_at = now + REFRESH_TOKEN_TTL;
+
+    sqlx::query(
+        "
+            INSERT INTO RefreshTokens(token_hash, session_id, expires_at)
+            VALUES ($1, $2, $3)
+        ",
+    )
+    .bind(hash_token(&refresh_token))
+    .bind(&session_id)
+    .bind(refresh_expires_at)
+    .execute(&mut *db)
+    .await?;
+    let school_id = sqlx::query_scalar::<_, Option<i32>>(
+        "UPDATE Sessions SET expires_at = $2 WHERE id = $1 RETURNING school_id",
+    )
+    .bind(&session_id)
+    .bind(refresh_expires_at)
+    .fetch_one(&mut *db)
+    .await?;
 
-    let expires_at = OffsetDateTime::now_utc() + Duration::days(3);
     let claims = Claims {
-        expires_at,
-        role: row.get("role"),
-        employee_id: row.get("id"),
+        expires_at: now + ACCESS_TOKEN_TTL,
+        role,
+        user_id,
+        session_id,
+        school_id,
+        impersonator_id: None,
+        service_account_id: None,
     };
+    let token = KEYS.get().unwrap().encode(&claims)?;
 
-    let token =
-        jsonwebtoken::encode(&Header::default(), &claims, ENCODING_KEY.get().unwrap()).unwrap();
-    let cookie = Cookie::build(("token", token))
-        .expires(expires_at)
+    let access = Cookie::build(("token", token))
+        .expires(claims.expires_at)
+        .secure(true)
+        .http_only(true)
+        .path("/");
+    let refresh = Cookie::build(("refresh_token", refresh_token))
+        .expires(refresh_expires_at)
         .secure(true)
         .http_only(true)
         .path("/");
 
-    jar = jar.remove("token").add(cookie);
-    Ok(jar)
+    Ok(jar.add(access).add(refresh))
 }
 
-/// Log out
+/// Log out and revoke the current session
 #[utoipa::path(
     post,
     path = "/auth/logout",
     tag = "Authentication",
     responses((status = 200))
 )]
-async fn logout(jar: CookieJar) -> CookieJar {
-    jar.remove("token")
+async fn logout(
+    State(state): RouteState,
+    claims: Option<Claims>,
+    jar: CookieJar,
+) -> RouteResult<CookieJar> {
+    if let Some(claims) = claims {
+        sqlx::query("UPDATE Sessions SET revoked_at = now() WHERE id = $1")
+            .bind(claims.session_id)
+            .execute(&state.db)
+            .await?;
+    }
+
+    Ok(remove_tokens(jar))
+}
+
+/// Fetches active sessions of the current user
+#[utoipa::path(
+    get,
+    path = "/auth/sessions",
+    tag = "Authentication",
+    responses((status = 200, body = Vec<Session>))
+)]
+async fn sessions(State(state): RouteState, claims: Claims) -> RouteResult<Json<Vec<Session>>> {
+    let sessions = sqlx::query_as::<_, Session>(
+        "
+            SELECT *, id = $3 AS current FROM Sessions
+            WHERE
+                role = $1 AND user_id = $2 AND
+                revoked_at IS NULL AND expires_at > now()
+            ORDER BY last_seen_at DESC
+        ",
+    )
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .bind(&claims.session_id)
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok(Json(sessions))
+}
+
+/// Revokes a session of the current user by id
+#[utoipa::path(
+    delete,
+    path = "/auth/sessions/{id}",
+    tag = "Authentication",
+    params(("id" = String, Path, description = "Id of the session to revoke")),
+    responses((status = 200))
+)]
+async fn revoke(Path(id): Path<String>, State(state): RouteState, claims: Claims) -> RouteResult {
+    let mut tx = state.db.begin().await?;
+    let result = sqlx::query(
+        "
+            UPDATE Sessions SET revoked_at = now()
+            WHERE id = $1 AND role = $2 AND user_id = $3 AND revoked_at IS NULL
+        ",
+    )
+    .bind(&id)
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        fail!(!NOT_FOUND, "Сессии с таким ИД не существует");
+    }
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Delete,
+        AuditEntity::Session,
+        None,
+        Some(&serde_json::json!({ "session_id": id })),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Revokes all sessions of the current user including the current one
+#[utoipa::path(
+    delete,
+    path = "/auth/sessions",
+    tag = "Authentication",
+    responses((status = 200))
+)]
+async fn revoke_all(
+    State(state): RouteState,
+    claims: Claims,
+    jar: CookieJar,
+) -> RouteResult<CookieJar> {
+    let mut tx = state.db.begin().await?;
+    revoke_sessions(&mut *tx, claims.role, claims.user_id).await?;
+    let user = Participant {
+        role: claims.role,
+        user_id: claims.user_id,
+    };
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Delete,
+        AuditEntity::Session,
+        None,
+        Some(&user),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(remove_tokens(jar))
+}
+
+/// Revokes all sessions of the user, e.g. once the user is archived
+pub(super) async fn revoke_sessions<'e>(
+    db: impl PgExecutor<'e>,
+    role: Role,
+    user_id: i32,
+) -> RouteResult {
+    sqlx::query(
+        "
+            UPDATE Sessions SET revoked_at = now()
+            WHERE role = $1 AND user_id = $2 AND revoked_at IS NULL
+        ",
+    )
+    .bind(role)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
 }
 
 pub fn openapi() -> utoipa::openapi::OpenApi {
     #[derive(OpenApi)]
-    #[openapi(paths(login, logout), components(schemas(LoginRequest)))]
+    #[openapi(
+        paths(
+            login,
+            login_second_factor,
+            refresh,
+            logout,
+            sessions,
+            revoke,
+            revoke_all
+        ),
+        components(schemas(LoginRequest, Account, LoginResponse, SecondFactorRequest, Session))
+    )]
     struct Api;
 
     Api::openapi()
@@ -133,6 +722,15 @@ pub fn openapi() -> utoipa::openapi::OpenApi {
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/login", post(login))
+        .route("/login/2fa", post(login_second_factor))
+        .route("/refresh", post(refresh))
         .route("/logout", post(logout))
         .route("/me", get(me))
+        .route("/sessions", get(sessions).delete(revoke_all))
+        .route("/sessions/:id", delete(revoke))
+        .nest("/2fa", super::two_factor::router())
+        .nest("/password", super::passwords::router())
+        .nest("/lockouts", super::lockouts::router())
+        .nest("/sso", super::sso::router())
+        .nest("/impersonate", super::impersonation::router())
 }
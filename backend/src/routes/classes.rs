@@ -1,51 +1,117 @@
-use super::{Json, Path, Query, RouteResult, RouteState};
-use crate::{fail, models::Class, AppState};
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    dependencies::{self, Dependency, DependencyKind, Remove, Source},
+    not_null, nullable,
+    pagination::{ClassPage, Order, Page, Paged, Pagination},
+    roles, students,
+    versions::{IfMatch, Tagged},
+    Json, Path, Query, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Class, Permission},
+    AppState,
+};
 use axum::{extract::State, routing::*};
 use serde::Deserialize;
 use utoipa::{IntoParams, OpenApi, ToSchema};
 
+const DEPENDENCIES: &[Source] = &[
+    Source {
+        kind: DependencyKind::Student,
+        blocking: true,
+        query:
+            "SELECT id FROM Students WHERE class_id = $1 AND school_id = $2 AND archived_at IS NULL",
+    },
+    Source {
+        kind: DependencyKind::Announcement,
+        blocking: false,
+        query: "
+            SELECT DISTINCT announcement_id AS id FROM AnnouncementAudiences
+            WHERE class_id = $1 AND school_id = $2
+        ",
+    },
+];
+
 #[derive(Deserialize, IntoParams)]
 #[serde(deny_unknown_fields)]
 struct Fetch {
     name: Option<String>,
     id: Option<i32>,
+    /// Fetch archived classes instead of active ones
+    #[serde(default)]
+    archived: bool,
     count: Option<i64>,
-    offset: Option<i64>,
+    cursor: Option<String>,
 }
 
 /// Fetches classes names and ids
-#[utoipa::path(get, path = "/classes", tag = "Classes management", params(Fetch))]
+#[utoipa::path(get, path = "/classes", tag = "Classes management", params(Fetch), responses((status = 200, body = ClassPage)))]
 async fn fetch(
     State(state): RouteState,
+    claims: Claims,
     Query(query): Query<Fetch>,
-) -> RouteResult<Json<Vec<Class>>> {
+) -> RouteResult<Json<Page<Class>>> {
     let Fetch {
         name,
         id,
+        archived,
         count,
-        offset,
+        cursor,
     } = query;
 
-    let count = count.unwrap_or(50).clamp(0, 50);
-    let offset = offset.unwrap_or(0).clamp(0, 5000);
+    let school_id = claims.school()?;
+    let pagination = Pagination::new(count, cursor, Order::ID)?;
 
-    let classes = sqlx::query_as::<_, Class>(
+    let query = pagination.query(
         r#"
-        SELECT * FROM Classes
-        WHERE
-            coalesce(class ILIKE ('%' || $3 || '%'), true) AND
-            coalesce(id = $4, true)
-        LIMIT $1 OFFSET $2
-    "#,
-    )
-    .bind(count)
-    .bind(offset)
-    .bind(name)
-    .bind(id)
-    .fetch_all(&state.db)
-    .await?;
+            SELECT * FROM Classes
+            WHERE
+                school_id = $6 AND
+                (archived_at IS NOT NULL) = $7 AND
+                coalesce(class ILIKE ('%' || $4 || '%'), true) AND
+                coalesce(id = $5, true)
+        "#,
+    );
+    let classes = pagination
+        .bind(sqlx::query_as::<_, Paged<Class>>(&query))
+        .bind(name)
+        .bind(id)
+        .bind(school_id)
+        .bind(archived)
+        .fetch_all(&state.db)
+        .await?;
 
-    Ok(Json(classes))
+    Ok(Json(pagination.finish(classes)))
+}
+
+/// Fetches a class by id, the `ETag` header carries its version
+#[utoipa::path(
+    get,
+    path = "/classes/{id}",
+    tag = "Classes management",
+    params(("id" = i32, Path, description = "Id of the class")),
+    responses((status = 200, body = Class), (status = 404))
+)]
+async fn fetch_one(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Class>> {
+    let school_id = claims.school()?;
+
+    let Some(class) =
+        sqlx::query_as::<_, Class>("SELECT * FROM Classes WHERE id = $1 AND school_id = $2")
+            .bind(id)
+            .bind(school_id)
+            .fetch_optional(&state.db)
+            .await?
+    else {
+        fail!(!NOT_FOUND, "Класса с таким ИД не существует");
+    };
+
+    Ok(Tagged(class))
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -63,21 +129,43 @@ struct CreateOrUpdateClassRequest {
 )]
 async fn create(
     State(state): RouteState,
+    claims: Claims,
     Json(data): Json<CreateOrUpdateClassRequest>,
-) -> RouteResult<Json<Class>> {
+) -> RouteResult<Tagged<Class>> {
     let CreateOrUpdateClassRequest { name } = data;
 
-    let result = sqlx::query_as::<_, Class>("INSERT INTO Classes(class) VALUES($1) RETURNING *")
-        .bind(name)
-        .fetch_one(&state.db)
-        .await;
+    roles::ensure(&state.db, &claims, Permission::ClassesWrite).await?;
+    let school_id = claims.school()?;
 
-    match result {
-        Ok(class) => Ok(Json(class)),
+    let mut tx = state.db.begin().await?;
+    let result = sqlx::query_as::<_, Class>(
+        "INSERT INTO Classes(class, school_id) VALUES($1, $2) RETURNING *",
+    )
+    .bind(name)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
+    .await;
+
+    let class = match result {
+        Ok(class) => class,
         Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
             fail!(!BAD_REQUEST, "Такой класс уже существует"),
-        Err(err) => Err(err.into()),
-    }
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::Class,
+        Some(class.id),
+        None,
+        Some(&class),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(class))
 }
 
 /// Updates a class with specified id
@@ -86,48 +174,276 @@ async fn create(
     path = "/classes/{id}",
     tag = "Classes management",
     params(("id" = i32, Path, description = "Id of the new class to update")),
-    responses((status = 200, body = Class))
+    responses((status = 200, body = Class), (status = 412), (status = 428))
 )]
 async fn update(
     Path(id): Path<i32>,
     State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
     Json(data): Json<CreateOrUpdateClassRequest>,
-) -> RouteResult<Json<Class>> {
+) -> RouteResult<Tagged<Class>> {
     let CreateOrUpdateClassRequest { name } = data;
 
-    let Some(class) =
-        sqlx::query_as::<_, Class>("UPDATE Classes SET class = $2 WHERE id = $1 RETURNING *")
-            .bind(id)
-            .bind(name)
-            .fetch_optional(&state.db)
-            .await?
+    roles::ensure(&state.db, &claims, Permission::ClassesWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let before = sqlx::query_as::<_, Class>(
+        "SELECT * FROM Classes WHERE id = $1 AND school_id = $2 FOR UPDATE",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if_match.check(before.as_ref())?;
+    let Some(class) = sqlx::query_as::<_, Class>(
+        "
+            UPDATE Classes SET class = $2
+            WHERE id = $1 AND school_id = $3 AND archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
     else {
         fail!(!BAD_REQUEST, "Класса с таким ИД не существует");
     };
 
-    Ok(Json(class))
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Class,
+        Some(id),
+        before.as_ref(),
+        Some(&class),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(class))
 }
 
-/// Deletes a class by id
-#[utoipa::path(delete, path = "/classes/{id}", tag = "Classes management", params(("id" = i32, Path, description = "Id of the class to delete")))]
-async fn remove(Path(id): Path<i32>, State(state): RouteState) -> RouteResult {
-    let result = sqlx::query("DELETE FROM Classes WHERE id = $1")
-        .bind(id)
-        .execute(&state.db)
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct PatchClassRequest {
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    name: Option<Option<String>>,
+}
+
+/// Changes some fields of a class with specified id
+#[utoipa::path(
+    patch,
+    path = "/classes/{id}",
+    tag = "Classes management",
+    params(("id" = i32, Path, description = "Id of the class to update")),
+    request_body = PatchClassRequest,
+    responses((status = 200, body = Class), (status = 412), (status = 428))
+)]
+async fn patch(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+    Json(data): Json<PatchClassRequest>,
+) -> RouteResult<Tagged<Class>> {
+    let PatchClassRequest { name } = data;
+    let name = not_null(name, "name")?;
+
+    roles::ensure(&state.db, &claims, Permission::ClassesWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let before = sqlx::query_as::<_, Class>(
+        "SELECT * FROM Classes WHERE id = $1 AND school_id = $2 FOR UPDATE",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if_match.check(before.as_ref())?;
+    let Some(class) = sqlx::query_as::<_, Class>(
+        "
+            UPDATE Classes SET class = coalesce($2, class)
+            WHERE id = $1 AND school_id = $3 AND archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!BAD_REQUEST, "Класса с таким ИД не существует");
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Class,
+        Some(id),
+        before.as_ref(),
+        Some(&class),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(class))
+}
+
+/// Fetches rows referencing the class
+#[utoipa::path(
+    get,
+    path = "/classes/{id}/dependencies",
+    tag = "Classes management",
+    params(("id" = i32, Path, description = "Id of the class")),
+    responses((status = 200, body = Vec<Dependency>))
+)]
+async fn fetch_dependencies(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Json<Vec<Dependency>>> {
+    roles::ensure(&state.db, &claims, Permission::ClassesWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut conn = state.db.acquire().await?;
+    let dependencies = dependencies::collect(&mut conn, id, school_id, DEPENDENCIES).await?;
+
+    Ok(Json(dependencies))
+}
+
+/// Archives a class by id. Fails with 409 while the class has active students, unless they are
+/// moved to another class with `reassign_to`
+#[utoipa::path(delete, path = "/classes/{id}", tag = "Classes management", params(("id" = i32, Path, description = "Id of the class to archive"), Remove), responses((status = 200), (status = 409), (status = 412), (status = 428)))]
+async fn remove(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+    Query(query): Query<Remove>,
+) -> RouteResult {
+    let Remove { reassign_to } = query;
+
+    roles::ensure(&state.db, &claims, Permission::ClassesWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    if_match
+        .lock(&mut tx, "Classes", id, Some(school_id))
         .await?;
+    let Some(class) = sqlx::query_as::<_, Class>(
+        "
+            UPDATE Classes SET archived_at = now()
+            WHERE id = $1 AND school_id = $2 AND archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!BAD_REQUEST, "Класса с таким ИД не существует");
+    };
 
-    if result.rows_affected() == 0 {
-        fail!(!BAD_REQUEST, "Такого предмета не существует");
+    if let Some(target) = reassign_to {
+        dependencies::ensure_target(&mut tx, "Classes", id, target, school_id).await?;
+        students::reassign_class(&mut tx, &claims, school_id, id, target).await?;
     }
+    let dependencies = dependencies::collect(&mut tx, id, school_id, DEPENDENCIES).await?;
+    dependencies::ensure_unblocked(dependencies)?;
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Archive,
+        AuditEntity::Class,
+        Some(id),
+        None,
+        Some(&class),
+    )
+    .await?;
+    tx.commit().await?;
 
     Ok(())
 }
 
+/// Restores an archived class by id
+#[utoipa::path(
+    post,
+    path = "/classes/{id}/restore",
+    tag = "Classes management",
+    params(("id" = i32, Path, description = "Id of the class to restore")),
+    responses((status = 200, body = Class))
+)]
+async fn restore(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Class>> {
+    roles::ensure(&state.db, &claims, Permission::ClassesWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(class) = sqlx::query_as::<_, Class>(
+        "
+            UPDATE Classes SET archived_at = NULL
+            WHERE id = $1 AND school_id = $2 AND archived_at IS NOT NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!BAD_REQUEST, "Архивного класса с таким ИД не существует");
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Restore,
+        AuditEntity::Class,
+        Some(id),
+        None,
+        Some(&class),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(class))
+}
+
 pub fn openapi() -> utoipa::openapi::OpenApi {
     #[derive(OpenApi)]
     #[openapi(
-        paths(remove, fetch, update, create),
-        components(schemas(CreateOrUpdateClassRequest))
+        paths(
+            remove,
+            fetch,
+            fetch_one,
+            update,
+            patch,
+            create,
+            restore,
+            fetch_dependencies
+        ),
+        components(schemas(
+            CreateOrUpdateClassRequest,
+            PatchClassRequest,
+            Class,
+            ClassPage,
+            Dependency,
+            DependencyKind
+        ))
     )]
     struct Api;
 
@@ -137,5 +453,10 @@ pub fn openapi() -> utoipa::openapi::OpenApi {
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(fetch).post(create))
-        .route("/:id", put(update).delete(remove))
+        .route(
+            "/:id",
+            get(fetch_one).put(update).patch(patch).delete(remove),
+        )
+        .route("/:id/restore", post(restore))
+        .route("/:id/dependencies", get(fetch_dependencies))
 }
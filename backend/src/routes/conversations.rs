@@ -0,0 +1,531 @@
+use super::{
+    pagination::{ConversationPage, MessagePage, Order, Page, Paged, Pagination},
+    Json, Path, Query, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Conversation, Message, Participant, Role},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use serde::Deserialize;
+use sqlx::PgPool;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+/// Checks whether the current user is allowed to start a conversation with `to`.
+/// Employees can message anyone in their school, students can message employees, their
+/// classmates and parents, parents can only message teachers and principals of the school
+/// their children attend
+async fn can_message(db: &PgPool, claims: &Claims, to: Participant) -> RouteResult<bool> {
+    let school_id = claims.school()?;
+    let query = match (claims.role, to.role) {
+        (_, Role::Teacher | Role::Principal | Role::Staff) if claims.role != Role::Parent =>
+            sqlx::query_scalar(
+                "
+                    SELECT EXISTS(
+                        SELECT 1 FROM Employees
+                        WHERE id = $1 AND role = $2 AND school_id = $3 AND archived_at IS NULL
+                    )
+                ",
+            )
+            .bind(to.user_id)
+            .bind(to.role)
+            .bind(school_id),
+        (Role::Teacher | Role::Principal | Role::Staff, Role::Student) => sqlx::query_scalar(
+            "
+                SELECT EXISTS(
+                    SELECT 1 FROM Students WHERE id = $1 AND school_id = $2 AND archived_at IS NULL
+                )
+            ",
+        )
+        .bind(to.user_id)
+        .bind(school_id),
+        (Role::Teacher | Role::Principal | Role::Staff, Role::Parent) => sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM Parents WHERE id = $1 AND school_id = $2)",
+        )
+        .bind(to.user_id)
+        .bind(school_id),
+        (Role::Student, Role::Student) => sqlx::query_scalar(
+            "
+                SELECT EXISTS(
+                    SELECT 1 FROM Students A
+                    JOIN Students B ON A.class_id = B.class_id
+                    WHERE A.id = $1 AND B.id = $2 AND B.archived_at IS NULL
+                )
+            ",
+        )
+        .bind(claims.user_id)
+        .bind(to.user_id),
+        (Role::Student, Role::Parent) => sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM ParentStudent WHERE student_id = $1 AND parent_id = $2)",
+        )
+        .bind(claims.user_id)
+        .bind(to.user_id),
+        // Teachers are assigned to subjects rather than classes and every class studies every
+        // subject, so every teacher of the child's school teaches the child
+        (Role::Parent, Role::Teacher) => sqlx::query_scalar(
+            "
+                SELECT EXISTS(
+                    SELECT 1 FROM ParentStudent P
+                    JOIN Students S ON S.id = P.student_id
+                    JOIN Teachers T ON T.school_id = S.school_id
+                    JOIN Employees E ON E.id = T.employee_id
+                    WHERE
+                        P.parent_id = $1 AND S.archived_at IS NULL AND
+                        T.employee_id = $2 AND E.archived_at IS NULL
+                )
+            ",
+        )
+        .bind(claims.user_id)
+        .bind(to.user_id),
+        (Role::Parent, Role::Principal) => sqlx::query_scalar(
+            "
+                SELECT EXISTS(
+                    SELECT 1 FROM ParentStudent P
+                    JOIN Students S ON S.id = P.student_id
+                    JOIN Employees E ON E.school_id = S.school_id
+                    WHERE
+                        P.parent_id = $1 AND S.archived_at IS NULL AND
+                        E.id = $2 AND E.role = 'principal' AND E.archived_at IS NULL
+                )
+            ",
+        )
+        .bind(claims.user_id)
+        .bind(to.user_id),
+        _ => return Ok(false),
+    };
+
+    let allowed = query.fetch_one(db).await?;
+
+    Ok(allowed)
+}
+
+/// Fails unless the current user participates in the conversation
+async fn ensure_participant(db: &PgPool, claims: &Claims, conversation_id: i32) -> RouteResult {
+    let exists = sqlx::query_scalar::<_, bool>(
+        "
+            SELECT EXISTS(
+                SELECT 1 FROM ConversationParticipants
+                WHERE conversation_id = $1 AND role = $2 AND user_id = $3
+            )
+        ",
+    )
+    .bind(conversation_id)
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .fetch_one(db)
+    .await?;
+
+    if !exists {
+        fail!(!NOT_FOUND, "Диалога с таким ИД не существует");
+    }
+
+    Ok(())
+}
+
+/// Loads participants and last messages of the conversations in place
+async fn attach_details(db: &PgPool, conversations: &mut [Conversation]) -> RouteResult {
+    let ids = conversations.iter().map(|c| c.id).collect::<Vec<_>>();
+
+    let participants = sqlx::query_as::<_, (i32, Role, i32)>(
+        "
+            SELECT conversation_id, role, user_id FROM ConversationParticipants
+            WHERE conversation_id = any($1)
+        ",
+    )
+    .bind(&ids)
+    .fetch_all(db)
+    .await?;
+
+    for (conversation_id, role, user_id) in participants {
+        if let Some(c) = conversations.iter_mut().find(|c| c.id == conversation_id) {
+            c.participants.push(Participant { role, user_id });
+        }
+    }
+
+    let messages = sqlx::query_as::<_, Message>(
+        "
+            SELECT DISTINCT ON (conversation_id) * FROM Messages
+            WHERE conversation_id = any($1)
+            ORDER BY conversation_id, id DESC
+        ",
+    )
+    .bind(&ids)
+    .fetch_all(db)
+    .await?;
+
+    for message in messages {
+        if let Some(c) = conversations
+            .iter_mut()
+            .find(|c| c.id == message.conversation_id)
+        {
+            c.last_message = Some(message);
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+struct Fetch {
+    count: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Fetches conversations of the current user, most recently active first
+#[utoipa::path(
+    get,
+    path = "/conversations",
+    tag = "Messaging",
+    params(Fetch),
+    responses((status = 200, body = ConversationPage))
+)]
+async fn fetch(
+    State(state): RouteState,
+    claims: Claims,
+    Query(query): Query<Fetch>,
+) -> RouteResult<Json<Page<Conversation>>> {
+    let Fetch { count, cursor } = query;
+
+    let pagination = Pagination::new(
+        count,
+        cursor,
+        Order::by("active_at", "TIMESTAMPTZ").desc(true),
+    )?;
+
+    let query = pagination.query(
+        r#"
+            SELECT
+                C.id,
+                C.created_at,
+                (
+                    SELECT count(*) FROM Messages M
+                    WHERE
+                        M.conversation_id = C.id AND
+                        M.id > P.last_read_message_id AND
+                        NOT (M.sender_role = $4 AND M.sender_id = $5)
+                ) AS unread,
+                coalesce(
+                    (SELECT max(sent_at) FROM Messages WHERE conversation_id = C.id),
+                    C.created_at
+                ) AS active_at
+            FROM Conversations C
+            JOIN ConversationParticipants P ON P.conversation_id = C.id
+            WHERE P.role = $4 AND P.user_id = $5
+        "#,
+    );
+    let conversations = pagination
+        .bind(sqlx::query_as::<_, Paged<Conversation>>(&query))
+        .bind(claims.role)
+        .bind(claims.user_id)
+        .fetch_all(&state.db)
+        .await?;
+
+    let mut page = pagination.finish(conversations);
+    attach_details(&state.db, &mut page.items).await?;
+
+    Ok(Json(page))
+}
+
+/// Returns total number of unread messages of the current user
+#[utoipa::path(
+    get,
+    path = "/conversations/unread",
+    tag = "Messaging",
+    responses((status = 200, body = i64))
+)]
+async fn unread(State(state): RouteState, claims: Claims) -> RouteResult<Json<i64>> {
+    let unread = sqlx::query_scalar::<_, i64>(
+        "
+            SELECT count(*) FROM Messages M
+            JOIN ConversationParticipants P USING (conversation_id)
+            WHERE
+                P.role = $1 AND P.user_id = $2 AND
+                M.id > P.last_read_message_id AND
+                NOT (M.sender_role = $1 AND M.sender_id = $2)
+        ",
+    )
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .fetch_one(&state.db)
+    .await?;
+
+    Ok(Json(unread))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct CreateConversationRequest {
+    participants: Vec<Participant>,
+    body: String,
+}
+
+/// Starts a new conversation with the first message
+#[utoipa::path(
+    post,
+    path = "/conversations",
+    tag = "Messaging",
+    request_body = CreateConversationRequest,
+    responses((status = 200, body = Conversation))
+)]
+async fn create(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CreateConversationRequest>,
+) -> RouteResult<Json<Conversation>> {
+    let CreateConversationRequest {
+        mut participants,
+        body,
+    } = data;
+
+    if body.trim().is_empty() {
+        fail!(!BAD_REQUEST, "Сообщение не может быть пустым", "body");
+    }
+
+    let me = Participant {
+        role: claims.role,
+        user_id: claims.user_id,
+    };
+    let mut seen = vec![me];
+    participants.retain(|p| {
+        let new = !seen.contains(p);
+        seen.push(*p);
+        new
+    });
+    if participants.is_empty() {
+        fail!(
+            !BAD_REQUEST,
+            "Необходимо указать собеседников",
+            "participants"
+        );
+    }
+
+    for &participant in &participants {
+        if !can_message(&state.db, &claims, participant).await? {
+            fail!(
+                !FORBIDDEN,
+                "Вы не можете писать этому пользователю",
+                "participants"
+            );
+        }
+    }
+
+    let mut tx = state.db.begin().await?;
+    let (id, created_at) = sqlx::query_as::<_, (i32, time::OffsetDateTime)>(
+        "INSERT INTO Conversations(school_id) VALUES ($1) RETURNING id, created_at",
+    )
+    .bind(claims.school()?)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    participants.push(me);
+    for &Participant { role, user_id } in &participants {
+        sqlx::query(
+            "
+                INSERT INTO ConversationParticipants(conversation_id, role, user_id)
+                VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING
+            ",
+        )
+        .bind(id)
+        .bind(role)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let message = sqlx::query_as::<_, Message>(
+        "
+            INSERT INTO Messages(conversation_id, sender_role, sender_id, body)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .bind(body)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "
+            UPDATE ConversationParticipants SET last_read_message_id = $4
+            WHERE conversation_id = $1 AND role = $2 AND user_id = $3
+        ",
+    )
+    .bind(id)
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .bind(message.id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(Conversation {
+        id,
+        created_at,
+        unread: 0,
+        participants,
+        last_message: Some(message),
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+struct History {
+    count: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Fetches messages of a conversation, newest first
+#[utoipa::path(
+    get,
+    path = "/conversations/{id}/messages",
+    tag = "Messaging",
+    params(("id" = i32, Path, description = "Id of the conversation"), History),
+    responses((status = 200, body = MessagePage))
+)]
+async fn history(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    Query(query): Query<History>,
+) -> RouteResult<Json<Page<Message>>> {
+    let History { count, cursor } = query;
+
+    let pagination = Pagination::new(count, cursor, Order::ID.desc(true))?;
+
+    ensure_participant(&state.db, &claims, id).await?;
+
+    let query = pagination.query("SELECT * FROM Messages WHERE conversation_id = $4");
+    let messages = pagination
+        .bind(sqlx::query_as::<_, Paged<Message>>(&query))
+        .bind(id)
+        .fetch_all(&state.db)
+        .await?;
+
+    Ok(Json(pagination.finish(messages)))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct SendMessageRequest {
+    body: String,
+}
+
+/// Sends a message to the conversation
+#[utoipa::path(
+    post,
+    path = "/conversations/{id}/messages",
+    tag = "Messaging",
+    params(("id" = i32, Path, description = "Id of the conversation")),
+    request_body = SendMessageRequest,
+    responses((status = 200, body = Message))
+)]
+async fn send(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<SendMessageRequest>,
+) -> RouteResult<Json<Message>> {
+    let SendMessageRequest { body } = data;
+
+    if body.trim().is_empty() {
+        fail!(!BAD_REQUEST, "Сообщение не может быть пустым", "body");
+    }
+
+    ensure_participant(&state.db, &claims, id).await?;
+
+    let mut tx = state.db.begin().await?;
+    let message = sqlx::query_as::<_, Message>(
+        "
+            INSERT INTO Messages(conversation_id, sender_role, sender_id, body)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .bind(body)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "
+            UPDATE ConversationParticipants SET last_read_message_id = $4
+            WHERE conversation_id = $1 AND role = $2 AND user_id = $3
+        ",
+    )
+    .bind(id)
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .bind(message.id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(message))
+}
+
+/// Marks all messages of the conversation as read
+#[utoipa::path(
+    post,
+    path = "/conversations/{id}/read",
+    tag = "Messaging",
+    params(("id" = i32, Path, description = "Id of the conversation")),
+    responses((status = 200))
+)]
+async fn read(Path(id): Path<i32>, State(state): RouteState, claims: Claims) -> RouteResult {
+    let result = sqlx::query(
+        "
+            UPDATE ConversationParticipants
+            SET last_read_message_id = coalesce(
+                (SELECT max(id) FROM Messages WHERE conversation_id = $1),
+                0
+            )
+            WHERE conversation_id = $1 AND role = $2 AND user_id = $3
+        ",
+    )
+    .bind(id)
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .execute(&state.db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        fail!(!NOT_FOUND, "Диалога с таким ИД не существует");
+    }
+
+    Ok(())
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(fetch, unread, create, history, send, read),
+        components(schemas(
+            Conversation,
+            ConversationPage,
+            Message,
+            MessagePage,
+            Participant,
+            CreateConversationRequest,
+            SendMessageRequest
+        ))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(fetch).post(create))
+        .route("/unread", get(unread))
+        .route("/:id/messages", get(history).post(send))
+        .route("/:id/read", post(read))
+}
@@ -0,0 +1,120 @@
+use super::RouteResult;
+use crate::{error::Error, fail};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use utoipa::{IntoParams, ToSchema};
+
+/// Kind of rows referencing an entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyKind {
+    Room,
+    Teacher,
+    Student,
+    Parent,
+    Mark,
+    Announcement,
+}
+
+/// Rows referencing an entity
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Dependency {
+    pub kind: DependencyKind,
+    /// Active rows prevent the entity from being archived. Others, like marks, are history which
+    /// is kept along with the archived entity
+    pub blocking: bool,
+    pub count: i64,
+    /// Ids of the first 100 rows
+    pub ids: Vec<i32>,
+}
+
+/// Query selecting `id` of the referencing rows, `$1` is the id of the entity and `$2` is the
+/// school
+pub(super) struct Source {
+    pub kind: DependencyKind,
+    pub blocking: bool,
+    pub query: &'static str,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Remove {
+    /// Moves the blocking rows to this entity before archiving
+    pub reassign_to: Option<i32>,
+}
+
+/// Collects the rows referencing the entity, skipping the sources without any
+pub(super) async fn collect(
+    db: &mut PgConnection,
+    id: i32,
+    school_id: i32,
+    sources: &[Source],
+) -> RouteResult<Vec<Dependency>> {
+    let mut dependencies = vec![];
+    for source in sources {
+        let (count, ids) = sqlx::query_as::<_, (i64, Vec<i32>)>(&format!(
+            "
+                SELECT count(*), coalesce((array_agg(id ORDER BY id))[1:100], '{{}}')
+                FROM ({}) T
+            ",
+            source.query
+        ))
+        .bind(id)
+        .bind(school_id)
+        .fetch_one(&mut *db)
+        .await?;
+
+        if count > 0 {
+            dependencies.push(Dependency {
+                kind: source.kind,
+                blocking: source.blocking,
+                count,
+                ids,
+            });
+        }
+    }
+
+    Ok(dependencies)
+}
+
+/// Fails with 409 listing the blocking dependencies if there are any
+pub(super) fn ensure_unblocked(dependencies: Vec<Dependency>) -> RouteResult {
+    let blocking = dependencies
+        .into_iter()
+        .filter(|d| d.blocking)
+        .collect::<Vec<_>>();
+    if blocking.is_empty() {
+        return Ok(());
+    }
+
+    Err(Error::Conflict {
+        message: "Нельзя архивировать, пока на запись ссылаются другие записи".into(),
+        details: serde_json::json!({ "dependencies": blocking }),
+    })
+}
+
+/// Rejects the reassignment target unless it is another active row of the `table` in the school
+pub(super) async fn ensure_target(
+    db: &mut PgConnection,
+    table: &str,
+    id: i32,
+    target: i32,
+    school_id: i32,
+) -> RouteResult {
+    let exists = sqlx::query_scalar::<_, bool>(&format!(
+        "SELECT EXISTS(SELECT 1 FROM {table} WHERE id = $1 AND school_id = $2 AND archived_at IS NULL)"
+    ))
+    .bind(target)
+    .bind(school_id)
+    .fetch_one(db)
+    .await?;
+    if !exists || target == id {
+        fail!(
+            !BAD_REQUEST,
+            "Запись для переноса не найдена",
+            "reassign_to"
+        );
+    }
+
+    Ok(())
+}
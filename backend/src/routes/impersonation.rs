@@ -0,0 +1,197 @@
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    auth::random_token,
+    roles, Json, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::{Claims, ClientIp},
+    models::{Participant, Permission, Role},
+    AppState, KEYS,
+};
+use axum::{extract::State, routing::*};
+use axum_extra::{
+    extract::{cookie::Cookie, CookieJar},
+    headers::UserAgent,
+    TypedHeader,
+};
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+use utoipa::OpenApi;
+
+/// Impersonation sessions cannot be refreshed and end after this time
+const IMPERSONATION_TTL: Duration = Duration::minutes(30);
+
+#[derive(Serialize)]
+struct Impersonation {
+    #[serde(flatten)]
+    target: Participant,
+    session_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    expires_at: OffsetDateTime,
+}
+
+/// Replaces the access token with a short-lived one acting as another user, so support sees
+/// exactly what the user sees. The refresh token is kept, `/auth/refresh` returns to the own
+/// session
+#[utoipa::path(
+    post,
+    path = "/auth/impersonate",
+    tag = "Impersonation",
+    request_body = Participant,
+    responses((status = 200), (status = 403), (status = 404))
+)]
+async fn start(
+    State(state): RouteState,
+    claims: Claims,
+    jar: CookieJar,
+    ClientIp(ip): ClientIp,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    Json(data): Json<Participant>,
+) -> RouteResult<CookieJar> {
+    let Participant { role, user_id } = data;
+
+    let permissions = roles::resolve(&state.db, &claims).await?;
+    if !permissions.contains(&Permission::Impersonate) {
+        fail!(!FORBIDDEN, "Недостаточно прав");
+    }
+    // District administrators act within schools as their principals
+    if claims.impersonator_id.is_some() || role == Role::Admin {
+        fail!(!FORBIDDEN, "Недостаточно прав");
+    }
+    // Restricted sessions can reach `/auth` endpoints, they must not act as anyone
+    let restricted = sqlx::query_scalar::<_, bool>(
+        "SELECT totp_pending OR password_change_pending FROM Sessions WHERE id = $1",
+    )
+    .bind(&claims.session_id)
+    .fetch_one(&state.db)
+    .await?;
+    if restricted {
+        fail!(!FORBIDDEN, "Недостаточно прав");
+    }
+    if role == claims.role && user_id == claims.user_id {
+        fail!(!BAD_REQUEST, "Нельзя войти от своего имени");
+    }
+
+    let school_id = sqlx::query_scalar::<_, i32>(
+        "
+            SELECT school_id FROM Employees WHERE id = $2 AND role = $1 AND archived_at IS NULL
+            UNION ALL
+            SELECT school_id FROM Students WHERE id = $2 AND $1 = 'student' AND archived_at IS NULL
+            UNION ALL
+            SELECT school_id FROM Parents WHERE id = $2 AND $1 = 'parent'
+        ",
+    )
+    .bind(role)
+    .bind(user_id)
+    .fetch_optional(&state.db)
+    .await?
+    .filter(|&school_id| claims.role == Role::Admin || claims.school_id == Some(school_id));
+    let Some(school_id) = school_id else {
+        fail!(!NOT_FOUND, "Пользователь не найден");
+    };
+    // Acting as the user must not grant permissions the caller does not have,
+    // so e.g. only principals can act as principals
+    let target_permissions = roles::of_user(&state.db, role, user_id).await?;
+    if !target_permissions.iter().all(|p| permissions.contains(p)) {
+        fail!(!FORBIDDEN, "Недостаточно прав");
+    }
+
+    let session_id = random_token(32);
+    let expires_at = OffsetDateTime::now_utc() + IMPERSONATION_TTL;
+
+    let mut tx = state.db.begin().await?;
+    sqlx::query(
+        "
+            INSERT INTO Sessions(
+                id, role, user_id, expires_at, user_agent, ip, impersonator_id, school_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ",
+    )
+    .bind(&session_id)
+    .bind(role)
+    .bind(user_id)
+    .bind(expires_at)
+    .bind(user_agent.map(|TypedHeader(ua)| ua.to_string()))
+    .bind(ip.to_string())
+    .bind(claims.user_id)
+    .bind(school_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let impersonation = Impersonation {
+        target: Participant { role, user_id },
+        session_id: session_id.clone(),
+        expires_at,
+    };
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::Impersonation,
+        None,
+        None,
+        Some(&impersonation),
+    )
+    .await?;
+    tx.commit().await?;
+
+    tracing::info!(
+        "Employee #{} impersonates {role:?} #{user_id}",
+        claims.user_id
+    );
+
+    let claims = Claims {
+        expires_at,
+        role,
+        user_id,
+        session_id,
+        school_id: Some(school_id),
+        impersonator_id: Some(claims.user_id),
+        service_account_id: None,
+    };
+    let token = KEYS.get().unwrap().encode(&claims)?;
+    let access = Cookie::build(("token", token))
+        .expires(expires_at)
+        .secure(true)
+        .http_only(true)
+        .path("/");
+
+    Ok(jar.add(access))
+}
+
+/// Ends the impersonation. Call `/auth/refresh` afterwards to continue as yourself
+#[utoipa::path(
+    delete,
+    path = "/auth/impersonate",
+    tag = "Impersonation",
+    responses((status = 200), (status = 400))
+)]
+async fn stop(State(state): RouteState, claims: Claims, jar: CookieJar) -> RouteResult<CookieJar> {
+    if claims.impersonator_id.is_none() {
+        fail!(
+            !BAD_REQUEST,
+            "Вход от имени другого пользователя не выполнен"
+        );
+    }
+
+    sqlx::query("UPDATE Sessions SET revoked_at = now() WHERE id = $1")
+        .bind(&claims.session_id)
+        .execute(&state.db)
+        .await?;
+
+    Ok(jar.remove(Cookie::build("token").path("/")))
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(paths(start, stop), components(schemas(Participant)))]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", post(start).delete(stop))
+}
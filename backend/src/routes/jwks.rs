@@ -0,0 +1,28 @@
+use crate::{AppState, KEYS};
+use axum::{routing::*, Json};
+use jsonwebtoken::jwk::JwkSet;
+use utoipa::OpenApi;
+
+/// Public keys for verification of access tokens by other services, in the JWKS format.
+/// Symmetric keys are never published
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    tag = "Authentication",
+    responses((status = 200, description = "JSON Web Key Set"))
+)]
+async fn jwks() -> Json<JwkSet> {
+    Json(KEYS.get().unwrap().jwks())
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(paths(jwks))]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/jwks.json", get(jwks))
+}
@@ -0,0 +1,338 @@
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    pagination::{FailedLoginPage, Order, Page, Paged, Pagination},
+    roles, Json, Query, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Participant, Permission, SecurityPolicy},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, PgExecutor, PgPool, Type};
+use std::net::IpAddr;
+use time::{Duration, OffsetDateTime};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+/// Failures older than this are forgotten
+const FAILURE_WINDOW: Duration = Duration::DAY;
+const MAX_LOCKOUT: Duration = Duration::DAY;
+/// Phone numbers of accounts are never longer, the rest is not worth storing
+const MAX_PHONE_LENGTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+enum ThrottleScope {
+    /// Keyed by the phone number used to log in
+    Account,
+    /// Keyed by the client address
+    Ip,
+}
+
+fn truncate_phone(phone: &str) -> String {
+    phone.chars().take(MAX_PHONE_LENGTH).collect()
+}
+
+/// Rejects the login attempt if the account or the address is locked
+pub(super) async fn check(
+    db: &PgPool,
+    phone: &str,
+    ip: IpAddr,
+    user_agent: Option<&str>,
+) -> RouteResult {
+    let phone = truncate_phone(phone);
+
+    let locked_until = sqlx::query_scalar::<_, Option<OffsetDateTime>>(
+        "
+            SELECT max(locked_until) FROM LoginThrottles
+            WHERE
+                ((scope = 'account' AND subject = $1) OR (scope = 'ip' AND subject = $2)) AND
+                locked_until > now()
+        ",
+    )
+    .bind(&phone)
+    .bind(ip.to_string())
+    .fetch_one(db)
+    .await?;
+    let Some(locked_until) = locked_until else {
+        return Ok(());
+    };
+
+    sqlx::query(
+        "INSERT INTO FailedLogins(phone, ip, user_agent, locked) VALUES ($1, $2, $3, true)",
+    )
+    .bind(&phone)
+    .bind(ip.to_string())
+    .bind(user_agent)
+    .execute(db)
+    .await?;
+
+    let seconds = (locked_until - OffsetDateTime::now_utc())
+        .whole_seconds()
+        .max(1);
+    fail!(
+        !TOO_MANY_REQUESTS,
+        format!("Слишком много попыток входа, повторите через {seconds} с")
+    );
+}
+
+/// Counts a failed login against the account and the address, locking them once they exceed the
+/// limits of the policy. Every further failure doubles the lockout
+pub(super) async fn record_failure(
+    db: &PgPool,
+    phone: &str,
+    ip: IpAddr,
+    user_agent: Option<&str>,
+) -> RouteResult {
+    let phone = truncate_phone(phone);
+    let policy = super::policy::load(db).await?;
+
+    let mut tx = db.begin().await?;
+    sqlx::query(
+        "INSERT INTO FailedLogins(phone, ip, user_agent, locked) VALUES ($1, $2, $3, false)",
+    )
+    .bind(&phone)
+    .bind(ip.to_string())
+    .bind(user_agent)
+    .execute(&mut *tx)
+    .await?;
+
+    for (scope, subject) in [
+        (ThrottleScope::Account, phone.clone()),
+        (ThrottleScope::Ip, ip.to_string()),
+    ] {
+        let failures = sqlx::query_scalar::<_, i32>(
+            "
+                INSERT INTO LoginThrottles(scope, subject, failures) VALUES ($1, $2, 1)
+                ON CONFLICT (scope, subject) DO UPDATE SET
+                    failures = CASE
+                        WHEN LoginThrottles.last_failure_at < $3 THEN 1
+                        ELSE LoginThrottles.failures + 1
+                    END,
+                    last_failure_at = now()
+                RETURNING failures
+            ",
+        )
+        .bind(scope)
+        .bind(&subject)
+        .bind(OffsetDateTime::now_utc() - FAILURE_WINDOW)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let Some(lockout) = lockout(&policy, scope, failures) else {
+            continue;
+        };
+        tracing::warn!("Locking {scope:?} {subject} after {failures} failed logins for {lockout}");
+
+        sqlx::query(
+            "UPDATE LoginThrottles SET locked_until = $3 WHERE scope = $1 AND subject = $2",
+        )
+        .bind(scope)
+        .bind(&subject)
+        .bind(OffsetDateTime::now_utc() + lockout)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Duration of the lockout after the number of consecutive failures, if any
+fn lockout(policy: &SecurityPolicy, scope: ThrottleScope, failures: i32) -> Option<Duration> {
+    let max_failures = match scope {
+        ThrottleScope::Account => policy.login_max_failures,
+        ThrottleScope::Ip => policy.login_max_failures_per_ip,
+    };
+    if failures < max_failures {
+        return None;
+    }
+
+    let exponent = (failures - max_failures).min(20) as u32;
+    let lockout = Duration::seconds(policy.login_lockout_seconds.saturating_mul(1 << exponent));
+
+    Some(lockout.min(MAX_LOCKOUT))
+}
+
+/// Forgets failed logins into the account after a successful one
+pub(super) async fn reset<'e>(db: impl PgExecutor<'e>, phone: &str) -> RouteResult {
+    sqlx::query("DELETE FROM LoginThrottles WHERE scope = 'account' AND subject = $1")
+        .bind(phone)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+struct Lockout {
+    scope: ThrottleScope,
+    /// Phone number or address
+    subject: String,
+    failures: i32,
+    #[serde(with = "time::serde::rfc3339")]
+    last_failure_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    locked_until: OffsetDateTime,
+}
+
+/// Fetches accounts and addresses which are currently locked. Lockouts are not tied to a school,
+/// so only district administrators can see them
+#[utoipa::path(
+    get,
+    path = "/auth/lockouts",
+    tag = "Login lockouts",
+    responses((status = 200, body = Vec<Lockout>), (status = 403))
+)]
+async fn fetch(State(state): RouteState, claims: Claims) -> RouteResult<Json<Vec<Lockout>>> {
+    roles::ensure_admin(&claims)?;
+
+    let lockouts = sqlx::query_as::<_, Lockout>(
+        "
+            SELECT * FROM LoginThrottles WHERE locked_until > now()
+            ORDER BY locked_until DESC
+        ",
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok(Json(lockouts))
+}
+
+/// Unlocks the account and forgets its failed logins
+#[utoipa::path(
+    post,
+    path = "/auth/lockouts/unlock",
+    tag = "Login lockouts",
+    request_body = Participant,
+    responses((status = 200), (status = 403), (status = 404))
+)]
+async fn unlock(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<Participant>,
+) -> RouteResult {
+    let Participant { role, user_id } = data;
+
+    roles::ensure(&state.db, &claims, Permission::SecurityManage).await?;
+
+    // District administrators unlock users of every school
+    let Some(phone) = sqlx::query_scalar::<_, String>(
+        "
+            SELECT phone FROM Employees
+            WHERE id = $2 AND role = $1 AND coalesce(school_id = $3, $3 IS NULL)
+            UNION ALL
+            SELECT phone FROM Students
+            WHERE id = $2 AND $1 = 'student' AND coalesce(school_id = $3, true)
+            UNION ALL
+            SELECT phone FROM Parents
+            WHERE id = $2 AND $1 = 'parent' AND coalesce(school_id = $3, true)
+        ",
+    )
+    .bind(role)
+    .bind(user_id)
+    .bind(claims.school_id)
+    .fetch_optional(&state.db)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Пользователь не найден");
+    };
+
+    let mut tx = state.db.begin().await?;
+    reset(&mut *tx, &phone).await?;
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Delete,
+        AuditEntity::Lockout,
+        Some(user_id),
+        Some(&data),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+struct FetchFailedLogins {
+    phone: Option<String>,
+    ip: Option<String>,
+    count: Option<i64>,
+    cursor: Option<String>,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct FailedLogin {
+    id: i64,
+    phone: String,
+    ip: String,
+    user_agent: Option<String>,
+    /// The attempt was rejected because of a lockout
+    locked: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+}
+
+/// Fetches failed logins, newest first, optionally filtered by the phone or the address.
+/// Only available to district administrators
+#[utoipa::path(
+    get,
+    path = "/auth/lockouts/failed-logins",
+    tag = "Login lockouts",
+    params(FetchFailedLogins),
+    responses((status = 200, body = FailedLoginPage), (status = 403))
+)]
+async fn failed_logins(
+    State(state): RouteState,
+    claims: Claims,
+    Query(query): Query<FetchFailedLogins>,
+) -> RouteResult<Json<Page<FailedLogin>>> {
+    let FetchFailedLogins {
+        phone,
+        ip,
+        count,
+        cursor,
+    } = query;
+
+    roles::ensure_admin(&claims)?;
+    let pagination = Pagination::new(count, cursor, Order::ID.desc(true))?;
+
+    let query = pagination.query(
+        "
+            SELECT * FROM FailedLogins
+            WHERE ($4::VARCHAR IS NULL OR phone = $4) AND ($5::VARCHAR IS NULL OR ip = $5)
+        ",
+    );
+    let failed = pagination
+        .bind(sqlx::query_as::<_, Paged<FailedLogin>>(&query))
+        .bind(phone)
+        .bind(ip)
+        .fetch_all(&state.db)
+        .await?;
+
+    Ok(Json(pagination.finish(failed)))
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(fetch, unlock, failed_logins),
+        components(schemas(Lockout, FailedLogin, FailedLoginPage, ThrottleScope, Participant))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(fetch))
+        .route("/unlock", post(unlock))
+        .route("/failed-logins", get(failed_logins))
+}
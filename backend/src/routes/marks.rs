@@ -1,15 +1,37 @@
-use super::{Json, Query, RouteResult, RouteState};
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    pagination::{MarkPage, Order, Page, Paged, Pagination},
+    roles, Json, Query, RouteResult, RouteState,
+};
 use crate::{
+    events::{MarkEvent, MarkEventKind},
     fail,
+    jobs::{self, Job},
     middleware::Claims,
-    models::{Mark, Role},
+    models::{Mark, Permission, Role},
     AppState,
 };
-use axum::{extract::State, routing::*};
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::*,
+};
+use futures_util::{future, stream, Stream, StreamExt};
 use serde::Deserialize;
+use std::{convert::Infallible, sync::Arc};
 use time::OffsetDateTime;
+use tokio::sync::broadcast::error::RecvError;
 use utoipa::{IntoParams, OpenApi, ToSchema};
 
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum MarkSort {
+    #[default]
+    Id,
+    Time,
+    Mark,
+}
+
 #[derive(Deserialize, IntoParams)]
 #[serde(deny_unknown_fields)]
 struct Fetch {
@@ -19,14 +41,24 @@ struct Fetch {
     teachers_ids: Vec<i32>,
     #[serde(default)]
     subject_ids: Vec<i32>,
+    #[serde(default)]
+    exclude_student_ids: Vec<i32>,
+    #[serde(default)]
+    exclude_teacher_ids: Vec<i32>,
+    #[serde(default)]
+    exclude_subject_ids: Vec<i32>,
     least: Option<i16>,
     most: Option<i16>,
     #[serde(with = "time::serde::rfc3339::option", default)]
     after: Option<OffsetDateTime>,
     #[serde(with = "time::serde::rfc3339::option", default)]
     before: Option<OffsetDateTime>,
+    #[serde(default)]
+    sort: MarkSort,
+    #[serde(default)]
+    desc: bool,
     count: Option<i64>,
-    offset: Option<i64>,
+    cursor: Option<String>,
 }
 
 /// Fetch student marks
@@ -35,52 +67,70 @@ struct Fetch {
     path = "/marks",
     tag = "Marks management",
     params(Fetch),
-    responses((status = 200, body = Vec<Mark>))
+    responses((status = 200, body = MarkPage))
 )]
 async fn fetch(
     State(state): RouteState,
+    claims: Claims,
     Query(query): Query<Fetch>,
-) -> RouteResult<Json<Vec<Mark>>> {
+) -> RouteResult<Json<Page<Mark>>> {
     let Fetch {
         student_ids,
         teachers_ids,
         subject_ids,
+        exclude_student_ids,
+        exclude_teacher_ids,
+        exclude_subject_ids,
         least,
         most,
         after,
         before,
+        sort,
+        desc,
         count,
-        offset,
+        cursor,
     } = query;
 
-    let count = count.map(|s| s.clamp(0, 500));
-    let offset = offset.unwrap_or(0).clamp(0, 10000);
+    let school_id = claims.school()?;
+    let order = match sort {
+        MarkSort::Id => Order::ID,
+        MarkSort::Time => Order::by("time", "TIMESTAMPTZ"),
+        MarkSort::Mark => Order::by("mark", "SMALLINT"),
+    };
 
-    let marks = sqlx::query_as::<_, Mark>(
+    let pagination = Pagination::new(count, cursor, order.desc(desc))?;
+    let query = pagination.query(
         r#"
             SELECT * FROM Marks
             WHERE
-                (student_id = any($3) OR cardinality($3) = 0) AND
-                (teacher_id = any($4) OR cardinality($4) = 0) AND
-                (subject_id = any($5) OR cardinality($5) = 0) AND
-                mark BETWEEN coalesce($6, 2) AND coalesce($7, 5) AND
-                time BETWEEN coalesce($8, '-infinity'::timestamptz) AND coalesce($9, '+infinity'::timestamptz)
-            LIMIT $1 OFFSET $2
+                school_id = $11 AND
+                (student_id = any($4) OR cardinality($4) = 0) AND
+                (teacher_id = any($5) OR cardinality($5) = 0) AND
+                (subject_id = any($6) OR cardinality($6) = 0) AND
+                mark BETWEEN coalesce($7, 2) AND coalesce($8, 5) AND
+                time BETWEEN coalesce($9, '-infinity'::timestamptz) AND coalesce($10, '+infinity'::timestamptz) AND
+                NOT (student_id = any($12)) AND
+                NOT (teacher_id = any($13)) AND
+                NOT (subject_id = any($14))
         "#,
-    )
-    .bind(count)
-    .bind(offset)
-    .bind(student_ids)
-    .bind(teachers_ids)
-    .bind(subject_ids)
-    .bind(least)
-    .bind(most)
-    .bind(after)
-    .bind(before)
-    .fetch_all(&state.db)
-    .await?;
+    );
+    let marks = pagination
+        .bind(sqlx::query_as::<_, Paged<Mark>>(&query))
+        .bind(student_ids)
+        .bind(teachers_ids)
+        .bind(subject_ids)
+        .bind(least)
+        .bind(most)
+        .bind(after)
+        .bind(before)
+        .bind(school_id)
+        .bind(exclude_student_ids)
+        .bind(exclude_teacher_ids)
+        .bind(exclude_subject_ids)
+        .fetch_all(&state.db)
+        .await?;
 
-    Ok(Json(marks))
+    Ok(Json(pagination.finish(marks)))
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -93,7 +143,7 @@ struct CreateMarkRequest {
 }
 
 /// Create a new mark
-/// If authenticated as a principal, teacher_id is required
+/// Employees other than teachers need the `marks.write.all` permission and teacher_id
 #[utoipa::path(
     post,
     path = "/marks",
@@ -117,36 +167,183 @@ async fn create(
         fail!(!BAD_REQUEST, "Оценка должна быть межды 1 и 5")
     }
 
+    let school_id = claims.school()?;
     let teacher_id = match claims.role {
-        Role::Teacher => claims.employee_id,
-        Role::Principal => teacher_id.ok_or(fail!(BAD_REQUEST, "teacher_id is required"))?,
+        Role::Teacher => claims.user_id,
+        Role::Principal | Role::Staff | Role::Admin => {
+            roles::ensure(&state.db, &claims, Permission::MarksWriteAll).await?;
+            teacher_id.ok_or(fail!(BAD_REQUEST, "teacher_id is required"))?
+        }
         Role::Student => fail!(!FORBIDDEN, "Ученик не может добавлять оценки"),
+        Role::Parent => fail!(!FORBIDDEN, "Родитель не может добавлять оценки"),
     };
 
-    sqlx::query(
+    // Archived rows are only kept for the history
+    let archived = sqlx::query_scalar::<_, bool>(
+        "
+            SELECT EXISTS(
+                SELECT 1 FROM Students WHERE id = $1 AND archived_at IS NOT NULL
+                UNION ALL
+                SELECT 1 FROM Employees WHERE id = $2 AND archived_at IS NOT NULL
+                UNION ALL
+                SELECT 1 FROM Subjects WHERE id = $3 AND archived_at IS NOT NULL
+            )
+        ",
+    )
+    .bind(student_id)
+    .bind(teacher_id)
+    .bind(subject_id)
+    .fetch_one(&state.db)
+    .await?;
+    if archived {
+        fail!(
+            !BAD_REQUEST,
+            "Ученик, учитель или предмет находится в архиве"
+        );
+    }
+
+    let mut tx = state.db.begin().await?;
+    let result = sqlx::query_as::<_, Mark>(
         "
-            INSERT INTO Marks(teacher_id, student_id, subject_id, mark)
-            VALUES($1, $2, $3, $4)
+            INSERT INTO Marks(teacher_id, student_id, subject_id, mark, school_id)
+            VALUES($1, $2, $3, $4, $5)
+            RETURNING *
         ",
     )
     .bind(teacher_id)
     .bind(student_id)
     .bind(subject_id)
     .bind(mark)
-    .execute(&state.db)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
+    .await;
+
+    let mark = match result {
+        Ok(mark) => mark,
+        Err(err)
+            if matches!(err.as_database_error(), Some(err) if
+                err.is_foreign_key_violation() &&
+                err.constraint() == Some("marks_student_id_fkey")
+            ) =>
+            fail!(!BAD_REQUEST, "Ученика с таким ИД не существует"),
+        Err(err)
+            if matches!(err.as_database_error(), Some(err) if
+                err.is_foreign_key_violation() &&
+                err.constraint() == Some("marks_subject_id_fkey")
+            ) =>
+            fail!(!BAD_REQUEST, "Предмета с таким ИД не существует"),
+        Err(err)
+            if matches!(err.as_database_error(), Some(err) if
+                err.is_foreign_key_violation() &&
+                err.constraint() == Some("marks_teacher_id_fkey")
+            ) =>
+            fail!(!BAD_REQUEST, "Учителя с таким ИД не существует"),
+        Err(err) => return Err(err.into()),
+    };
+
+    jobs::enqueue(&mut *tx, Job::NewMark { mark_id: mark.id }, None).await?;
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::Mark,
+        Some(mark.id),
+        None,
+        Some(&mark),
+    )
     .await?;
+    tx.commit().await?;
+    state.marks.publish(MarkEventKind::Created, mark);
 
     Ok(())
 }
 
+/// Marks the subscriber is interested in
+enum Scope {
+    School(i32),
+    Teacher(i32),
+    Students(Vec<i32>),
+}
+
+impl Scope {
+    fn matches(&self, event: &MarkEvent) -> bool {
+        match self {
+            Scope::School(id) => event.mark.school_id == *id,
+            Scope::Teacher(id) => event.mark.teacher_id == *id,
+            Scope::Students(ids) => ids.contains(&event.mark.student_id),
+        }
+    }
+}
+
+/// Subscribe to mark changes as Server-Sent Events.
+/// Students receive their own marks, parents receive marks of their children,
+/// teachers receive marks they have given and employees with `marks.write.all` receive all marks
+/// of the school
+#[utoipa::path(
+    get,
+    path = "/marks/events",
+    tag = "Marks management",
+    responses((status = 200, content_type = "text/event-stream"))
+)]
+async fn events(
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let school_id = claims.school()?;
+    let scope = match claims.role {
+        Role::Principal | Role::Staff | Role::Admin
+            if roles::has(&state.db, &claims, Permission::MarksWriteAll).await? =>
+            Scope::School(school_id),
+        Role::Principal | Role::Staff | Role::Admin => Scope::Students(vec![]),
+        Role::Teacher => Scope::Teacher(claims.user_id),
+        Role::Student => Scope::Students(vec![claims.user_id]),
+        Role::Parent => {
+            let ids = sqlx::query_scalar::<_, i32>(
+                "SELECT student_id FROM ParentStudent WHERE parent_id = $1",
+            )
+            .bind(claims.user_id)
+            .fetch_all(&state.db)
+            .await?;
+
+            Scope::Students(ids)
+        }
+    };
+
+    let mut shutdown = state.shutdown.clone();
+    let stream = stream::unfold(state.marks.subscribe(), |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((event, rx)),
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Mark subscriber lagged behind by {skipped} events")
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+    .filter(move |event: &Arc<MarkEvent>| future::ready(scope.matches(event)))
+    .map(|event| {
+        let event = Event::default().event("mark").json_data(&*event);
+        Ok(event.expect("Mark event is always serializable"))
+    })
+    .take_until(async move { _ = shutdown.changed().await });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
 pub fn openapi() -> utoipa::openapi::OpenApi {
     #[derive(OpenApi)]
-    #[openapi(paths(fetch, create), components(schemas(Mark, CreateMarkRequest)))]
+    #[openapi(
+        paths(fetch, create, events),
+        components(schemas(Mark, MarkPage, MarkSort, CreateMarkRequest))
+    )]
     struct Api;
 
     Api::openapi()
 }
 
 pub fn router() -> Router<AppState> {
-    Router::new().route("/", get(fetch).post(create))
+    Router::new()
+        .route("/", get(fetch).post(create))
+        .route("/events", get(events))
 }
@@ -0,0 +1,116 @@
+use super::{Json, RouteResult, RouteState};
+use crate::{
+    middleware::Claims,
+    notifications::{NotificationChannel, NotificationEvent},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+use utoipa::{OpenApi, ToSchema};
+
+#[derive(Serialize, Deserialize, FromRow, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct Preference {
+    event: NotificationEvent,
+    channel: NotificationChannel,
+    enabled: bool,
+}
+
+/// Fetches notification preferences of the current user for every event and channel
+#[utoipa::path(
+    get,
+    path = "/notifications/preferences",
+    tag = "Notifications",
+    responses((status = 200, body = Vec<Preference>))
+)]
+async fn preferences(
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Json<Vec<Preference>>> {
+    let stored = sqlx::query_as::<_, Preference>(
+        "
+            SELECT event, channel, enabled FROM NotificationPreferences
+            WHERE role = $1 AND user_id = $2
+        ",
+    )
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut preferences = vec![];
+    for event in NotificationEvent::ALL {
+        for channel in NotificationChannel::ALL {
+            let enabled = stored
+                .iter()
+                .find(|p| p.event == event && p.channel == channel)
+                .map(|p| p.enabled)
+                .unwrap_or(channel.enabled_by_default());
+
+            preferences.push(Preference {
+                event,
+                channel,
+                enabled,
+            });
+        }
+    }
+
+    Ok(Json(preferences))
+}
+
+/// Updates notification preferences of the current user.
+/// Only the listed event and channel pairs are changed
+#[utoipa::path(
+    put,
+    path = "/notifications/preferences",
+    tag = "Notifications",
+    request_body = Vec<Preference>,
+    responses((status = 200))
+)]
+async fn update(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<Vec<Preference>>,
+) -> RouteResult {
+    let mut tx = state.db.begin().await?;
+    for Preference {
+        event,
+        channel,
+        enabled,
+    } in data
+    {
+        sqlx::query(
+            "
+                INSERT INTO NotificationPreferences(role, user_id, event, channel, enabled)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (role, user_id, event, channel) DO UPDATE SET enabled = $5
+            ",
+        )
+        .bind(claims.role)
+        .bind(claims.user_id)
+        .bind(event)
+        .bind(channel)
+        .bind(enabled)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(preferences, update),
+        components(schemas(Preference, NotificationEvent, NotificationChannel))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/preferences", get(preferences).put(update))
+}
@@ -0,0 +1,248 @@
+use super::{audit::AuditEntry, lockouts::FailedLogin, RouteResult};
+use crate::{
+    fail,
+    models::{
+        Announcement, Class, Conversation, Employee, Mark, Message, Room, Student, Subject, Teacher,
+    },
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    postgres::{PgArguments, PgRow},
+    query::QueryAs,
+    FromRow, Postgres, Row, ValueRef,
+};
+use utoipa::ToSchema;
+
+/// Page size used when `count` is not set
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 100;
+
+/// Page of a list endpoint
+#[derive(Serialize, ToSchema)]
+#[aliases(
+    SubjectPage = Page<Subject>,
+    ClassPage = Page<Class>,
+    RoomPage = Page<Room>,
+    StudentPage = Page<Student>,
+    TeacherPage = Page<Teacher>,
+    EmployeePage = Page<Employee>,
+    MarkPage = Page<Mark>,
+    AnnouncementPage = Page<Announcement>,
+    AuditEntryPage = Page<AuditEntry>,
+    ConversationPage = Page<Conversation>,
+    MessagePage = Page<Message>,
+    FailedLoginPage = Page<FailedLogin>
+)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of rows matching the filters across all pages
+    pub total: i64,
+    /// Pass as `cursor` to fetch the following page, not set on the last one
+    pub next: Option<String>,
+    /// Pass as `cursor` to fetch the preceding page, not set on the first one
+    pub prev: Option<String>,
+}
+
+/// Position in the list, encoded into the opaque `cursor` parameter
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    /// Whether the page follows the position or precedes it
+    #[serde(rename = "f")]
+    forward: bool,
+    #[serde(rename = "i")]
+    id: i64,
+    /// Value of the sort column as text
+    #[serde(rename = "k", default)]
+    key: Option<String>,
+    /// Sort column the position was taken in, the cursor is only valid in the same order
+    #[serde(rename = "s", default)]
+    sort: Option<String>,
+    #[serde(rename = "d", default)]
+    desc: bool,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+    }
+
+    fn decode(cursor: &str) -> RouteResult<Self> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or(fail!(BAD_REQUEST, "Недействительный курсор", "cursor"))
+    }
+}
+
+/// Order of a list, ties are broken by `id` in the same direction
+#[derive(Clone, Copy)]
+pub(super) struct Order {
+    /// Sort column and its SQL type, the rows are ordered by `id` alone if not set
+    key: Option<(&'static str, &'static str)>,
+    desc: bool,
+}
+
+impl Order {
+    /// Oldest rows first
+    pub const ID: Self = Self {
+        key: None,
+        desc: false,
+    };
+
+    pub const fn by(column: &'static str, ty: &'static str) -> Self {
+        Self {
+            key: Some((column, ty)),
+            desc: false,
+        }
+    }
+
+    pub const fn desc(self, desc: bool) -> Self {
+        Self { desc, ..self }
+    }
+}
+
+/// Row of the page query. The query always returns at least one row to carry the total, without
+/// an item if nothing matches
+pub(super) struct Paged<T> {
+    total: i64,
+    item: Option<(T, i64, Option<String>)>,
+}
+
+impl<'r, T: FromRow<'r, PgRow>> FromRow<'r, PgRow> for Paged<T> {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        let item = match row.try_get_raw("page_id")?.is_null() {
+            true => None,
+            false => Some((
+                T::from_row(row)?,
+                row.try_get("page_id")?,
+                row.try_get("page_key")?,
+            )),
+        };
+
+        Ok(Self {
+            total: row.try_get("total")?,
+            item,
+        })
+    }
+}
+
+pub(super) struct Pagination {
+    limit: i64,
+    order: Order,
+    cursor: Option<Cursor>,
+}
+
+impl Pagination {
+    /// Fails if the cursor was issued for a different order of the list
+    pub fn new(count: Option<i64>, cursor: Option<String>, order: Order) -> RouteResult<Self> {
+        let cursor = cursor.as_deref().map(Cursor::decode).transpose()?;
+        let sort = order.key.map(|(column, _)| column);
+        if cursor
+            .as_ref()
+            .is_some_and(|c| c.sort.as_deref() != sort || c.desc != order.desc)
+        {
+            fail!(!BAD_REQUEST, "Недействительный курсор", "cursor");
+        }
+
+        Ok(Self {
+            limit: count.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+            order,
+            cursor,
+        })
+    }
+
+    fn forward(&self) -> bool {
+        self.cursor.as_ref().is_none_or(|c| c.forward)
+    }
+
+    /// Wraps the query selecting the filtered rows into the one selecting the page. `$1`, `$2`
+    /// and `$3` are bound by `bind`, so parameters of the filters start at `$4`
+    pub fn query(&self, filtered: &str) -> String {
+        let order = self.order;
+        // Preceding pages are selected in the reverse order and flipped back by `finish`
+        let (comparison, direction) = match order.desc == self.forward() {
+            true => ("<", "DESC"),
+            false => (">", "ASC"),
+        };
+        let (key, position, order_by, page_key) = match order.key {
+            Some((column, ty)) => (
+                format!("(F.{column}, F.id)"),
+                format!("(K.key::{ty}, K.id)"),
+                format!("F.{column} {direction}, F.id {direction}"),
+                format!("F.{column}::TEXT"),
+            ),
+            None => (
+                "F.id".to_owned(),
+                "K.id".to_owned(),
+                format!("F.id {direction}"),
+                "NULL".to_owned(),
+            ),
+        };
+
+        format!(
+            "
+                WITH
+                    Filtered AS ({filtered}),
+                    Keyset AS (SELECT $2::BIGINT AS id, $3::TEXT AS key)
+                SELECT * FROM (SELECT count(*) AS total FROM Filtered) T
+                LEFT JOIN LATERAL (
+                    SELECT F.*, F.id::BIGINT AS page_id, {page_key} AS page_key
+                    FROM Filtered F, Keyset K
+                    WHERE K.id IS NULL OR {key} {comparison} {position}
+                    ORDER BY {order_by}
+                    LIMIT $1
+                ) P ON true
+            "
+        )
+    }
+
+    pub fn bind<'q, T>(
+        &self,
+        query: QueryAs<'q, Postgres, T, PgArguments>,
+    ) -> QueryAs<'q, Postgres, T, PgArguments> {
+        // One more row tells whether there is a page after this one
+        query
+            .bind(self.limit + 1)
+            .bind(self.cursor.as_ref().map(|c| c.id))
+            .bind(self.cursor.as_ref().and_then(|c| c.key.clone()))
+    }
+
+    pub fn finish<T>(self, rows: Vec<Paged<T>>) -> Page<T> {
+        let total = rows.first().map_or(0, |r| r.total);
+        let mut items = rows.into_iter().filter_map(|r| r.item).collect::<Vec<_>>();
+
+        let more = items.len() as i64 > self.limit;
+        items.truncate(self.limit as usize);
+        let forward = self.forward();
+        if !forward {
+            items.reverse();
+        }
+
+        // The page the cursor came from is always there to go back to
+        let (has_next, has_prev) = match forward {
+            true => (more, self.cursor.is_some()),
+            false => (self.cursor.is_some(), more),
+        };
+        let cursor = |(_, id, key): &(T, i64, Option<String>), forward| {
+            Cursor {
+                forward,
+                id: *id,
+                key: key.clone(),
+                sort: self.order.key.map(|(column, _)| column.to_owned()),
+                desc: self.order.desc,
+            }
+            .encode()
+        };
+        let next = items.last().filter(|_| has_next).map(|i| cursor(i, true));
+        let prev = items.first().filter(|_| has_prev).map(|i| cursor(i, false));
+
+        Page {
+            items: items.into_iter().map(|(item, ..)| item).collect(),
+            total,
+            next,
+            prev,
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    auth::hash_token,
+    roles, Json, RouteResult, RouteState, Secret,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Participant, Permission, Role, SecurityPolicy},
+    notifications::{Notification, NotificationChannel, NotificationEvent},
+    AppState,
+};
+use argon2::{password_hash::SaltString, Argon2, PasswordHasher, PasswordVerifier};
+use axum::{extract::State, routing::*};
+use rand::{rngs::OsRng, Rng};
+use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
+use utoipa::{OpenApi, ToSchema};
+
+/// Table the users with the role are stored in
+pub(super) fn table(role: Role) -> &'static str {
+    match role {
+        Role::Teacher | Role::Principal | Role::Staff | Role::Admin => "Employees",
+        Role::Student => "Students",
+        Role::Parent => "Parents",
+    }
+}
+
+/// Checks the password against the strength requirements of the policy
+fn validate(policy: &SecurityPolicy, password: &str) -> RouteResult {
+    if password.chars().count() < policy.password_min_length {
+        fail!(
+            !BAD_REQUEST,
+            format!(
+                "Пароль должен содержать не менее {} символов",
+                policy.password_min_length
+            ),
+            "password"
+        );
+    }
+    if policy.password_require_digit && !password.chars().any(|c| c.is_numeric()) {
+        fail!(!BAD_REQUEST, "Пароль должен содержать цифры", "password");
+    }
+    if policy.password_require_mixed_case
+        && !(password.chars().any(|c| c.is_lowercase())
+            && password.chars().any(|c| c.is_uppercase()))
+    {
+        fail!(
+            !BAD_REQUEST,
+            "Пароль должен содержать строчные и заглавные буквы",
+            "password"
+        );
+    }
+
+    Ok(())
+}
+
+/// Validates the password against the security policy and hashes it
+pub async fn hash<'e>(db: impl PgExecutor<'e>, password: &str) -> RouteResult<String> {
+    validate(&super::policy::load(db).await?, password)?;
+
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &SaltString::generate(OsRng))
+        .unwrap()
+        .to_string();
+
+    Ok(hash)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct ChangePasswordRequest {
+    current_password: String,
+    new_password: String,
+}
+
+/// Changes password of the current user. Other sessions of the user are revoked
+#[utoipa::path(
+    post,
+    path = "/auth/password",
+    tag = "Passwords",
+    request_body = ChangePasswordRequest,
+    responses((status = 200), (status = 400))
+)]
+async fn change(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<ChangePasswordRequest>,
+) -> RouteResult {
+    let ChangePasswordRequest {
+        current_password,
+        new_password,
+    } = data;
+
+    if claims.impersonator_id.is_some() {
+        fail!(
+            !FORBIDDEN,
+            "Недоступно при входе от имени другого пользователя"
+        );
+    }
+
+    let password_hash = sqlx::query_scalar::<_, String>(&format!(
+        "SELECT password_hash FROM {} WHERE id = $1",
+        table(claims.role)
+    ))
+    .bind(claims.user_id)
+    .fetch_one(&state.db)
+    .await?;
+
+    Argon2::default()
+        .verify_password(
+            current_password.as_bytes(),
+            &password_hash.as_str().try_into().unwrap(),
+        )
+        .map_err(|_| fail!(BAD_REQUEST, "Неправильный пароль", "current_password"))?;
+    if current_password == new_password {
+        fail!(
+            !BAD_REQUEST,
+            "Новый пароль должен отличаться от текущего",
+            "new_password"
+        );
+    }
+
+    let password_hash = hash(&state.db, &new_password).await?;
+
+    let mut tx = state.db.begin().await?;
+    sqlx::query(&format!(
+        "UPDATE {} SET password_hash = $2, password_change_required = false WHERE id = $1",
+        table(claims.role)
+    ))
+    .bind(claims.user_id)
+    .bind(password_hash)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "
+            UPDATE Sessions
+            SET
+                password_change_pending = false,
+                revoked_at = CASE WHEN id = $3 THEN revoked_at ELSE coalesce(revoked_at, now()) END
+            WHERE role = $1 AND user_id = $2
+        ",
+    )
+    .bind(claims.role)
+    .bind(claims.user_id)
+    .bind(&claims.session_id)
+    .execute(&mut *tx)
+    .await?;
+    let user = Participant {
+        role: claims.role,
+        user_id: claims.user_id,
+    };
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Password,
+        Some(claims.user_id),
+        None,
+        Some(&user),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct IssueResetCodeRequest {
+    role: Role,
+    user_id: i32,
+    /// Channel to deliver the code through.
+    /// Without it the code is returned in the response to be handed over in person
+    channel: Option<NotificationChannel>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct IssueResetCodeResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+}
+
+/// Issues a one-time password reset code for the user, valid for an hour.
+/// The previously issued code of the user stops working
+#[utoipa::path(
+    post,
+    path = "/auth/password/reset-codes",
+    tag = "Passwords",
+    request_body = IssueResetCodeRequest,
+    responses((status = 200, body = IssueResetCodeResponse), (status = 403))
+)]
+async fn issue_reset_code(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<IssueResetCodeRequest>,
+) -> RouteResult<Secret<IssueResetCodeResponse>> {
+    let IssueResetCodeRequest {
+        role,
+        user_id,
+        channel,
+    } = data;
+
+    roles::ensure(&state.db, &claims, Permission::PasswordsReset).await?;
+    // Otherwise the code returned in the response would give away the account of a user with
+    // more permissions than the caller, such as a principal
+    if role == Role::Admin && claims.role != Role::Admin {
+        fail!(!FORBIDDEN, "Недостаточно прав");
+    }
+    let target_permissions = roles::of_user(&state.db, role, user_id).await?;
+    roles::ensure_all(&state.db, &claims, &target_permissions).await?;
+
+    // District administrators reset passwords in every school
+    let exists = sqlx::query_scalar::<_, bool>(
+        "
+            SELECT EXISTS(
+                SELECT 1 FROM Employees
+                WHERE id = $2 AND role = $1 AND coalesce(school_id = $3, $3 IS NULL)
+                UNION ALL
+                SELECT 1 FROM Students
+                WHERE id = $2 AND $1 = 'student' AND coalesce(school_id = $3, true)
+                UNION ALL
+                SELECT 1 FROM Parents
+                WHERE id = $2 AND $1 = 'parent' AND coalesce(school_id = $3, true)
+            )
+        ",
+    )
+    .bind(role)
+    .bind(user_id)
+    .bind(claims.school_id)
+    .fetch_one(&state.db)
+    .await?;
+    if !exists {
+        fail!(!NOT_FOUND, "Пользователь не найден");
+    }
+
+    let code = format!("{:08}", OsRng.gen_range(0..100_000_000));
+
+    let mut tx = state.db.begin().await?;
+    sqlx::query(
+        "
+            INSERT INTO PasswordResets(role, user_id, code_hash, expires_at)
+            VALUES ($1, $2, $3, now() + interval '1 hour')
+            ON CONFLICT (role, user_id) DO UPDATE
+            SET code_hash = $3, expires_at = excluded.expires_at, attempts = 0
+        ",
+    )
+    .bind(role)
+    .bind(user_id)
+    .bind(hash_token(&code))
+    .execute(&mut *tx)
+    .await?;
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::PasswordResetCode,
+        Some(user_id),
+        None,
+        Some(&serde_json::json!({ "role": role, "user_id": user_id, "channel": channel })),
+    )
+    .await?;
+
+    let Some(channel) = channel else {
+        tx.commit().await?;
+        return Ok(Secret(IssueResetCodeResponse { code: Some(code) }));
+    };
+
+    let notification = Notification {
+        event: NotificationEvent::PasswordReset,
+        title: "Сброс пароля".into(),
+        body: format!("Код для сброса пароля: {code}. Код действует один час"),
+    };
+    state
+        .notifier
+        .deliver(channel, &Participant { role, user_id }, &notification)
+        .await?;
+    tx.commit().await?;
+
+    Ok(Secret(IssueResetCodeResponse { code: None }))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct ResetPasswordRequest {
+    phone: String,
+    code: String,
+    password: String,
+}
+
+/// Sets a new password using the reset code. All sessions of the user are revoked
+#[utoipa::path(
+    post,
+    path = "/auth/password/reset",
+    tag = "Passwords",
+    request_body = ResetPasswordRequest,
+    responses((status = 200), (status = 400))
+)]
+async fn reset(State(state): RouteState, Json(data): Json<ResetPasswordRequest>) -> RouteResult {
+    let ResetPasswordRequest {
+        phone,
+        code,
+        password,
+    } = data;
+
+    let mut tx = state.db.begin().await?;
+    // Several accounts may share the phone, the code tells which one is reset
+    let codes = sqlx::query_as::<_, (Role, i32, String, Option<i32>)>(
+        "
+            WITH Users AS (
+                SELECT role, id, school_id FROM Employees WHERE phone = $1
+                UNION ALL
+                SELECT 'student'::Role, id, school_id FROM Students WHERE phone = $1
+                UNION ALL
+                SELECT 'parent'::Role, id, school_id FROM Parents WHERE phone = $1
+            )
+            UPDATE PasswordResets R SET attempts = attempts + 1
+            FROM Users U
+            WHERE
+                (R.role, R.user_id) = (U.role, U.id) AND
+                R.expires_at > now() AND
+                R.attempts < 5
+            RETURNING R.role, R.user_id, R.code_hash, U.school_id
+        ",
+    )
+    .bind(phone)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let code_hash = hash_token(code.trim());
+    let Some((role, user_id, _, school_id)) =
+        codes.into_iter().find(|(_, _, hash, _)| *hash == code_hash)
+    else {
+        // Keep the incremented attempts counter
+        tx.commit().await?;
+        fail!(!BAD_REQUEST, "Неправильный или просроченный код", "code");
+    };
+
+    let password_hash = hash(&mut *tx, &password).await?;
+
+    sqlx::query(&format!(
+        "UPDATE {} SET password_hash = $2, password_change_required = false WHERE id = $1",
+        table(role)
+    ))
+    .bind(user_id)
+    .bind(password_hash)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM PasswordResets WHERE role = $1 AND user_id = $2")
+        .bind(role)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "
+            UPDATE Sessions SET revoked_at = now()
+            WHERE role = $1 AND user_id = $2 AND revoked_at IS NULL
+        ",
+    )
+    .bind(role)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+    let user = Participant { role, user_id };
+    audit::record_user(
+        &mut tx,
+        user,
+        school_id,
+        AuditAction::Update,
+        AuditEntity::Password,
+        Some(user_id),
+        Some(&user),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(change, issue_reset_code, reset),
+        components(schemas(
+            ChangePasswordRequest,
+            IssueResetCodeRequest,
+            IssueResetCodeResponse,
+            ResetPasswordRequest
+        ))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", post(change))
+        .route("/reset-codes", post(issue_reset_code))
+        .route("/reset", post(reset))
+}
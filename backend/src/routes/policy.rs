@@ -0,0 +1,226 @@
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    roles, Json, RouteResult, RouteState,
+};
+use crate::{
+    middleware::Claims,
+    models::{Permission, SchoolSecurityPolicy, SecurityPolicy},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use sqlx::{types::Json as SqlJson, PgConnection, PgExecutor};
+use utoipa::OpenApi;
+
+/// Loads the security policy, falling back to defaults if it was never configured
+pub async fn load<'e>(db: impl PgExecutor<'e>) -> RouteResult<SecurityPolicy> {
+    let policy = sqlx::query_scalar::<_, SqlJson<SecurityPolicy>>(
+        "SELECT value FROM Settings WHERE key = 'security_policy'",
+    )
+    .fetch_optional(db)
+    .await?
+    .map(|SqlJson(p)| p)
+    .unwrap_or_default();
+
+    Ok(policy)
+}
+
+/// Whether principals of the school must have two-factor authentication enabled, either by the
+/// district policy or by the school's own. District administrators follow the district policy
+pub async fn requires_2fa<'e>(
+    db: impl PgExecutor<'e>,
+    school_id: Option<i32>,
+) -> RouteResult<bool> {
+    let required = sqlx::query_scalar::<_, bool>(
+        "
+            SELECT
+                coalesce((
+                    SELECT (value->>'require_principal_2fa')::BOOLEAN FROM Settings
+                    WHERE key = 'security_policy'
+                ), false) OR
+                coalesce((SELECT require_principal_2fa FROM Schools WHERE id = $1), false)
+        ",
+    )
+    .bind(school_id)
+    .fetch_one(db)
+    .await?;
+
+    Ok(required)
+}
+
+/// Restricts or releases sessions of principals and district administrators without 2FA after
+/// the requirement changed, otherwise the policy would only apply once the sessions expire.
+/// Without `school_id` sessions in all schools are affected
+async fn apply_2fa_requirement(db: &mut PgConnection, school_id: Option<i32>) -> RouteResult {
+    sqlx::query(
+        "
+            UPDATE Sessions S SET totp_pending =
+                coalesce((
+                    SELECT (value->>'require_principal_2fa')::BOOLEAN FROM Settings
+                    WHERE key = 'security_policy'
+                ), false) OR
+                coalesce(Sc.require_principal_2fa, false)
+            FROM Employees E
+            LEFT JOIN Schools Sc ON Sc.id = E.school_id
+            WHERE
+                E.id = S.user_id AND E.role = S.role AND
+                S.role IN ('principal', 'admin') AND NOT E.totp_enabled AND
+                NOT S.totp_exempt AND S.impersonator_id IS NULL AND
+                S.revoked_at IS NULL AND S.expires_at > now() AND
+                ($1::INTEGER IS NULL OR E.school_id = $1)
+        ",
+    )
+    .bind(school_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Fetches the security policy
+#[utoipa::path(
+    get,
+    path = "/policy",
+    tag = "Security policy",
+    responses((status = 200, body = SecurityPolicy))
+)]
+async fn fetch(State(state): RouteState, claims: Claims) -> RouteResult<Json<SecurityPolicy>> {
+    roles::ensure(&state.db, &claims, Permission::SecurityManage).await?;
+
+    Ok(Json(load(&state.db).await?))
+}
+
+/// Replaces the security policy. It is shared by all schools, so only district administrators
+/// can change it, schools change their own with `PUT /policy/school`
+#[utoipa::path(
+    put,
+    path = "/policy",
+    tag = "Security policy",
+    request_body = SecurityPolicy,
+    responses((status = 200, body = SecurityPolicy))
+)]
+async fn update(
+    State(state): RouteState,
+    claims: Claims,
+    Json(policy): Json<SecurityPolicy>,
+) -> RouteResult<Json<SecurityPolicy>> {
+    roles::ensure_admin(&claims)?;
+
+    let mut tx = state.db.begin().await?;
+    let before = load(&mut *tx).await?;
+    sqlx::query(
+        "
+            INSERT INTO Settings(key, value) VALUES ('security_policy', $1)
+            ON CONFLICT (key) DO UPDATE SET value = $1
+        ",
+    )
+    .bind(SqlJson(&policy))
+    .execute(&mut *tx)
+    .await?;
+    if policy.require_principal_2fa != before.require_principal_2fa {
+        apply_2fa_requirement(&mut tx, None).await?;
+    }
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::SecurityPolicy,
+        None,
+        Some(&before),
+        Some(&policy),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(policy))
+}
+
+/// Fetches the security policy of the current school
+#[utoipa::path(
+    get,
+    path = "/policy/school",
+    tag = "Security policy",
+    responses((status = 200, body = SchoolSecurityPolicy), (status = 403))
+)]
+async fn fetch_school(
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Json<SchoolSecurityPolicy>> {
+    roles::ensure(&state.db, &claims, Permission::SecurityManage).await?;
+    let school_id = claims.school()?;
+
+    let require_principal_2fa =
+        sqlx::query_scalar::<_, bool>("SELECT require_principal_2fa FROM Schools WHERE id = $1")
+            .bind(school_id)
+            .fetch_one(&state.db)
+            .await?;
+
+    Ok(Json(SchoolSecurityPolicy {
+        require_principal_2fa,
+    }))
+}
+
+/// Replaces the security policy of the current school. It can only add requirements to the
+/// district policy
+#[utoipa::path(
+    put,
+    path = "/policy/school",
+    tag = "Security policy",
+    request_body = SchoolSecurityPolicy,
+    responses((status = 200, body = SchoolSecurityPolicy), (status = 403))
+)]
+async fn update_school(
+    State(state): RouteState,
+    claims: Claims,
+    Json(policy): Json<SchoolSecurityPolicy>,
+) -> RouteResult<Json<SchoolSecurityPolicy>> {
+    roles::ensure(&state.db, &claims, Permission::SecurityManage).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let before = sqlx::query_scalar::<_, bool>(
+        "SELECT require_principal_2fa FROM Schools WHERE id = $1 FOR UPDATE",
+    )
+    .bind(school_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE Schools SET require_principal_2fa = $2 WHERE id = $1")
+        .bind(school_id)
+        .bind(policy.require_principal_2fa)
+        .execute(&mut *tx)
+        .await?;
+    if policy.require_principal_2fa != before {
+        apply_2fa_requirement(&mut tx, Some(school_id)).await?;
+    }
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::SecurityPolicy,
+        Some(school_id),
+        Some(&SchoolSecurityPolicy {
+            require_principal_2fa: before,
+        }),
+        Some(&policy),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(policy))
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(fetch, update, fetch_school, update_school),
+        components(schemas(SecurityPolicy, SchoolSecurityPolicy))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(fetch).put(update))
+        .route("/school", get(fetch_school).put(update_school))
+}
@@ -1,63 +1,151 @@
-use super::{Json, RouteResult, RouteState};
-use crate::{fail, models::Employee, AppState};
-use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    auth, not_null, nullable,
+    pagination::{Order, Page, Paged, Pagination},
+    passwords, roles,
+    versions::{IfMatch, Tagged},
+    Json, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Employee, Permission, Role},
+    AppState,
+};
 use axum::{
     extract::{Path, Query, State},
     routing::*,
 };
-use rand::rngs::OsRng;
 use serde::Deserialize;
+use time::OffsetDateTime;
 use utoipa::{IntoParams, ToSchema};
 
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum PrincipalSort {
+    #[default]
+    Id,
+    LastName,
+    EmployedAt,
+}
+
 #[derive(Deserialize, IntoParams)]
 struct Fetch {
     name: Option<String>,
     id: Option<i32>,
+    /// Exact phone number
+    phone: Option<String>,
+    #[serde(default)]
+    exclude_ids: Vec<i32>,
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    employed_after: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    employed_before: Option<OffsetDateTime>,
+    /// Fetch archived principals instead of active ones
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    sort: PrincipalSort,
+    #[serde(default)]
+    desc: bool,
     count: Option<i64>,
-    offset: Option<i64>,
+    cursor: Option<String>,
 }
 
-/// Fetches pricipals
+/// Fetches principals of the school, district administrators see principals of all schools
 #[utoipa::path(
     get,
     path = "/principals",
     params(Fetch),
     responses(
-        (status = 200, body = Vec<Employee>)
+        (status = 200, body = EmployeePage)
     )
 )]
 async fn fetch(
     State(state): RouteState,
+    claims: Claims,
     Query(query): Query<Fetch>,
-) -> RouteResult<Json<Vec<Employee>>> {
+) -> RouteResult<Json<Page<Employee>>> {
     let Fetch {
         name,
         id,
+        phone,
+        exclude_ids,
+        employed_after,
+        employed_before,
+        archived,
+        sort,
+        desc,
         count,
-        offset,
+        cursor,
     } = query;
 
-    let count = count.unwrap_or(50).max(0);
-    let offset = offset.unwrap_or(0).max(0);
+    let order = match sort {
+        PrincipalSort::Id => Order::ID,
+        PrincipalSort::LastName => Order::by("last_name", "TEXT"),
+        PrincipalSort::EmployedAt => Order::by("employed_at", "TIMESTAMPTZ"),
+    };
 
-    let results = sqlx::query_as::<_, Employee>(
+    let pagination = Pagination::new(count, cursor, order.desc(desc))?;
+    let query = pagination.query(
         "
             SELECT * FROM Employees
             WHERE
-                coalesce(first_name || last_name || coalesce(middle_name, '') ILIKE ('%' || $3 || '%'), true) AND
-                coalesce(id = $4, true) AND
-                role = 'pricipal'
-            LIMIT $1 OFFSET $2
+                coalesce(search_name(first_name, last_name, middle_name) LIKE ('%' || search_text($4) || '%'), true) AND
+                coalesce(id = $5, true) AND
+                role = 'principal' AND
+                coalesce(school_id = $6, true) AND
+                (archived_at IS NOT NULL) = $7 AND
+                coalesce(phone = $8, true) AND
+                NOT (id = any($9)) AND
+                employed_at BETWEEN
+                    coalesce($10, '-infinity'::timestamptz) AND
+                    coalesce($11, 'infinity'::timestamptz)
+        ",
+    );
+    let results = pagination
+        .bind(sqlx::query_as::<_, Paged<Employee>>(&query))
+        .bind(name)
+        .bind(id)
+        .bind(claims.school_id)
+        .bind(archived)
+        .bind(phone)
+        .bind(exclude_ids)
+        .bind(employed_after)
+        .bind(employed_before)
+        .fetch_all(&state.db)
+        .await?;
+
+    Ok(Json(pagination.finish(results)))
+}
+
+/// Fetches a principal by id, the `ETag` header carries its version
+#[utoipa::path(
+    get,
+    path = "/principals/{id}",
+    params(("id" = i32, Path, description = "Id of the principal")),
+    responses((status = 200, body = Employee), (status = 404))
+)]
+async fn fetch_one(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Employee>> {
+    let Some(principal) = sqlx::query_as::<_, Employee>(
+        "
+            SELECT * FROM Employees
+            WHERE id = $1 AND role = 'principal' AND coalesce(school_id = $2, true)
         ",
     )
-    .bind(count)
-    .bind(offset)
-    .bind(name)
     .bind(id)
-    .fetch_all(&state.db)
-    .await?;
+    .bind(claims.school_id)
+    .fetch_optional(&state.db)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Завуча с таким ИД не существует");
+    };
 
-    Ok(Json(results))
+    Ok(Tagged(principal))
 }
 
 #[derive(Deserialize)]
@@ -66,30 +154,43 @@ struct CreatePrincipalRequest {
     last_name: String,
     middle_name: Option<String>,
     phone: String,
+    email: Option<String>,
     password: String,
+    /// Required for district administrators, other employees appoint principals of their school
+    school_id: Option<i32>,
 }
 
+/// Creates a principal
 async fn create(
     State(state): RouteState,
+    claims: Claims,
     Json(data): Json<CreatePrincipalRequest>,
-) -> RouteResult<Json<Employee>> {
+) -> RouteResult<Tagged<Employee>> {
     let CreatePrincipalRequest {
         first_name,
         last_name,
         middle_name,
         phone,
+        email,
         password,
+        school_id,
     } = data;
 
-    let password_hash = Argon2::default()
-        .hash_password(password.as_bytes(), &SaltString::generate(OsRng))
-        .unwrap()
-        .to_string();
+    roles::ensure(&state.db, &claims, Permission::PrincipalsWrite).await?;
+    let school_id = match claims.school_id {
+        Some(own) if school_id.is_some_and(|id| id != own) =>
+            fail!(!FORBIDDEN, "Недостаточно прав"),
+        Some(own) => own,
+        None => school_id.ok_or(fail!(BAD_REQUEST, "Необходимо указать школу", "school_id"))?,
+    };
 
+    let password_hash = passwords::hash(&state.db, &password).await?;
+
+    let mut tx = state.db.begin().await?;
     let result = sqlx::query_as::<_, Employee>(
-        "INSERT INTO Employees(first_name, last_name, middle_name, phone, password_hash, role) VALUES(
+        "INSERT INTO Employees(first_name, last_name, middle_name, phone, password_hash, email, role, school_id) VALUES(
             $1, $2, $3,
-            $4, $5, 'principal'
+            $4, $5, $6, 'principal', $7
         ) RETURNING *",
     )
     .bind(first_name)
@@ -97,18 +198,36 @@ async fn create(
     .bind(middle_name)
     .bind(phone)
     .bind(password_hash)
-    .fetch_one(&state.db)
+    .bind(email)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
     .await;
 
-    match result.map(Json) {
-        Ok(val) => Ok(val),
+    let principal = match result {
+        Ok(val) => val,
         Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
             fail!(
                 !BAD_REQUEST,
                 "Завуч с таким номером телефона уже существует"
             ),
-        Err(err) => Err(err.into()),
-    }
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_foreign_key_violation()) =>
+            fail!(!BAD_REQUEST, "Школы с таким ИД не существует", "school_id"),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::Principal,
+        Some(principal.id),
+        None,
+        Some(&principal),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(principal))
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -118,29 +237,47 @@ struct UpdatePrincipalRequest {
     last_name: String,
     middle_name: Option<String>,
     phone: String,
+    /// Kept when omitted on update, `PATCH` with `null` clears it
+    email: Option<String>,
     password: Option<String>,
 }
 
 async fn update(
     State(state): RouteState,
     Path(id): Path<i32>,
+    claims: Claims,
+    if_match: IfMatch,
     Json(data): Json<UpdatePrincipalRequest>,
-) -> RouteResult<Json<Employee>> {
+) -> RouteResult<Tagged<Employee>> {
     let UpdatePrincipalRequest {
         first_name,
         last_name,
         middle_name,
         phone,
+        email,
         password,
     } = data;
 
-    let password_hash = password.map(|pass| {
-        Argon2::default()
-            .hash_password(pass.as_bytes(), &SaltString::generate(OsRng))
-            .unwrap()
-            .to_string()
-    });
+    roles::ensure(&state.db, &claims, Permission::PrincipalsWrite).await?;
+
+    let password_hash = match password {
+        Some(password) => Some(passwords::hash(&state.db, &password).await?),
+        None => None,
+    };
 
+    let mut tx = state.db.begin().await?;
+    let before = sqlx::query_as::<_, Employee>(
+        "
+            SELECT * FROM Employees
+            WHERE id = $1 AND role = 'principal' AND coalesce(school_id = $2, true)
+            FOR UPDATE
+        ",
+    )
+    .bind(id)
+    .bind(claims.school_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if_match.check(before.as_ref())?;
     let result = sqlx::query_as::<_, Employee>(
         "
             UPDATE Employees SET
@@ -148,8 +285,11 @@ async fn update(
             last_name = $3,
             middle_name = $4,
             phone = $5,
-            password_hash = coalesce($6, password_hash)
-            WHERE id = $1
+            password_hash = coalesce($6, password_hash),
+            email = coalesce($7, email)
+            WHERE
+                id = $1 AND role = 'principal' AND coalesce(school_id = $8, true) AND
+                archived_at IS NULL
             RETURNING *
         ",
     )
@@ -159,46 +299,266 @@ async fn update(
     .bind(middle_name)
     .bind(phone)
     .bind(password_hash)
-    .fetch_optional(&state.db)
+    .bind(email)
+    .bind(claims.school_id)
+    .fetch_optional(&mut *tx)
+    .await;
+
+    let principal = match result {
+        Ok(Some(val)) => val,
+        Ok(None) => fail!(!NOT_FOUND, "Завуч с таким ИД не существует"),
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
+            fail!(
+                !BAD_REQUEST,
+                "Завуч с таким номером телефона уже существует"
+            ),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Principal,
+        Some(id),
+        before.as_ref(),
+        Some(&principal),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(principal))
+}
+
+/// Only the fields present are changed, `null` clears the optional ones
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct PatchPrincipalRequest {
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    first_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    last_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    middle_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    phone: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    email: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    password: Option<Option<String>>,
+}
+
+async fn patch(
+    State(state): RouteState,
+    Path(id): Path<i32>,
+    claims: Claims,
+    if_match: IfMatch,
+    Json(data): Json<PatchPrincipalRequest>,
+) -> RouteResult<Tagged<Employee>> {
+    let PatchPrincipalRequest {
+        first_name,
+        last_name,
+        middle_name,
+        phone,
+        email,
+        password,
+    } = data;
+    let first_name = not_null(first_name, "first_name")?;
+    let last_name = not_null(last_name, "last_name")?;
+    let phone = not_null(phone, "phone")?;
+    let password = not_null(password, "password")?;
+
+    roles::ensure(&state.db, &claims, Permission::PrincipalsWrite).await?;
+
+    let password_hash = match password {
+        Some(password) => Some(passwords::hash(&state.db, &password).await?),
+        None => None,
+    };
+
+    let mut tx = state.db.begin().await?;
+    let before = sqlx::query_as::<_, Employee>(
+        "
+            SELECT * FROM Employees
+            WHERE id = $1 AND role = 'principal' AND coalesce(school_id = $2, true)
+            FOR UPDATE
+        ",
+    )
+    .bind(id)
+    .bind(claims.school_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if_match.check(before.as_ref())?;
+    let result = sqlx::query_as::<_, Employee>(
+        "
+            UPDATE Employees SET
+            first_name = coalesce($2, first_name),
+            last_name = coalesce($3, last_name),
+            middle_name = CASE WHEN $4 THEN $5 ELSE middle_name END,
+            phone = coalesce($6, phone),
+            password_hash = coalesce($7, password_hash),
+            email = CASE WHEN $8 THEN $9 ELSE email END
+            WHERE
+                id = $1 AND role = 'principal' AND coalesce(school_id = $10, true) AND
+                archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(first_name)
+    .bind(last_name)
+    .bind(middle_name.is_some())
+    .bind(middle_name.flatten())
+    .bind(phone)
+    .bind(password_hash)
+    .bind(email.is_some())
+    .bind(email.flatten())
+    .bind(claims.school_id)
+    .fetch_optional(&mut *tx)
     .await;
 
-    match result {
-        Ok(Some(val)) => Ok(Json(val)),
+    let principal = match result {
+        Ok(Some(val)) => val,
         Ok(None) => fail!(!NOT_FOUND, "Завуч с таким ИД не существует"),
         Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
             fail!(
                 !BAD_REQUEST,
                 "Завуч с таким номером телефона уже существует"
             ),
-        Err(err) => Err(err.into()),
-    }
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Principal,
+        Some(id),
+        before.as_ref(),
+        Some(&principal),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(principal))
 }
 
-/// Removes an employee by id
+/// Archives a principal by id
 #[utoipa::path(
     delete,
     path = "/principals/{id}",
     params(
-        ("id" = i32, Path, description = "Id of the employee to remove")
+        ("id" = i32, Path, description = "Id of the principal to archive")
     ),
     responses(
         (status = 200)
-    )
+    , (status = 412), (status = 428))
 )]
-async fn remove(State(state): RouteState, Path(id): Path<i32>) -> RouteResult {
-    let result = sqlx::query("DELETE FROM Employees WHERE id = $1 AND role = 'principal'")
-        .bind(id)
-        .execute(&state.db)
+async fn remove(
+    State(state): RouteState,
+    Path(id): Path<i32>,
+    claims: Claims,
+    if_match: IfMatch,
+) -> RouteResult {
+    roles::ensure(&state.db, &claims, Permission::PrincipalsWrite).await?;
+
+    let mut tx = state.db.begin().await?;
+    if_match
+        .lock(&mut tx, "Employees", id, claims.school_id)
         .await?;
-    if result.rows_affected() == 0 {
+    let Some(principal) = sqlx::query_as::<_, Employee>(
+        "
+            UPDATE Employees SET archived_at = now()
+            WHERE
+                id = $1 AND role = 'principal' AND coalesce(school_id = $2, true) AND
+                archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(claims.school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
         fail!(!NOT_FOUND, "Завуча с таким ИД не существует");
-    }
+    };
+    auth::revoke_sessions(&mut *tx, Role::Principal, id).await?;
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Archive,
+        AuditEntity::Principal,
+        Some(id),
+        None,
+        Some(&principal),
+    )
+    .await?;
+    tx.commit().await?;
 
     Ok(())
 }
 
+/// Restores an archived principal by id
+#[utoipa::path(
+    post,
+    path = "/principals/{id}/restore",
+    params(
+        ("id" = i32, Path, description = "Id of the principal to restore")
+    ),
+    responses(
+        (status = 200, body = Employee)
+    )
+)]
+async fn restore(
+    State(state): RouteState,
+    Path(id): Path<i32>,
+    claims: Claims,
+) -> RouteResult<Tagged<Employee>> {
+    roles::ensure(&state.db, &claims, Permission::PrincipalsWrite).await?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(principal) = sqlx::query_as::<_, Employee>(
+        "
+            UPDATE Employees SET archived_at = NULL
+            WHERE
+                id = $1 AND role = 'principal' AND coalesce(school_id = $2, true) AND
+                archived_at IS NOT NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(claims.school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Архивного завуча с таким ИД не существует");
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Restore,
+        AuditEntity::Principal,
+        Some(id),
+        None,
+        Some(&principal),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(principal))
+}
+
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(fetch).post(create))
-        .route("/:id", put(update).delete(remove))
+        .route(
+            "/:id",
+            get(fetch_one).put(update).patch(patch).delete(remove),
+        )
+        .route("/:id/restore", post(restore))
 }
@@ -0,0 +1,479 @@
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    Json, Path, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{AccessRole, Permission, Role},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use serde::Deserialize;
+use sqlx::PgExecutor;
+use utoipa::{OpenApi, ToSchema};
+
+/// Effective permissions of the user. Principals and district administrators have all of them,
+/// other employees have those of their access roles. Requests made with API keys are limited by
+/// the key scopes and have none
+pub(super) async fn resolve<'e>(
+    db: impl PgExecutor<'e>,
+    claims: &Claims,
+) -> RouteResult<Vec<Permission>> {
+    if claims.service_account_id.is_some() {
+        return Ok(vec![]);
+    }
+
+    of_user(db, claims.role, claims.user_id).await
+}
+
+/// Permissions the user has when signed in, regardless of the current request
+pub(super) async fn of_user<'e>(
+    db: impl PgExecutor<'e>,
+    role: Role,
+    user_id: i32,
+) -> RouteResult<Vec<Permission>> {
+    if !role.is_employee() {
+        return Ok(vec![]);
+    }
+    if matches!(role, Role::Principal | Role::Admin) {
+        return Ok(Permission::ALL.to_vec());
+    }
+
+    let permissions = sqlx::query_scalar::<_, Permission>(
+        "
+            SELECT DISTINCT unnest(permissions) FROM AccessRoles
+            JOIN EmployeeAccessRoles ON access_role_id = id
+            WHERE employee_id = $1
+            ORDER BY 1
+        ",
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(permissions)
+}
+
+pub(super) async fn has<'e>(
+    db: impl PgExecutor<'e>,
+    claims: &Claims,
+    permission: Permission,
+) -> RouteResult<bool> {
+    Ok(resolve(db, claims).await?.contains(&permission))
+}
+
+/// Rejects the request unless the user has the permission
+pub(super) async fn ensure<'e>(
+    db: impl PgExecutor<'e>,
+    claims: &Claims,
+    permission: Permission,
+) -> RouteResult {
+    if !has(db, claims, permission).await? {
+        fail!(!FORBIDDEN, "Недостаточно прав");
+    }
+
+    Ok(())
+}
+
+/// Rejects the request unless the user has every one of the permissions, so nobody can grant
+/// themselves or others permissions they do not have
+pub(super) async fn ensure_all<'e>(
+    db: impl PgExecutor<'e>,
+    claims: &Claims,
+    permissions: &[Permission],
+) -> RouteResult {
+    let granted = resolve(db, claims).await?;
+    if !permissions.iter().all(|p| granted.contains(p)) {
+        fail!(!FORBIDDEN, "Недостаточно прав");
+    }
+
+    Ok(())
+}
+
+/// Rejects the request unless it is made by a district administrator. Used for settings
+/// shared by all schools
+pub(super) fn ensure_admin(claims: &Claims) -> RouteResult {
+    if claims.role != Role::Admin || claims.service_account_id.is_some() {
+        fail!(!FORBIDDEN, "Недостаточно прав");
+    }
+
+    Ok(())
+}
+
+/// Fetches permissions of the current user
+#[utoipa::path(
+    get,
+    path = "/roles/me",
+    tag = "Access roles",
+    responses((status = 200, body = Vec<Permission>))
+)]
+async fn mine(State(state): RouteState, claims: Claims) -> RouteResult<Json<Vec<Permission>>> {
+    Ok(Json(resolve(&state.db, &claims).await?))
+}
+
+/// Fetches all permissions which can be granted
+#[utoipa::path(
+    get,
+    path = "/roles/permissions",
+    tag = "Access roles",
+    responses((status = 200, body = Vec<Permission>))
+)]
+async fn permissions(_: Claims) -> Json<Vec<Permission>> {
+    Json(Permission::ALL.to_vec())
+}
+
+/// Fetches access roles
+#[utoipa::path(
+    get,
+    path = "/roles",
+    tag = "Access roles",
+    responses((status = 200, body = Vec<AccessRole>), (status = 403))
+)]
+async fn fetch(State(state): RouteState, claims: Claims) -> RouteResult<Json<Vec<AccessRole>>> {
+    ensure(&state.db, &claims, Permission::RolesManage).await?;
+    let school_id = claims.school()?;
+
+    let roles = sqlx::query_as::<_, AccessRole>(
+        "SELECT * FROM AccessRoles WHERE school_id = $1 ORDER BY id",
+    )
+    .bind(school_id)
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok(Json(roles))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct CreateOrUpdateRoleRequest {
+    name: String,
+    permissions: Vec<Permission>,
+}
+
+/// Creates an access role
+#[utoipa::path(
+    post,
+    path = "/roles",
+    tag = "Access roles",
+    request_body = CreateOrUpdateRoleRequest,
+    responses((status = 200, body = AccessRole), (status = 403))
+)]
+async fn create(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CreateOrUpdateRoleRequest>,
+) -> RouteResult<Json<AccessRole>> {
+    let CreateOrUpdateRoleRequest {
+        name,
+        mut permissions,
+    } = data;
+
+    ensure(&state.db, &claims, Permission::RolesManage).await?;
+    let school_id = claims.school()?;
+
+    permissions.sort_by_key(|&p| p as u8);
+    permissions.dedup();
+    ensure_all(&state.db, &claims, &permissions).await?;
+
+    let mut tx = state.db.begin().await?;
+    let result = sqlx::query_as::<_, AccessRole>(
+        "INSERT INTO AccessRoles(name, permissions, school_id) VALUES ($1, $2, $3) RETURNING *",
+    )
+    .bind(name)
+    .bind(permissions)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
+    .await;
+
+    let role = match result {
+        Ok(role) => role,
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
+            fail!(
+                !BAD_REQUEST,
+                "Роль с таким названием уже существует",
+                "name"
+            ),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::AccessRole,
+        Some(role.id),
+        None,
+        Some(&role),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(role))
+}
+
+/// Updates an access role, employees with the role are affected immediately
+#[utoipa::path(
+    put,
+    path = "/roles/{id}",
+    tag = "Access roles",
+    params(("id" = i32, Path, description = "Id of the role to update")),
+    request_body = CreateOrUpdateRoleRequest,
+    responses((status = 200, body = AccessRole), (status = 403), (status = 404))
+)]
+async fn update(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CreateOrUpdateRoleRequest>,
+) -> RouteResult<Json<AccessRole>> {
+    let CreateOrUpdateRoleRequest {
+        name,
+        mut permissions,
+    } = data;
+
+    ensure(&state.db, &claims, Permission::RolesManage).await?;
+    let school_id = claims.school()?;
+
+    permissions.sort_by_key(|&p| p as u8);
+    permissions.dedup();
+    ensure_all(&state.db, &claims, &permissions).await?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(before) = sqlx::query_as::<_, AccessRole>(
+        "SELECT * FROM AccessRoles WHERE id = $1 AND school_id = $2 FOR UPDATE",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Роли с таким ИД не существует");
+    };
+
+    let result = sqlx::query_as::<_, AccessRole>(
+        "UPDATE AccessRoles SET name = $2, permissions = $3 WHERE id = $1 RETURNING *",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(permissions)
+    .fetch_one(&mut *tx)
+    .await;
+
+    let role = match result {
+        Ok(role) => role,
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
+            fail!(
+                !BAD_REQUEST,
+                "Роль с таким названием уже существует",
+                "name"
+            ),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::AccessRole,
+        Some(id),
+        Some(&before),
+        Some(&role),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(role))
+}
+
+/// Deletes an access role, it is taken away from all employees
+#[utoipa::path(
+    delete,
+    path = "/roles/{id}",
+    tag = "Access roles",
+    params(("id" = i32, Path, description = "Id of the role to delete")),
+    responses((status = 200), (status = 403), (status = 404))
+)]
+async fn remove(Path(id): Path<i32>, State(state): RouteState, claims: Claims) -> RouteResult {
+    ensure(&state.db, &claims, Permission::RolesManage).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(role) = sqlx::query_as::<_, AccessRole>(
+        "DELETE FROM AccessRoles WHERE id = $1 AND school_id = $2 RETURNING *",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Роли с таким ИД не существует");
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Delete,
+        AuditEntity::AccessRole,
+        Some(id),
+        Some(&role),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Fetches access roles assigned to the employee
+#[utoipa::path(
+    get,
+    path = "/roles/employees/{id}",
+    tag = "Access roles",
+    params(("id" = i32, Path, description = "Id of the employee")),
+    responses((status = 200, body = Vec<AccessRole>), (status = 403))
+)]
+async fn assigned(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Json<Vec<AccessRole>>> {
+    ensure(&state.db, &claims, Permission::RolesManage).await?;
+    let school_id = claims.school()?;
+
+    let roles = sqlx::query_as::<_, AccessRole>(
+        "
+            SELECT AccessRoles.* FROM AccessRoles
+            JOIN EmployeeAccessRoles ON access_role_id = id
+            WHERE employee_id = $1 AND AccessRoles.school_id = $2
+            ORDER BY id
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok(Json(roles))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct AssignRolesRequest {
+    role_ids: Vec<i32>,
+}
+
+/// Replaces access roles of the employee
+#[utoipa::path(
+    put,
+    path = "/roles/employees/{id}",
+    tag = "Access roles",
+    params(("id" = i32, Path, description = "Id of the employee")),
+    request_body = AssignRolesRequest,
+    responses((status = 200), (status = 403), (status = 404))
+)]
+async fn assign(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<AssignRolesRequest>,
+) -> RouteResult {
+    let AssignRolesRequest { mut role_ids } = data;
+
+    ensure(&state.db, &claims, Permission::RolesManage).await?;
+    let school_id = claims.school()?;
+
+    role_ids.sort_unstable();
+    role_ids.dedup();
+
+    let mut tx = state.db.begin().await?;
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM Employees WHERE id = $1 AND school_id = $2)",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if !exists {
+        fail!(!NOT_FOUND, "Сотрудника с таким ИД не существует");
+    }
+
+    let permissions = sqlx::query_scalar::<_, Permission>(
+        "
+            SELECT DISTINCT unnest(permissions) FROM AccessRoles
+            WHERE id = ANY($1) AND school_id = $2
+        ",
+    )
+    .bind(&role_ids)
+    .bind(school_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    ensure_all(&mut *tx, &claims, &permissions).await?;
+
+    let before = sqlx::query_scalar::<_, i32>(
+        "
+            DELETE FROM EmployeeAccessRoles WHERE employee_id = $1
+            RETURNING access_role_id
+        ",
+    )
+    .bind(id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let result = sqlx::query(
+        "
+            INSERT INTO EmployeeAccessRoles(employee_id, access_role_id, school_id)
+            SELECT $1, unnest($2::INTEGER[]), $3
+        ",
+    )
+    .bind(id)
+    .bind(&role_ids)
+    .bind(school_id)
+    .execute(&mut *tx)
+    .await;
+    match result {
+        Ok(_) => {}
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_foreign_key_violation()) =>
+            fail!(!BAD_REQUEST, "Роли с таким ИД не существует", "role_ids"),
+        Err(err) => return Err(err.into()),
+    }
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::RoleAssignment,
+        Some(id),
+        Some(&before),
+        Some(&role_ids),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(mine, permissions, fetch, create, update, remove, assigned, assign),
+        components(schemas(
+            AccessRole,
+            Permission,
+            CreateOrUpdateRoleRequest,
+            AssignRolesRequest
+        ))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(fetch).post(create))
+        .route("/me", get(mine))
+        .route("/permissions", get(permissions))
+        .route("/:id", put(update).delete(remove))
+        .route("/employees/:id", get(assigned).put(assign))
+}
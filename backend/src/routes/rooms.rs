@@ -1,9 +1,41 @@
-use super::{Json, Path, Query, RouteResult, RouteState};
-use crate::{fail, models::Room, AppState};
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    dependencies::{self, Dependency, DependencyKind, Remove, Source},
+    not_null, nullable,
+    pagination::{Order, Page, Paged, Pagination, RoomPage},
+    roles, teachers,
+    versions::{IfMatch, Tagged},
+    Json, Path, Query, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Permission, Room},
+    AppState,
+};
 use axum::{extract::State, routing::*};
 use serde::Deserialize;
+use sqlx::PgConnection;
 use utoipa::{IntoParams, OpenApi, ToSchema};
 
+const DEPENDENCIES: &[Source] = &[Source {
+    kind: DependencyKind::Teacher,
+    blocking: true,
+    query: "
+        SELECT id FROM Teachers
+        JOIN Employees ON id = employee_id
+        WHERE room_id = $1 AND Teachers.school_id = $2 AND archived_at IS NULL
+    ",
+}];
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum RoomSort {
+    #[default]
+    Id,
+    Name,
+}
+
 #[derive(Deserialize, IntoParams)]
 #[serde(deny_unknown_fields)]
 struct Fetch {
@@ -11,8 +43,19 @@ struct Fetch {
     id: Option<i32>,
     #[serde(default)]
     subject_ids: Vec<i32>,
+    #[serde(default)]
+    exclude_ids: Vec<i32>,
+    #[serde(default)]
+    exclude_subject_ids: Vec<i32>,
+    /// Fetch archived rooms instead of active ones
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    sort: RoomSort,
+    #[serde(default)]
+    desc: bool,
     count: Option<i64>,
-    offset: Option<i64>,
+    cursor: Option<String>,
 }
 
 /// Fetches rooms
@@ -21,42 +64,87 @@ struct Fetch {
     path = "/rooms",
     tag = "Room management",
     params(Fetch),
-    responses((status = 200, body = Vec<Room>))
+    responses((status = 200, body = RoomPage))
 )]
 async fn fetch(
     State(state): RouteState,
+    claims: Claims,
     Query(query): Query<Fetch>,
-) -> RouteResult<Json<Vec<Room>>> {
+) -> RouteResult<Json<Page<Room>>> {
     let Fetch {
         name,
         id,
         subject_ids,
+        exclude_ids,
+        exclude_subject_ids,
+        archived,
+        sort,
+        desc,
         count,
-        offset,
+        cursor,
     } = query;
 
-    let count = count.unwrap_or(50).clamp(0, 100);
-    let offset = offset.unwrap_or(0).clamp(0, 5000);
+    let school_id = claims.school()?;
+    let order = match sort {
+        RoomSort::Id => Order::ID,
+        RoomSort::Name => Order::by("room", "TEXT"),
+    };
 
-    let rooms = sqlx::query_as::<_, Room>(
+    let pagination = Pagination::new(count, cursor, order.desc(desc))?;
+    let query = pagination.query(
         r#"
             SELECT * FROM Rooms
             WHERE
-                coalesce(room ILIKE ('%' || $3 || '%'), true) AND
-                coalesce(id = $4, true) AND
-                (subject_id = any($5) OR cardinality($5) = 0)
-            LIMIT $1 OFFSET $2
+                school_id = $7 AND
+                (archived_at IS NOT NULL) = $8 AND
+                coalesce(room ILIKE ('%' || $4 || '%'), true) AND
+                coalesce(id = $5, true) AND
+                (subject_id = any($6) OR cardinality($6) = 0) AND
+                NOT (id = any($9)) AND
+                NOT coalesce(subject_id = any($10), false)
         "#,
-    )
-    .bind(count)
-    .bind(offset)
-    .bind(name)
-    .bind(id)
-    .bind(subject_ids)
-    .fetch_all(&state.db)
-    .await?;
+    );
+    let rooms = pagination
+        .bind(sqlx::query_as::<_, Paged<Room>>(&query))
+        .bind(name)
+        .bind(id)
+        .bind(subject_ids)
+        .bind(school_id)
+        .bind(archived)
+        .bind(exclude_ids)
+        .bind(exclude_subject_ids)
+        .fetch_all(&state.db)
+        .await?;
+
+    Ok(Json(pagination.finish(rooms)))
+}
 
-    Ok(Json(rooms))
+/// Fetches a room by id, the `ETag` header carries its version
+#[utoipa::path(
+    get,
+    path = "/rooms/{id}",
+    tag = "Room management",
+    params(("id" = i32, Path, description = "Id of the room")),
+    responses((status = 200, body = Room), (status = 404))
+)]
+async fn fetch_one(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Room>> {
+    let school_id = claims.school()?;
+
+    let Some(room) =
+        sqlx::query_as::<_, Room>("SELECT * FROM Rooms WHERE id = $1 AND school_id = $2")
+            .bind(id)
+            .bind(school_id)
+            .fetch_optional(&state.db)
+            .await?
+    else {
+        fail!(!NOT_FOUND, "Кабинета с таким ИД не существует");
+    };
+
+    Ok(Tagged(room))
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -76,25 +164,46 @@ struct CreateOrUpdateRoomRequest {
 )]
 async fn create(
     State(state): RouteState,
+    claims: Claims,
     Json(data): Json<CreateOrUpdateRoomRequest>,
-) -> RouteResult<Json<Room>> {
+) -> RouteResult<Tagged<Room>> {
     let CreateOrUpdateRoomRequest { name, subject_id } = data;
 
-    let result =
-        sqlx::query_as::<_, Room>("INSERT INTO Rooms(room, subject_id) VALUES($1, $2) RETURNING *")
-            .bind(name)
-            .bind(subject_id)
-            .fetch_one(&state.db)
-            .await;
+    roles::ensure(&state.db, &claims, Permission::RoomsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let result = sqlx::query_as::<_, Room>(
+        "INSERT INTO Rooms(room, subject_id, school_id) VALUES($1, $2, $3) RETURNING *",
+    )
+    .bind(name)
+    .bind(subject_id)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
+    .await;
 
-    match result.map(Json) {
-        Ok(value) => Ok(value),
+    let room = match result {
+        Ok(room) => room,
         Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
             fail!(!BAD_REQUEST, "Такой кабинет уже существует"),
         Err(err) if matches!(err.as_database_error(), Some(err) if err.is_foreign_key_violation()) =>
             fail!(!BAD_REQUEST, "Предмет с данным ИД не существует"),
-        Err(err) => Err(err.into()),
-    }
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::Room,
+        Some(room.id),
+        None,
+        Some(&room),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(room))
 }
 
 /// Updates a room with specified id
@@ -104,58 +213,352 @@ async fn create(
     tag = "Room management",
     params(("id" = i32, Path, description = "Id of the room to update")),
     request_body = CreateOrUpdateRoomRequest,
-    responses((status = 200, body = Room))
+    responses((status = 200, body = Room), (status = 412), (status = 428))
 )]
 async fn update(
     Path(id): Path<i32>,
     State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
     Json(data): Json<CreateOrUpdateRoomRequest>,
-) -> RouteResult<Json<Room>> {
+) -> RouteResult<Tagged<Room>> {
     let CreateOrUpdateRoomRequest { name, subject_id } = data;
 
+    roles::ensure(&state.db, &claims, Permission::RoomsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let before = sqlx::query_as::<_, Room>(
+        "SELECT * FROM Rooms WHERE id = $1 AND school_id = $2 FOR UPDATE",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if_match.check(before.as_ref())?;
     let result = sqlx::query_as::<_, Room>(
-        "UPDATE Rooms SET room = $2, subject_id = $3 WHERE id = $1 RETURNING *",
+        "
+            UPDATE Rooms SET room = $2, subject_id = $3
+            WHERE id = $1 AND school_id = $4 AND archived_at IS NULL
+            RETURNING *
+        ",
     )
     .bind(id)
     .bind(name)
     .bind(subject_id)
-    .fetch_optional(&state.db)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
     .await;
 
-    match result {
-        Ok(Some(room)) => Ok(Json(room)),
+    let room = match result {
+        Ok(Some(room)) => room,
         Ok(None) => fail!(!BAD_REQUEST, "Комнаты с таким ИД не существует"),
         Err(err) if matches!(err.as_database_error(), Some(err) if err.is_foreign_key_violation()) =>
             fail!(!BAD_REQUEST, "Предмета с таким ИД не существует"),
-        Err(err) => Err(err.into()),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Room,
+        Some(id),
+        before.as_ref(),
+        Some(&room),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(room))
+}
+
+/// Only the fields present are changed, `null` clears the subject
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct PatchRoomRequest {
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<i32>)]
+    subject_id: Option<Option<i32>>,
+}
+
+/// Changes some fields of a room with specified id
+#[utoipa::path(
+    patch,
+    path = "/rooms/{id}",
+    tag = "Room management",
+    params(("id" = i32, Path, description = "Id of the room to update")),
+    request_body = PatchRoomRequest,
+    responses((status = 200, body = Room), (status = 412), (status = 428))
+)]
+async fn patch(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+    Json(data): Json<PatchRoomRequest>,
+) -> RouteResult<Tagged<Room>> {
+    let PatchRoomRequest { name, subject_id } = data;
+    let name = not_null(name, "name")?;
+
+    roles::ensure(&state.db, &claims, Permission::RoomsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let before = sqlx::query_as::<_, Room>(
+        "SELECT * FROM Rooms WHERE id = $1 AND school_id = $2 FOR UPDATE",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if_match.check(before.as_ref())?;
+    let result = sqlx::query_as::<_, Room>(
+        "
+            UPDATE Rooms SET
+                room = coalesce($2, room),
+                subject_id = CASE WHEN $3 THEN $4 ELSE subject_id END
+            WHERE id = $1 AND school_id = $5 AND archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(subject_id.is_some())
+    .bind(subject_id.flatten())
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await;
+
+    let room = match result {
+        Ok(Some(room)) => room,
+        Ok(None) => fail!(!BAD_REQUEST, "Комнаты с таким ИД не существует"),
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_foreign_key_violation()) =>
+            fail!(!BAD_REQUEST, "Предмета с таким ИД не существует"),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Room,
+        Some(id),
+        before.as_ref(),
+        Some(&room),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(room))
+}
+
+/// Moves active rooms of the subject to another one
+pub(super) async fn reassign_subject(
+    db: &mut PgConnection,
+    claims: &Claims,
+    school_id: i32,
+    from: i32,
+    to: i32,
+) -> RouteResult {
+    let before = sqlx::query_as::<_, Room>(
+        "
+            SELECT * FROM Rooms
+            WHERE subject_id = $1 AND school_id = $2 AND archived_at IS NULL
+            ORDER BY id
+            FOR UPDATE
+        ",
+    )
+    .bind(from)
+    .bind(school_id)
+    .fetch_all(&mut *db)
+    .await?;
+    let after = sqlx::query_as::<_, Room>(
+        "
+            WITH Updated AS (
+                UPDATE Rooms SET subject_id = $3
+                WHERE subject_id = $1 AND school_id = $2 AND archived_at IS NULL
+                RETURNING *
+            )
+            SELECT * FROM Updated ORDER BY id
+        ",
+    )
+    .bind(from)
+    .bind(school_id)
+    .bind(to)
+    .fetch_all(&mut *db)
+    .await?;
+
+    for (before, after) in before.iter().zip(&after) {
+        audit::record(
+            db,
+            Some(claims),
+            AuditAction::Update,
+            AuditEntity::Room,
+            Some(after.id),
+            Some(before),
+            Some(after),
+        )
+        .await?;
     }
+
+    Ok(())
+}
+
+/// Fetches rows referencing the room
+#[utoipa::path(
+    get,
+    path = "/rooms/{id}/dependencies",
+    tag = "Room management",
+    params(("id" = i32, Path, description = "Id of the room")),
+    responses((status = 200, body = Vec<Dependency>))
+)]
+async fn fetch_dependencies(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Json<Vec<Dependency>>> {
+    roles::ensure(&state.db, &claims, Permission::RoomsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut conn = state.db.acquire().await?;
+    let dependencies = dependencies::collect(&mut conn, id, school_id, DEPENDENCIES).await?;
+
+    Ok(Json(dependencies))
 }
 
-/// Delete a room
+/// Archives a room. Fails with 409 while active teachers work in the room, unless they are
+/// moved to another room with `reassign_to`
 #[utoipa::path(
     delete,
     path = "/rooms/{id}",
     tag = "Room management",
-    params(("id" = i32, Path, description = "Id of the room to delete")),
-    responses((status = 200))
+    params(("id" = i32, Path, description = "Id of the room to archive"), Remove),
+    responses((status = 200), (status = 409), (status = 412), (status = 428))
 )]
-async fn remove(Path(id): Path<i32>, State(state): RouteState) -> RouteResult {
-    let result = sqlx::query("DELETE FROM Rooms WHERE id = $1")
-        .bind(id)
-        .execute(&state.db)
-        .await?;
-    if result.rows_affected() == 0 {
+async fn remove(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+    Query(query): Query<Remove>,
+) -> RouteResult {
+    let Remove { reassign_to } = query;
+
+    roles::ensure(&state.db, &claims, Permission::RoomsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    if_match.lock(&mut tx, "Rooms", id, Some(school_id)).await?;
+    let Some(room) = sqlx::query_as::<_, Room>(
+        "
+            UPDATE Rooms SET archived_at = now()
+            WHERE id = $1 AND school_id = $2 AND archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
         fail!(!BAD_REQUEST, "Такого кабинета не существует");
+    };
+
+    if let Some(target) = reassign_to {
+        dependencies::ensure_target(&mut tx, "Rooms", id, target, school_id).await?;
+        teachers::reassign(&mut tx, &claims, school_id, "room_id", id, target).await?;
     }
+    let dependencies = dependencies::collect(&mut tx, id, school_id, DEPENDENCIES).await?;
+    dependencies::ensure_unblocked(dependencies)?;
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Archive,
+        AuditEntity::Room,
+        Some(id),
+        None,
+        Some(&room),
+    )
+    .await?;
+    tx.commit().await?;
 
     Ok(())
 }
 
+/// Restores an archived room
+#[utoipa::path(
+    post,
+    path = "/rooms/{id}/restore",
+    tag = "Room management",
+    params(("id" = i32, Path, description = "Id of the room to restore")),
+    responses((status = 200, body = Room))
+)]
+async fn restore(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Room>> {
+    roles::ensure(&state.db, &claims, Permission::RoomsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(room) = sqlx::query_as::<_, Room>(
+        "
+            UPDATE Rooms SET archived_at = NULL
+            WHERE id = $1 AND school_id = $2 AND archived_at IS NOT NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!BAD_REQUEST, "Архивного кабинета с таким ИД не существует");
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Restore,
+        AuditEntity::Room,
+        Some(id),
+        None,
+        Some(&room),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(room))
+}
+
 pub fn openapi() -> utoipa::openapi::OpenApi {
     #[derive(OpenApi)]
     #[openapi(
-        paths(fetch, create, update, remove),
-        components(schemas(CreateOrUpdateRoomRequest, Room))
+        paths(
+            fetch,
+            fetch_one,
+            create,
+            update,
+            patch,
+            remove,
+            restore,
+            fetch_dependencies
+        ),
+        components(schemas(
+            CreateOrUpdateRoomRequest,
+            PatchRoomRequest,
+            Room,
+            RoomPage,
+            RoomSort,
+            Dependency,
+            DependencyKind
+        ))
     )]
     struct Api;
 
@@ -165,5 +568,10 @@ pub fn openapi() -> utoipa::openapi::OpenApi {
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(fetch).post(create))
-        .route("/:id", put(update).delete(remove))
+        .route(
+            "/:id",
+            get(fetch_one).put(update).patch(patch).delete(remove),
+        )
+        .route("/:id/restore", post(restore))
+        .route("/:id/dependencies", get(fetch_dependencies))
 }
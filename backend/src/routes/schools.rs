@@ -0,0 +1,277 @@
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    auth::hash_token,
+    passwords, roles, Json, Path, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Employee, School},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use dotenvy::var;
+use serde::Deserialize;
+use utoipa::{OpenApi, ToSchema};
+
+/// Fetches schools. District administrators see all of them, other users only their own
+#[utoipa::path(
+    get,
+    path = "/schools",
+    tag = "Schools",
+    responses((status = 200, body = Vec<School>))
+)]
+async fn fetch(State(state): RouteState, claims: Claims) -> RouteResult<Json<Vec<School>>> {
+    let schools = sqlx::query_as::<_, School>(
+        "SELECT * FROM Schools WHERE coalesce(id = $1, $1 IS NULL) ORDER BY id",
+    )
+    .bind(claims.school_id)
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok(Json(schools))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct CreateOrUpdateSchoolRequest {
+    name: String,
+}
+
+/// Creates a school. Its first principal is created by `POST /principals`
+#[utoipa::path(
+    post,
+    path = "/schools",
+    tag = "Schools",
+    request_body = CreateOrUpdateSchoolRequest,
+    responses((status = 200, body = School), (status = 403))
+)]
+async fn create(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CreateOrUpdateSchoolRequest>,
+) -> RouteResult<Json<School>> {
+    let CreateOrUpdateSchoolRequest { name } = data;
+
+    roles::ensure_admin(&claims)?;
+
+    let mut tx = state.db.begin().await?;
+    let result = sqlx::query_as::<_, School>("INSERT INTO Schools(name) VALUES ($1) RETURNING *")
+        .bind(name)
+        .fetch_one(&mut *tx)
+        .await;
+
+    let school = match result {
+        Ok(school) => school,
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
+            fail!(
+                !BAD_REQUEST,
+                "Школа с таким названием уже существует",
+                "name"
+            ),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::School,
+        Some(school.id),
+        None,
+        Some(&school),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(school))
+}
+
+/// Renames a school
+#[utoipa::path(
+    put,
+    path = "/schools/{id}",
+    tag = "Schools",
+    params(("id" = i32, Path, description = "Id of the school to rename")),
+    request_body = CreateOrUpdateSchoolRequest,
+    responses((status = 200, body = School), (status = 403), (status = 404))
+)]
+async fn update(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CreateOrUpdateSchoolRequest>,
+) -> RouteResult<Json<School>> {
+    let CreateOrUpdateSchoolRequest { name } = data;
+
+    roles::ensure_admin(&claims)?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(before) =
+        sqlx::query_as::<_, School>("SELECT * FROM Schools WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+    else {
+        fail!(!NOT_FOUND, "Школы с таким ИД не существует");
+    };
+
+    let result =
+        sqlx::query_as::<_, School>("UPDATE Schools SET name = $2 WHERE id = $1 RETURNING *")
+            .bind(id)
+            .bind(name)
+            .fetch_one(&mut *tx)
+            .await;
+
+    let school = match result {
+        Ok(school) => school,
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
+            fail!(
+                !BAD_REQUEST,
+                "Школа с таким названием уже существует",
+                "name"
+            ),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::School,
+        Some(id),
+        Some(&before),
+        Some(&school),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(school))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct CreateAdminRequest {
+    first_name: String,
+    last_name: String,
+    middle_name: Option<String>,
+    phone: String,
+    email: Option<String>,
+    password: String,
+    /// Value of `ADMIN_BOOTSTRAP_TOKEN`, required to create the first administrator without
+    /// authentication
+    bootstrap_token: Option<String>,
+}
+
+/// Creates a district administrator. Without authentication only the first one can be created,
+/// given the bootstrap token configured on the server
+#[utoipa::path(
+    post,
+    path = "/schools/admins",
+    tag = "Schools",
+    request_body = CreateAdminRequest,
+    responses((status = 200, body = Employee), (status = 401), (status = 403))
+)]
+async fn create_admin(
+    State(state): RouteState,
+    claims: Option<Claims>,
+    Json(data): Json<CreateAdminRequest>,
+) -> RouteResult<Json<Employee>> {
+    let CreateAdminRequest {
+        first_name,
+        last_name,
+        middle_name,
+        phone,
+        email,
+        password,
+        bootstrap_token,
+    } = data;
+
+    match &claims {
+        Some(claims) => roles::ensure_admin(claims)?,
+        None => {
+            let expected = var("ADMIN_BOOTSTRAP_TOKEN").ok().filter(|t| !t.is_empty());
+            let valid = expected
+                .zip(bootstrap_token)
+                .is_some_and(|(expected, token)| hash_token(&expected) == hash_token(&token));
+            if !valid {
+                fail!(!UNAUTHORIZED, "Необходима авторизация");
+            }
+        }
+    }
+
+    let password_hash = passwords::hash(&state.db, &password).await?;
+
+    let mut tx = state.db.begin().await?;
+    if claims.is_none() {
+        // Concurrent bootstrap requests wait for each other, so only one of them succeeds
+        sqlx::query("LOCK TABLE Employees IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut *tx)
+            .await?;
+        let exists = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM Employees WHERE role = 'admin')",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if exists {
+            fail!(!UNAUTHORIZED, "Необходима авторизация");
+        }
+    }
+    let result = sqlx::query_as::<_, Employee>(
+        "
+            INSERT INTO Employees(first_name, last_name, middle_name, phone, password_hash, email, role)
+            VALUES ($1, $2, $3, $4, $5, $6, 'admin')
+            RETURNING *
+        ",
+    )
+    .bind(first_name)
+    .bind(last_name)
+    .bind(middle_name)
+    .bind(phone)
+    .bind(password_hash)
+    .bind(email)
+    .fetch_one(&mut *tx)
+    .await;
+
+    let admin = match result {
+        Ok(val) => val,
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
+            fail!(
+                !BAD_REQUEST,
+                "Работник с таким номером телефона уже существует"
+            ),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        claims.as_ref(),
+        AuditAction::Create,
+        AuditEntity::Admin,
+        Some(admin.id),
+        None,
+        Some(&admin),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(admin))
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(fetch, create, update, create_admin),
+        components(schemas(School, Employee, CreateOrUpdateSchoolRequest, CreateAdminRequest))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(fetch).post(create))
+        .route("/:id", put(update))
+        .route("/admins", post(create_admin))
+}
@@ -0,0 +1,183 @@
+use super::{Json, Query, RouteResult, RouteState};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Employee, Parent, Role, Student, Teacher},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+/// Roles which can be searched for
+const SEARCHABLE: &[Role] = &[Role::Student, Role::Teacher, Role::Principal, Role::Parent];
+
+/// Person found by the search, tagged with its role
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "role")]
+enum SearchResult {
+    Student(Student),
+    Teacher(Teacher),
+    Principal(Employee),
+    Parent(Parent),
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+struct Search {
+    /// Parts of the full name in any order, typos and ё written as е are tolerated
+    q: String,
+    /// Roles to search for, all of students, teachers, principals and parents by default
+    #[serde(default)]
+    roles: Vec<Role>,
+    count: Option<i64>,
+}
+
+/// Condition matching the full name of people in the table against the query `Q.q`. It repeats
+/// the expressions of the search indexes, see `search` migration
+fn matches(table: &str) -> String {
+    let name = format!("search_name({table}.first_name, {table}.last_name, {table}.middle_name)");
+    format!(
+        "
+            ({name} % Q.q OR Q.q <% {name} OR
+            to_tsvector('simple', {name}) @@ plainto_tsquery('simple', Q.q))
+        "
+    )
+}
+
+/// Searches students, teachers, principals and parents of the school by name, the most similar
+/// first. Only available to employees
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "Search",
+    params(Search),
+    responses((status = 200, body = Vec<SearchResult>), (status = 400), (status = 403))
+)]
+async fn search(
+    State(state): RouteState,
+    claims: Claims,
+    Query(query): Query<Search>,
+) -> RouteResult<Json<Vec<SearchResult>>> {
+    let Search { q, roles, count } = query;
+
+    if !claims.role.is_employee() {
+        fail!(!FORBIDDEN, "Недостаточно прав");
+    }
+    let school_id = claims.school()?;
+
+    let q = q.trim();
+    if q.is_empty() {
+        fail!(!BAD_REQUEST, "Пустой поисковый запрос", "q");
+    }
+    let roles = match roles.is_empty() {
+        true => SEARCHABLE.to_vec(),
+        false => roles,
+    };
+    let count = count.unwrap_or(20).clamp(1, 50);
+
+    let found = sqlx::query_as::<_, (Role, i32)>(&format!(
+        "
+            WITH Q AS (SELECT search_text($1) AS q)
+            SELECT role, id FROM (
+                SELECT 'student'::Role AS role, S.id, search_name(S.first_name, S.last_name, S.middle_name) AS name
+                FROM Students S, Q
+                WHERE S.school_id = $2 AND S.archived_at IS NULL AND {students}
+                UNION ALL
+                SELECT E.role, E.id, search_name(E.first_name, E.last_name, E.middle_name)
+                FROM Employees E, Q
+                WHERE
+                    E.school_id = $2 AND E.archived_at IS NULL AND
+                    E.role IN ('teacher', 'principal') AND {employees}
+                UNION ALL
+                SELECT 'parent', P.id, search_name(P.first_name, P.last_name, P.middle_name)
+                FROM Parents P, Q
+                WHERE P.school_id = $2 AND {parents}
+            ) T, Q
+            WHERE role = any($3)
+            ORDER BY greatest(similarity(name, Q.q), word_similarity(Q.q, name)) DESC, role, id
+            LIMIT $4
+        ",
+        students = matches("S"),
+        employees = matches("E"),
+        parents = matches("P"),
+    ))
+    .bind(q)
+    .bind(school_id)
+    .bind(&roles)
+    .bind(count)
+    .fetch_all(&state.db)
+    .await?;
+
+    let ids = |role| {
+        found
+            .iter()
+            .filter(|(r, _)| *r == role)
+            .map(|(_, id)| *id)
+            .collect::<Vec<_>>()
+    };
+    let mut students = sqlx::query_as::<_, Student>("SELECT * FROM Students WHERE id = any($1)")
+        .bind(ids(Role::Student))
+        .fetch_all(&state.db)
+        .await?;
+    let mut teachers = sqlx::query_as::<_, Teacher>(
+        "
+            SELECT * FROM Teachers
+            JOIN Employees ON Employees.id = Teachers.employee_id
+            WHERE id = any($1)
+        ",
+    )
+    .bind(ids(Role::Teacher))
+    .fetch_all(&state.db)
+    .await?;
+    let mut principals =
+        sqlx::query_as::<_, Employee>("SELECT * FROM Employees WHERE id = any($1)")
+            .bind(ids(Role::Principal))
+            .fetch_all(&state.db)
+            .await?;
+    let mut parents = sqlx::query_as::<_, Parent>("SELECT * FROM Parents WHERE id = any($1)")
+        .bind(ids(Role::Parent))
+        .fetch_all(&state.db)
+        .await?;
+
+    // Keep the order of relevance
+    let results = found
+        .iter()
+        .filter_map(|&(role, id)| match role {
+            Role::Student => students
+                .iter()
+                .position(|s| s.id == id)
+                .map(|i| SearchResult::Student(students.swap_remove(i))),
+            Role::Teacher => teachers
+                .iter()
+                .position(|t| t.employee.id == id)
+                .map(|i| SearchResult::Teacher(teachers.swap_remove(i))),
+            Role::Principal => principals
+                .iter()
+                .position(|p| p.id == id)
+                .map(|i| SearchResult::Principal(principals.swap_remove(i))),
+            Role::Parent => parents
+                .iter()
+                .position(|p| p.id == id)
+                .map(|i| SearchResult::Parent(parents.swap_remove(i))),
+            Role::Staff | Role::Admin => None,
+        })
+        .collect();
+
+    Ok(Json(results))
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(search),
+        components(schemas(SearchResult, Student, Teacher, Employee, Parent, Role))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(search))
+}
@@ -0,0 +1,336 @@
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    auth::{hash_token, random_token},
+    roles, Json, Path, RouteResult, RouteState, Secret,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{ApiKey, ApiScope, Permission, ServiceAccount},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::{OpenApi, ToSchema};
+
+/// Keys are told apart from other bearer tokens by this prefix
+const KEY_PREFIX: &str = "sk_";
+
+/// Fetches service accounts with their keys
+#[utoipa::path(
+    get,
+    path = "/service-accounts",
+    tag = "Service accounts",
+    responses((status = 200, body = Vec<ServiceAccount>), (status = 403))
+)]
+async fn fetch(State(state): RouteState, claims: Claims) -> RouteResult<Json<Vec<ServiceAccount>>> {
+    roles::ensure(&state.db, &claims, Permission::ServiceAccountsManage).await?;
+    let school_id = claims.school()?;
+
+    let mut accounts = sqlx::query_as::<_, ServiceAccount>(
+        "SELECT * FROM ServiceAccounts WHERE school_id = $1 ORDER BY id",
+    )
+    .bind(school_id)
+    .fetch_all(&state.db)
+    .await?;
+    let mut keys = sqlx::query_as::<_, ApiKey>(
+        "
+            SELECT ApiKeys.* FROM ApiKeys
+            JOIN ServiceAccounts A ON A.id = service_account_id
+            WHERE A.school_id = $1
+            ORDER BY ApiKeys.id
+        ",
+    )
+    .bind(school_id)
+    .fetch_all(&state.db)
+    .await?;
+
+    for account in &mut accounts {
+        let (own, rest) = keys
+            .into_iter()
+            .partition(|k| k.service_account_id == account.id);
+        account.keys = own;
+        keys = rest;
+    }
+
+    Ok(Json(accounts))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct CreateServiceAccountRequest {
+    name: String,
+}
+
+/// Creates a service account, it has no access until a key is issued
+#[utoipa::path(
+    post,
+    path = "/service-accounts",
+    tag = "Service accounts",
+    request_body = CreateServiceAccountRequest,
+    responses((status = 200, body = ServiceAccount), (status = 403))
+)]
+async fn create(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CreateServiceAccountRequest>,
+) -> RouteResult<Json<ServiceAccount>> {
+    let CreateServiceAccountRequest { name } = data;
+
+    roles::ensure(&state.db, &claims, Permission::ServiceAccountsManage).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let result = sqlx::query_as::<_, ServiceAccount>(
+        "INSERT INTO ServiceAccounts(name, created_by, school_id) VALUES ($1, $2, $3) RETURNING *",
+    )
+    .bind(name)
+    .bind(claims.user_id)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
+    .await;
+
+    let account = match result {
+        Ok(account) => account,
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
+            fail!(
+                !BAD_REQUEST,
+                "Сервисный аккаунт с таким именем уже существует",
+                "name"
+            ),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::ServiceAccount,
+        Some(account.id),
+        None,
+        Some(&account),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(account))
+}
+
+/// Deletes the service account together with its keys
+#[utoipa::path(
+    delete,
+    path = "/service-accounts/{id}",
+    tag = "Service accounts",
+    params(("id" = i32, Path, description = "Id of the service account to delete")),
+    responses((status = 200), (status = 403))
+)]
+async fn remove(Path(id): Path<i32>, State(state): RouteState, claims: Claims) -> RouteResult {
+    roles::ensure(&state.db, &claims, Permission::ServiceAccountsManage).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(account) = sqlx::query_as::<_, ServiceAccount>(
+        "DELETE FROM ServiceAccounts WHERE id = $1 AND school_id = $2 RETURNING *",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Сервисный аккаунт с таким ИД не существует");
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Delete,
+        AuditEntity::ServiceAccount,
+        Some(id),
+        Some(&account),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct CreateKeyRequest {
+    scopes: Vec<ApiScope>,
+    /// The key never expires if not set
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    expires_at: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CreateKeyResponse {
+    /// Pass it in the `Authorization: Bearer` header. It is not shown again
+    key: String,
+    #[serde(flatten)]
+    info: ApiKey,
+}
+
+/// Issues a new API key of the service account
+#[utoipa::path(
+    post,
+    path = "/service-accounts/{id}/keys",
+    tag = "Service accounts",
+    params(("id" = i32, Path, description = "Id of the service account")),
+    request_body = CreateKeyRequest,
+    responses((status = 200, body = CreateKeyResponse), (status = 403))
+)]
+async fn create_key(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CreateKeyRequest>,
+) -> RouteResult<Secret<CreateKeyResponse>> {
+    let CreateKeyRequest {
+        mut scopes,
+        expires_at,
+    } = data;
+
+    roles::ensure(&state.db, &claims, Permission::ServiceAccountsManage).await?;
+    let school_id = claims.school()?;
+
+    scopes.sort_by_key(|&s| s as u8);
+    scopes.dedup();
+    if scopes.is_empty() {
+        fail!(
+            !BAD_REQUEST,
+            "Необходимо указать хотя бы одну область доступа",
+            "scopes"
+        );
+    }
+    if expires_at.is_some_and(|e| e <= OffsetDateTime::now_utc()) {
+        fail!(
+            !BAD_REQUEST,
+            "Срок действия должен быть в будущем",
+            "expires_at"
+        );
+    }
+
+    let key = format!("{KEY_PREFIX}{}", random_token(40));
+    let mut tx = state.db.begin().await?;
+    let Some(info) = sqlx::query_as::<_, ApiKey>(
+        "
+            INSERT INTO ApiKeys(service_account_id, prefix, key_hash, scopes, expires_at)
+            SELECT id, $2, $3, $4, $5 FROM ServiceAccounts WHERE id = $1 AND school_id = $6
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(&key[..KEY_PREFIX.len() + 6])
+    .bind(hash_token(&key))
+    .bind(scopes)
+    .bind(expires_at)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Сервисный аккаунт с таким ИД не существует");
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::ApiKey,
+        Some(info.id),
+        None,
+        Some(&info),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Secret(CreateKeyResponse { key, info }))
+}
+
+/// Revokes the API key, requests with it are rejected immediately
+#[utoipa::path(
+    delete,
+    path = "/service-accounts/{id}/keys/{key_id}",
+    tag = "Service accounts",
+    params(
+        ("id" = i32, Path, description = "Id of the service account"),
+        ("key_id" = i32, Path, description = "Id of the key to revoke"),
+    ),
+    responses((status = 200), (status = 403))
+)]
+async fn revoke_key(
+    Path((id, key_id)): Path<(i32, i32)>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult {
+    roles::ensure(&state.db, &claims, Permission::ServiceAccountsManage).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(before) = sqlx::query_as::<_, ApiKey>(
+        "
+            SELECT ApiKeys.* FROM ApiKeys
+            JOIN ServiceAccounts A ON A.id = service_account_id
+            WHERE
+                ApiKeys.id = $2 AND service_account_id = $1 AND A.school_id = $3 AND
+                revoked_at IS NULL
+            FOR UPDATE OF ApiKeys
+        ",
+    )
+    .bind(id)
+    .bind(key_id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Действующий ключ с таким ИД не существует");
+    };
+
+    let after = sqlx::query_as::<_, ApiKey>(
+        "UPDATE ApiKeys SET revoked_at = now() WHERE id = $1 RETURNING *",
+    )
+    .bind(key_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::ApiKey,
+        Some(key_id),
+        Some(&before),
+        Some(&after),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(fetch, create, remove, create_key, revoke_key),
+        components(schemas(
+            ServiceAccount,
+            ApiKey,
+            ApiScope,
+            CreateServiceAccountRequest,
+            CreateKeyRequest,
+            CreateKeyResponse
+        ))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(fetch).post(create))
+        .route("/:id", delete(remove))
+        .route("/:id/keys", post(create_key))
+        .route("/:id/keys/:key_id", delete(revoke_key))
+}
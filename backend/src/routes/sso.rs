@@ -0,0 +1,241 @@
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    auth::{random_token, start_session, Restrictions},
+    Query, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::ClientIp,
+    models::{Participant, Role},
+    oidc::IdToken,
+    AppState,
+};
+use axum::{extract::State, response::Redirect, routing::*};
+use axum_extra::{
+    extract::{
+        cookie::{Cookie, SameSite},
+        CookieJar,
+    },
+    headers::UserAgent,
+    TypedHeader,
+};
+use serde::Deserialize;
+use sqlx::PgConnection;
+use time::Duration;
+use utoipa::{IntoParams, OpenApi};
+
+/// Holds the state of the login in the browser which started it, so a callback URL opened
+/// elsewhere cannot log its visitor into someone else's account
+const STATE_COOKIE: &str = "sso_state";
+
+fn state_cookie(value: String) -> Cookie<'static> {
+    Cookie::build((STATE_COOKIE, value))
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .path("/auth/sso")
+        .max_age(Duration::minutes(10))
+        .build()
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+struct LoginQuery {
+    /// Path to return to after the login, `/` by default
+    redirect: Option<String>,
+}
+
+/// Starts the login of an employee through the identity provider of the district
+#[utoipa::path(
+    get,
+    path = "/auth/sso/login",
+    tag = "Single sign-on",
+    params(LoginQuery),
+    responses((status = 303, description = "Redirect to the identity provider"), (status = 404))
+)]
+async fn login(
+    State(state): RouteState,
+    jar: CookieJar,
+    Query(query): Query<LoginQuery>,
+) -> RouteResult<(CookieJar, Redirect)> {
+    let LoginQuery { redirect } = query;
+
+    let Some(oidc) = &state.oidc else {
+        fail!(!NOT_FOUND, "Вход через SSO не настроен");
+    };
+
+    // Only local paths, so the login cannot be used to redirect elsewhere
+    let redirect = redirect
+        .filter(|r| r.starts_with('/') && !r.starts_with("//") && !r.contains('\\'))
+        .unwrap_or_else(|| "/".into());
+    let (sso_state, nonce, code_verifier) = (random_token(32), random_token(32), random_token(64));
+
+    let url = oidc
+        .authorization_url(&sso_state, &nonce, &code_verifier)
+        .await?;
+    sqlx::query(
+        "
+            INSERT INTO SsoLogins(state, nonce, code_verifier, redirect_to, expires_at)
+            VALUES ($1, $2, $3, $4, now() + interval '10 minutes')
+        ",
+    )
+    .bind(&sso_state)
+    .bind(&nonce)
+    .bind(&code_verifier)
+    .bind(redirect)
+    .execute(&state.db)
+    .await?;
+
+    Ok((jar.add(state_cookie(sso_state)), Redirect::to(&url)))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct CallbackQuery {
+    code: Option<String>,
+    state: String,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// Finds the employee of the external subject. Unknown subjects are linked to the employee with
+/// the same verified email, so later changes of the email do not break the login
+async fn find_employee(db: &mut PgConnection, token: &IdToken) -> RouteResult<(i32, Role)> {
+    let linked = sqlx::query_as::<_, (i32, Role)>(
+        "
+            SELECT id, role FROM EmployeeIdentities
+            JOIN Employees ON id = employee_id
+            WHERE issuer = $1 AND subject = $2 AND archived_at IS NULL
+        ",
+    )
+    .bind(&token.iss)
+    .bind(&token.sub)
+    .fetch_optional(&mut *db)
+    .await?;
+    if let Some(employee) = linked {
+        return Ok(employee);
+    }
+
+    let Some(email) = token.email.as_ref().filter(|_| token.email_verified) else {
+        fail!(!FORBIDDEN, "Сотрудник не найден");
+    };
+    let matches = sqlx::query_as::<_, (i32, Role, Option<i32>)>(
+        "
+            SELECT id, role, school_id FROM Employees
+            WHERE lower(email) = lower($1) AND archived_at IS NULL
+            LIMIT 2
+        ",
+    )
+    .bind(email)
+    .fetch_all(&mut *db)
+    .await?;
+    let [(id, role, school_id)] = matches[..] else {
+        fail!(!FORBIDDEN, "Сотрудник не найден");
+    };
+
+    tracing::info!("Linking SSO subject {} to employee #{id}", token.sub);
+    sqlx::query("INSERT INTO EmployeeIdentities(issuer, subject, employee_id) VALUES ($1, $2, $3)")
+        .bind(&token.iss)
+        .bind(&token.sub)
+        .bind(id)
+        .execute(&mut *db)
+        .await?;
+    audit::record_user(
+        db,
+        Participant { role, user_id: id },
+        school_id,
+        AuditAction::Create,
+        AuditEntity::SsoIdentity,
+        Some(id),
+        Some(&serde_json::json!({ "issuer": token.iss, "subject": token.sub, "email": email })),
+    )
+    .await?;
+
+    Ok((id, role))
+}
+
+/// Completes the login after the identity provider redirects back.
+/// Two-factor authentication is left to the identity provider
+#[utoipa::path(
+    get,
+    path = "/auth/sso/callback",
+    tag = "Single sign-on",
+    params(CallbackQuery),
+    responses((status = 303, description = "Redirect to the page the login was started from"))
+)]
+async fn callback(
+    State(state): RouteState,
+    jar: CookieJar,
+    ClientIp(ip): ClientIp,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    Query(query): Query<CallbackQuery>,
+) -> RouteResult<(CookieJar, Redirect)> {
+    let CallbackQuery {
+        code,
+        state: sso_state,
+        error,
+        error_description,
+    } = query;
+
+    let Some(oidc) = &state.oidc else {
+        fail!(!NOT_FOUND, "Вход через SSO не настроен");
+    };
+
+    if jar.get(STATE_COOKIE).map(Cookie::value) != Some(sso_state.as_str()) {
+        fail!(!BAD_REQUEST, "Вход начат в другом браузере, войдите снова");
+    }
+    let jar = jar.remove(state_cookie(String::new()));
+
+    // Consumed even if the login fails, every attempt starts over
+    let Some((nonce, code_verifier, redirect_to)) = sqlx::query_as::<_, (String, String, String)>(
+        "
+            DELETE FROM SsoLogins WHERE state = $1 AND expires_at > now()
+            RETURNING nonce, code_verifier, redirect_to
+        ",
+    )
+    .bind(&sso_state)
+    .fetch_optional(&state.db)
+    .await?
+    else {
+        fail!(!BAD_REQUEST, "Время входа истекло, войдите снова");
+    };
+
+    let Some(code) = code else {
+        tracing::warn!("SSO login failed: {error:?} {error_description:?}");
+        fail!(!UNAUTHORIZED, "Не удалось войти через SSO");
+    };
+
+    let token = oidc.exchange(&code, &code_verifier, &nonce).await?;
+
+    let mut tx = state.db.begin().await?;
+    let (employee_id, role) = find_employee(&mut tx, &token).await?;
+    let jar = start_session(
+        &mut tx,
+        jar,
+        role,
+        employee_id,
+        user_agent,
+        ip,
+        Restrictions {
+            totp_exempt: true,
+            ..Default::default()
+        },
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok((jar, Redirect::to(&redirect_to)))
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(paths(login, callback))]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/login", get(login))
+        .route("/callback", get(callback))
+}
@@ -0,0 +1,413 @@
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    auth,
+    pagination::{EmployeePage, Order, Page, Paged, Pagination},
+    passwords, roles,
+    versions::{IfMatch, Tagged},
+    Json, Path, Query, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Employee, Permission, Role},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use serde::Deserialize;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+#[derive(Deserialize, IntoParams)]
+#[serde(deny_unknown_fields)]
+struct Fetch {
+    name: Option<String>,
+    id: Option<i32>,
+    /// Fetch archived staff members instead of active ones
+    #[serde(default)]
+    archived: bool,
+    count: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Fetches staff members
+#[utoipa::path(
+    get,
+    path = "/staff",
+    tag = "Staff management",
+    params(Fetch),
+    responses((status = 200, body = EmployeePage))
+)]
+async fn fetch(
+    State(state): RouteState,
+    claims: Claims,
+    Query(query): Query<Fetch>,
+) -> RouteResult<Json<Page<Employee>>> {
+    let Fetch {
+        name,
+        id,
+        archived,
+        count,
+        cursor,
+    } = query;
+
+    let school_id = claims.school()?;
+    let pagination = Pagination::new(count, cursor, Order::ID)?;
+
+    let query = pagination.query(
+        "
+            SELECT * FROM Employees
+            WHERE
+                coalesce(id = $4, true) AND
+                coalesce(search_name(first_name, last_name, middle_name) LIKE ('%' || search_text($5) || '%'), true) AND
+                role = 'staff' AND
+                school_id = $6 AND
+                (archived_at IS NOT NULL) = $7
+        ",
+    );
+    let staff = pagination
+        .bind(sqlx::query_as::<_, Paged<Employee>>(&query))
+        .bind(id)
+        .bind(name)
+        .bind(school_id)
+        .bind(archived)
+        .fetch_all(&state.db)
+        .await?;
+
+    Ok(Json(pagination.finish(staff)))
+}
+
+/// Fetches a staff member by id, the `ETag` header carries its version
+#[utoipa::path(
+    get,
+    path = "/staff/{id}",
+    tag = "Staff management",
+    params(("id" = i32, Path, description = "Id of the staff member")),
+    responses((status = 200, body = Employee), (status = 404))
+)]
+async fn fetch_one(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Employee>> {
+    let school_id = claims.school()?;
+
+    let Some(employee) = sqlx::query_as::<_, Employee>(
+        "
+            SELECT * FROM Employees
+            WHERE id = $1 AND role = 'staff' AND school_id = $2
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&state.db)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Сотрудника с таким ИД не существует");
+    };
+
+    Ok(Tagged(employee))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct CreateOrUpdateStaffRequest {
+    first_name: String,
+    last_name: String,
+    middle_name: Option<String>,
+    phone: String,
+    /// Kept when omitted on update, `PATCH` with `null` clears it
+    email: Option<String>,
+    /// Required for new staff members
+    password: Option<String>,
+}
+
+/// Creates a staff member. Access is granted by assigning access roles
+#[utoipa::path(
+    post,
+    path = "/staff",
+    tag = "Staff management",
+    request_body = CreateOrUpdateStaffRequest,
+    responses((status = 200, body = Employee), (status = 403))
+)]
+async fn create(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CreateOrUpdateStaffRequest>,
+) -> RouteResult<Tagged<Employee>> {
+    let CreateOrUpdateStaffRequest {
+        first_name,
+        last_name,
+        middle_name,
+        phone,
+        email,
+        password,
+    } = data;
+
+    roles::ensure(&state.db, &claims, Permission::StaffWrite).await?;
+    let school_id = claims.school()?;
+
+    let password = password.ok_or(fail!(BAD_REQUEST, "Необходим пароль для сотрудника"))?;
+    let password_hash = passwords::hash(&state.db, &password).await?;
+
+    let mut tx = state.db.begin().await?;
+    let result = sqlx::query_as::<_, Employee>(
+        "
+            INSERT INTO Employees(
+                first_name, last_name, middle_name, phone, password_hash, email, role,
+                password_change_required, school_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, 'staff', true, $7)
+            RETURNING *
+        ",
+    )
+    .bind(first_name)
+    .bind(last_name)
+    .bind(middle_name)
+    .bind(phone)
+    .bind(password_hash)
+    .bind(email)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
+    .await;
+
+    let employee = match result {
+        Ok(val) => val,
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
+            fail!(
+                !BAD_REQUEST,
+                "Работник с таким номером телефона уже существует"
+            ),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::Staff,
+        Some(employee.id),
+        None,
+        Some(&employee),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(employee))
+}
+
+/// Updates a staff member by id
+#[utoipa::path(
+    put,
+    path = "/staff/{id}",
+    tag = "Staff management",
+    params(("id" = i32, Path, description = "Id of the staff member to update")),
+    request_body = CreateOrUpdateStaffRequest,
+    responses((status = 200, body = Employee), (status = 403), (status = 404), (status = 412), (status = 428))
+)]
+async fn update(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+    Json(data): Json<CreateOrUpdateStaffRequest>,
+) -> RouteResult<Tagged<Employee>> {
+    let CreateOrUpdateStaffRequest {
+        first_name,
+        last_name,
+        middle_name,
+        phone,
+        email,
+        password,
+    } = data;
+
+    roles::ensure(&state.db, &claims, Permission::StaffWrite).await?;
+    let school_id = claims.school()?;
+
+    let password_hash = match password {
+        Some(password) => Some(passwords::hash(&state.db, &password).await?),
+        None => None,
+    };
+
+    let mut tx = state.db.begin().await?;
+    let Some(before) = sqlx::query_as::<_, Employee>(
+        "
+            SELECT * FROM Employees
+            WHERE id = $1 AND role = 'staff' AND school_id = $2 AND archived_at IS NULL
+            FOR UPDATE
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Сотрудника с таким ИД не существует");
+    };
+    if_match.check(Some(&before))?;
+
+    let result = sqlx::query_as::<_, Employee>(
+        "
+            UPDATE Employees
+            SET
+                first_name = $2,
+                last_name = $3,
+                middle_name = $4,
+                phone = $5,
+                password_hash = coalesce($6, password_hash),
+                password_change_required = password_change_required OR $6 IS NOT NULL,
+                email = coalesce($7, email)
+            WHERE id = $1 AND school_id = $8 AND archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(first_name)
+    .bind(last_name)
+    .bind(middle_name)
+    .bind(phone)
+    .bind(password_hash)
+    .bind(email)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
+    .await;
+
+    let employee = match result {
+        Ok(val) => val,
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
+            fail!(
+                !BAD_REQUEST,
+                "Работник с таким номером телефона уже существует"
+            ),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Staff,
+        Some(id),
+        Some(&before),
+        Some(&employee),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(employee))
+}
+
+/// Archives a staff member by id
+#[utoipa::path(
+    delete,
+    path = "/staff/{id}",
+    tag = "Staff management",
+    params(("id" = i32, Path, description = "Id of the staff member to archive")),
+    responses((status = 200), (status = 403), (status = 404), (status = 412), (status = 428))
+)]
+async fn remove(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+) -> RouteResult {
+    roles::ensure(&state.db, &claims, Permission::StaffWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    if_match
+        .lock(&mut tx, "Employees", id, Some(school_id))
+        .await?;
+    let Some(employee) = sqlx::query_as::<_, Employee>(
+        "
+            UPDATE Employees SET archived_at = now()
+            WHERE id = $1 AND role = 'staff' AND school_id = $2 AND archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Сотрудника с таким ИД не существует");
+    };
+    auth::revoke_sessions(&mut *tx, Role::Staff, id).await?;
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Archive,
+        AuditEntity::Staff,
+        Some(id),
+        None,
+        Some(&employee),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Restores an archived staff member by id
+#[utoipa::path(
+    post,
+    path = "/staff/{id}/restore",
+    tag = "Staff management",
+    params(("id" = i32, Path, description = "Id of the staff member to restore")),
+    responses((status = 200, body = Employee), (status = 403), (status = 404))
+)]
+async fn restore(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Employee>> {
+    roles::ensure(&state.db, &claims, Permission::StaffWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(employee) = sqlx::query_as::<_, Employee>(
+        "
+            UPDATE Employees SET archived_at = NULL
+            WHERE id = $1 AND role = 'staff' AND school_id = $2 AND archived_at IS NOT NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Архивного сотрудника с таким ИД не существует");
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Restore,
+        AuditEntity::Staff,
+        Some(id),
+        None,
+        Some(&employee),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(employee))
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(fetch, fetch_one, create, update, remove, restore),
+        components(schemas(Employee, EmployeePage, CreateOrUpdateStaffRequest))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(fetch).post(create))
+        .route("/:id", get(fetch_one).put(update).delete(remove))
+        .route("/:id/restore", post(restore))
+}
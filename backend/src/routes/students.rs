@@ -1,20 +1,78 @@
-use super::{Json, Path, Query, RouteResult, RouteState};
-use crate::{fail, models::Student, AppState};
-use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    auth,
+    dependencies::{self, Dependency, DependencyKind, Source},
+    not_null, nullable,
+    pagination::{Order, Page, Paged, Pagination, StudentPage},
+    passwords, roles,
+    versions::{IfMatch, Tagged},
+    Json, Path, Query, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Permission, Role, Student},
+    AppState,
+};
 use axum::{extract::State, routing::*};
-use rand::rngs::OsRng;
 use serde::Deserialize;
+use sqlx::PgConnection;
+use time::OffsetDateTime;
 use utoipa::{IntoParams, OpenApi, ToSchema};
 
+/// Marks and parents are kept in the archive, so nothing blocks archiving a student
+const DEPENDENCIES: &[Source] = &[
+    Source {
+        kind: DependencyKind::Mark,
+        blocking: false,
+        query: "SELECT id FROM Marks WHERE student_id = $1 AND school_id = $2",
+    },
+    Source {
+        kind: DependencyKind::Parent,
+        blocking: false,
+        query: "
+            SELECT id FROM ParentStudent
+            JOIN Parents ON id = parent_id
+            WHERE student_id = $1 AND school_id = $2
+        ",
+    },
+];
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum StudentSort {
+    #[default]
+    Id,
+    LastName,
+    EnrolledAt,
+}
+
 #[derive(Deserialize, IntoParams)]
 #[serde(deny_unknown_fields)]
 struct Fetch {
     name: Option<String>,
     id: Option<i32>,
+    /// Exact phone number
+    phone: Option<String>,
     #[serde(default)]
     class_ids: Vec<i32>,
+    #[serde(default)]
+    exclude_ids: Vec<i32>,
+    #[serde(default)]
+    exclude_class_ids: Vec<i32>,
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    enrolled_after: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    enrolled_before: Option<OffsetDateTime>,
+    /// Fetch archived students instead of active ones
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    sort: StudentSort,
+    #[serde(default)]
+    desc: bool,
     count: Option<i64>,
-    offset: Option<i64>,
+    cursor: Option<String>,
 }
 
 /// Fetches students
@@ -23,42 +81,187 @@ struct Fetch {
     path = "/students",
     tag = "Students management",
     params(Fetch),
-    responses((status = 200, body = Vec<Student>))
+    responses((status = 200, body = StudentPage))
 )]
 async fn fetch(
     State(state): RouteState,
+    claims: Claims,
     Query(query): Query<Fetch>,
-) -> RouteResult<Json<Vec<Student>>> {
+) -> RouteResult<Json<Page<Student>>> {
     let Fetch {
         name,
         id,
+        phone,
         class_ids,
+        exclude_ids,
+        exclude_class_ids,
+        enrolled_after,
+        enrolled_before,
+        archived,
+        sort,
+        desc,
         count,
-        offset,
+        cursor,
     } = query;
 
-    let count = count.unwrap_or(50).clamp(0, 100);
-    let offset = offset.unwrap_or(0).clamp(0, 10000);
+    let school_id = claims.school()?;
+    let order = match sort {
+        StudentSort::Id => Order::ID,
+        StudentSort::LastName => Order::by("last_name", "TEXT"),
+        StudentSort::EnrolledAt => Order::by("enrolled_at", "TIMESTAMPTZ"),
+    };
 
-    let students = sqlx::query_as::<_, Student>(
+    let pagination = Pagination::new(count, cursor, order.desc(desc))?;
+    let query = pagination.query(
         r#"
             SELECT * FROM Students
             WHERE
-                coalesce(id = $3, true) AND
-                coalesce(first_name || last_name || middle_name ILIKE ('%' || $4 || '%'), true) AND
-                (class_id = ANY($5) OR cardinality($5) = 0)
-            LIMIT $1 OFFSET $2
+                school_id = $7 AND
+                (archived_at IS NOT NULL) = $8 AND
+                coalesce(id = $4, true) AND
+                coalesce(search_name(first_name, last_name, middle_name) LIKE ('%' || search_text($5) || '%'), true) AND
+                (class_id = ANY($6) OR cardinality($6) = 0) AND
+                coalesce(phone = $9, true) AND
+                NOT (id = ANY($10)) AND
+                NOT (class_id = ANY($11)) AND
+                enrolled_at BETWEEN
+                    coalesce($12, '-infinity'::timestamptz) AND
+                    coalesce($13, 'infinity'::timestamptz)
         "#,
+    );
+    let students = pagination
+        .bind(sqlx::query_as::<_, Paged<Student>>(&query))
+        .bind(id)
+        .bind(name)
+        .bind(class_ids)
+        .bind(school_id)
+        .bind(archived)
+        .bind(phone)
+        .bind(exclude_ids)
+        .bind(exclude_class_ids)
+        .bind(enrolled_after)
+        .bind(enrolled_before)
+        .fetch_all(&state.db)
+        .await?;
+
+    Ok(Json(pagination.finish(students)))
+}
+
+/// Fetches a student by id, the `ETag` header carries its version
+#[utoipa::path(
+    get,
+    path = "/students/{id}",
+    tag = "Students management",
+    params(("id" = i32, Path, description = "Id of the student")),
+    responses((status = 200, body = Student), (status = 404))
+)]
+async fn fetch_one(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Student>> {
+    let school_id = claims.school()?;
+
+    let Some(student) =
+        sqlx::query_as::<_, Student>("SELECT * FROM Students WHERE id = $1 AND school_id = $2")
+            .bind(id)
+            .bind(school_id)
+            .fetch_optional(&state.db)
+            .await?
+    else {
+        fail!(!NOT_FOUND, "Ученика с таким ИД не существует");
+    };
+
+    Ok(Tagged(student))
+}
+
+/// Moves active students of the class to another one
+pub(super) async fn reassign_class(
+    db: &mut PgConnection,
+    claims: &Claims,
+    school_id: i32,
+    from: i32,
+    to: i32,
+) -> RouteResult {
+    let before = sqlx::query_as::<_, Student>(
+        "
+            SELECT * FROM Students
+            WHERE class_id = $1 AND school_id = $2 AND archived_at IS NULL
+            ORDER BY id
+            FOR UPDATE
+        ",
     )
-    .bind(count)
-    .bind(offset)
-    .bind(id)
-    .bind(name)
-    .bind(class_ids)
-    .fetch_all(&state.db)
+    .bind(from)
+    .bind(school_id)
+    .fetch_all(&mut *db)
+    .await?;
+    let after = sqlx::query_as::<_, Student>(
+        "
+            WITH Updated AS (
+                UPDATE Students SET class_id = $3
+                WHERE class_id = $1 AND school_id = $2 AND archived_at IS NULL
+                RETURNING *
+            )
+            SELECT * FROM Updated ORDER BY id
+        ",
+    )
+    .bind(from)
+    .bind(school_id)
+    .bind(to)
+    .fetch_all(&mut *db)
     .await?;
 
-    Ok(Json(students))
+    for (before, after) in before.iter().zip(&after) {
+        audit::record(
+            db,
+            Some(claims),
+            AuditAction::Update,
+            AuditEntity::Student,
+            Some(after.id),
+            Some(before),
+            Some(after),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Rejects archived classes, they are only kept for the history
+async fn ensure_active_class(db: &mut PgConnection, class_id: i32) -> RouteResult {
+    let archived = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM Classes WHERE id = $1 AND archived_at IS NOT NULL)",
+    )
+    .bind(class_id)
+    .fetch_one(db)
+    .await?;
+    if archived {
+        fail!(!BAD_REQUEST, "Класс находится в архиве", "class_id");
+    }
+
+    Ok(())
+}
+
+/// Fetches rows referencing the student
+#[utoipa::path(
+    get,
+    path = "/students/{id}/dependencies",
+    tag = "Students management",
+    params(("id" = i32, Path, description = "Id of the student")),
+    responses((status = 200, body = Vec<Dependency>)),
+)]
+async fn fetch_dependencies(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Json<Vec<Dependency>>> {
+    roles::ensure(&state.db, &claims, Permission::StudentsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut conn = state.db.acquire().await?;
+    let dependencies = dependencies::collect(&mut conn, id, school_id, DEPENDENCIES).await?;
+
+    Ok(Json(dependencies))
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -69,6 +272,8 @@ struct CreateOrUpdateStudentRequest {
     pub middle_name: Option<String>,
     pub class_id: i32,
     pub phone: String,
+    /// Kept when omitted on update, `PATCH` with `null` clears it
+    pub email: Option<String>,
     pub password: Option<String>,
 }
 
@@ -82,31 +287,34 @@ struct CreateOrUpdateStudentRequest {
 )]
 async fn create(
     State(state): RouteState,
+    claims: Claims,
     Json(data): Json<CreateOrUpdateStudentRequest>,
-) -> RouteResult<Json<Student>> {
+) -> RouteResult<Tagged<Student>> {
     let CreateOrUpdateStudentRequest {
         first_name,
         last_name,
         middle_name,
         class_id,
         phone,
+        email,
         password,
     } = data;
 
-    let password_hash = Argon2::default()
-        .hash_password(
-            password
-                .ok_or(fail!(BAD_REQUEST, "Необходим пароль для ученика"))?
-                .as_bytes(),
-            &SaltString::generate(OsRng),
-        )
-        .unwrap()
-        .to_string();
+    roles::ensure(&state.db, &claims, Permission::StudentsWrite).await?;
+    let school_id = claims.school()?;
+
+    let password = password.ok_or(fail!(BAD_REQUEST, "Необходим пароль для ученика"))?;
+    let password_hash = passwords::hash(&state.db, &password).await?;
 
+    let mut tx = state.db.begin().await?;
+    ensure_active_class(&mut tx, class_id).await?;
     let result = sqlx::query_as::<_, Student>(
         r#"
-            INSERT INTO Students(first_name, last_name, middle_name, class_id, phone, password_hash)
-            VALUES ($1, $2, $3, $4, $5, $6)
+            INSERT INTO Students(
+                first_name, last_name, middle_name, class_id, phone, password_hash, email,
+                password_change_required, school_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, true, $8)
             RETURNING *
         "#,
     )
@@ -116,17 +324,33 @@ async fn create(
     .bind(class_id)
     .bind(phone)
     .bind(password_hash)
-    .fetch_one(&state.db)
+    .bind(email)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
     .await;
 
-    match result {
-        Ok(student) => Ok(Json(student)),
+    let student = match result {
+        Ok(student) => student,
         Err(err) if matches!(err.as_database_error(), Some(err) if err.is_foreign_key_violation()) =>
             fail!(!BAD_REQUEST, "Класс с таким ИД не существует"),
         Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
             fail!(!BAD_REQUEST, "Такой ученик уже существует в данном классе"),
-        Err(err) => Err(err.into()),
-    }
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::Student,
+        Some(student.id),
+        None,
+        Some(&student),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(student))
 }
 
 /// Updates a student by id
@@ -136,29 +360,43 @@ async fn create(
     tag = "Students management",
     params(("id" = i32, Path, description = "Id of the student to update")),
     request_body = CreateOrUpdateStudentRequest,
-    responses((status = 200, body = Student)),
+    responses((status = 200, body = Student), (status = 412), (status = 428)),
 )]
 async fn update(
     Path(id): Path<i32>,
     State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
     Json(data): Json<CreateOrUpdateStudentRequest>,
-) -> RouteResult<Json<Student>> {
+) -> RouteResult<Tagged<Student>> {
     let CreateOrUpdateStudentRequest {
         first_name,
         last_name,
         middle_name,
         class_id,
         phone,
+        email,
         password,
     } = data;
 
-    let password_hash = password.map(|p| {
-        Argon2::default()
-            .hash_password(p.as_bytes(), &SaltString::generate(OsRng))
-            .unwrap()
-            .to_string()
-    });
+    roles::ensure(&state.db, &claims, Permission::StudentsWrite).await?;
+    let school_id = claims.school()?;
+
+    let password_hash = match password {
+        Some(password) => Some(passwords::hash(&state.db, &password).await?),
+        None => None,
+    };
 
+    let mut tx = state.db.begin().await?;
+    let before = sqlx::query_as::<_, Student>(
+        "SELECT * FROM Students WHERE id = $1 AND school_id = $2 FOR UPDATE",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if_match.check(before.as_ref())?;
+    ensure_active_class(&mut tx, class_id).await?;
     let result = sqlx::query_as::<_, Student>(
         r#"
             UPDATE Students
@@ -168,9 +406,11 @@ async fn update(
                 middle_name = $4,
                 class_id = $5,
                 phone = $6,
-                password_hash = coalesce($7, password_hash)
+                password_hash = coalesce($7, password_hash),
+                password_change_required = password_change_required OR $7 IS NOT NULL,
+                email = coalesce($8, email)
             WHERE
-                id = $1
+                id = $1 AND school_id = $9 AND archived_at IS NULL
             RETURNING *
         "#,
     )
@@ -181,45 +421,305 @@ async fn update(
     .bind(class_id)
     .bind(phone)
     .bind(password_hash)
-    .fetch_optional(&state.db)
+    .bind(email)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
     .await;
 
-    match result {
-        Ok(Some(student)) => Ok(Json(student)),
+    let student = match result {
+        Ok(Some(student)) => student,
         Ok(None) => fail!(!BAD_REQUEST, "Ученик с таким ИД не существует"),
         Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
             fail!(!BAD_REQUEST, "Такой ученик уже существует в данном классе"),
         Err(err) if matches!(err.as_database_error(), Some(err) if err.is_foreign_key_violation()) =>
             fail!(!BAD_REQUEST, "Класс с таким ИД не существует"),
-        Err(err) => Err(err.into()),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Student,
+        Some(id),
+        before.as_ref(),
+        Some(&student),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(student))
+}
+
+/// Only the fields present are changed, `null` clears the optional ones
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct PatchStudentRequest {
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    first_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    last_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    middle_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<i32>)]
+    class_id: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    phone: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    email: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    password: Option<Option<String>>,
+}
+
+/// Changes some fields of a student by id
+#[utoipa::path(
+    patch,
+    path = "/students/{id}",
+    tag = "Students management",
+    params(("id" = i32, Path, description = "Id of the student to update")),
+    request_body = PatchStudentRequest,
+    responses((status = 200, body = Student), (status = 412), (status = 428)),
+)]
+async fn patch(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+    Json(data): Json<PatchStudentRequest>,
+) -> RouteResult<Tagged<Student>> {
+    let PatchStudentRequest {
+        first_name,
+        last_name,
+        middle_name,
+        class_id,
+        phone,
+        email,
+        password,
+    } = data;
+    let first_name = not_null(first_name, "first_name")?;
+    let last_name = not_null(last_name, "last_name")?;
+    let class_id = not_null(class_id, "class_id")?;
+    let phone = not_null(phone, "phone")?;
+    let password = not_null(password, "password")?;
+
+    roles::ensure(&state.db, &claims, Permission::StudentsWrite).await?;
+    let school_id = claims.school()?;
+
+    let password_hash = match password {
+        Some(password) => Some(passwords::hash(&state.db, &password).await?),
+        None => None,
+    };
+
+    let mut tx = state.db.begin().await?;
+    let before = sqlx::query_as::<_, Student>(
+        "SELECT * FROM Students WHERE id = $1 AND school_id = $2 FOR UPDATE",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if_match.check(before.as_ref())?;
+    if let Some(class_id) = class_id {
+        ensure_active_class(&mut tx, class_id).await?;
     }
+    let result = sqlx::query_as::<_, Student>(
+        r#"
+            UPDATE Students
+            SET
+                first_name = coalesce($2, first_name),
+                last_name = coalesce($3, last_name),
+                middle_name = CASE WHEN $4 THEN $5 ELSE middle_name END,
+                class_id = coalesce($6, class_id),
+                phone = coalesce($7, phone),
+                password_hash = coalesce($8, password_hash),
+                password_change_required = password_change_required OR $8 IS NOT NULL,
+                email = CASE WHEN $9 THEN $10 ELSE email END
+            WHERE
+                id = $1 AND school_id = $11 AND archived_at IS NULL
+            RETURNING *
+        "#,
+    )
+    .bind(id)
+    .bind(first_name)
+    .bind(last_name)
+    .bind(middle_name.is_some())
+    .bind(middle_name.flatten())
+    .bind(class_id)
+    .bind(phone)
+    .bind(password_hash)
+    .bind(email.is_some())
+    .bind(email.flatten())
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await;
+
+    let student = match result {
+        Ok(Some(student)) => student,
+        Ok(None) => fail!(!BAD_REQUEST, "Ученик с таким ИД не существует"),
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
+            fail!(!BAD_REQUEST, "Такой ученик уже существует в данном классе"),
+        Err(err) if matches!(err.as_database_error(), Some(err) if err.is_foreign_key_violation()) =>
+            fail!(!BAD_REQUEST, "Класс с таким ИД не существует"),
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Student,
+        Some(id),
+        before.as_ref(),
+        Some(&student),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(student))
 }
 
-/// Deletes a student by id
+/// Archives a student by id. Marks of the student are kept and the student can no longer log in
 #[utoipa::path(
     delete,
     path = "/students/{id}",
     tag = "Students management",
-    params(("id" = i32, Path, description = "Id of the student to delete")),
-    responses((status = 200)),
+    params(("id" = i32, Path, description = "Id of the student to archive")),
+    responses((status = 200), (status = 412), (status = 428)),
 )]
-async fn remove(Path(id): Path<i32>, State(state): RouteState) -> RouteResult {
-    let result = sqlx::query("DELETE FROM Students WHERE id = $1")
-        .bind(id)
-        .execute(&state.db)
+async fn remove(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+) -> RouteResult {
+    roles::ensure(&state.db, &claims, Permission::StudentsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    if_match
+        .lock(&mut tx, "Students", id, Some(school_id))
         .await?;
-    if result.rows_affected() == 0 {
+    let Some(before) = sqlx::query_as::<_, Student>(
+        "
+            SELECT * FROM Students
+            WHERE id = $1 AND school_id = $2 AND archived_at IS NULL
+            FOR UPDATE
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
         fail!(!BAD_REQUEST, "Ученик с таким ИД не существует")
-    }
+    };
+    let student = sqlx::query_as::<_, Student>(
+        "UPDATE Students SET archived_at = now() WHERE id = $1 RETURNING *",
+    )
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await?;
+    auth::revoke_sessions(&mut *tx, Role::Student, id).await?;
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Archive,
+        AuditEntity::Student,
+        Some(id),
+        Some(&before),
+        Some(&student),
+    )
+    .await?;
+    tx.commit().await?;
 
     Ok(())
 }
 
+/// Restores an archived student by id
+#[utoipa::path(
+    post,
+    path = "/students/{id}/restore",
+    tag = "Students management",
+    params(("id" = i32, Path, description = "Id of the student to restore")),
+    responses((status = 200, body = Student)),
+)]
+async fn restore(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Student>> {
+    roles::ensure(&state.db, &claims, Permission::StudentsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(before) = sqlx::query_as::<_, Student>(
+        "
+            SELECT * FROM Students
+            WHERE id = $1 AND school_id = $2 AND archived_at IS NOT NULL
+            FOR UPDATE
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!BAD_REQUEST, "Архивного ученика с таким ИД не существует")
+    };
+    // The class may have been archived after the student, it has to be restored first
+    ensure_active_class(&mut tx, before.class_id).await?;
+    let student = sqlx::query_as::<_, Student>(
+        "UPDATE Students SET archived_at = NULL WHERE id = $1 RETURNING *",
+    )
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Restore,
+        AuditEntity::Student,
+        Some(id),
+        Some(&before),
+        Some(&student),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(student))
+}
+
 pub fn openapi() -> utoipa::openapi::OpenApi {
     #[derive(OpenApi)]
     #[openapi(
-        paths(fetch, create, update, remove),
-        components(schemas(Student, CreateOrUpdateStudentRequest))
+        paths(
+            fetch,
+            fetch_one,
+            create,
+            update,
+            patch,
+            remove,
+            restore,
+            fetch_dependencies
+        ),
+        components(schemas(
+            Student,
+            StudentPage,
+            StudentSort,
+            CreateOrUpdateStudentRequest,
+            PatchStudentRequest,
+            Dependency,
+            DependencyKind
+        ))
     )]
     struct Api;
 
@@ -229,5 +729,10 @@ pub fn openapi() -> utoipa::openapi::OpenApi {
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(fetch).post(create))
-        .route("/:id", put(update).delete(remove))
+        .route(
+            "/:id",
+            get(fetch_one).put(update).patch(patch).delete(remove),
+        )
+        .route("/:id/restore", post(restore))
+        .route("/:id/dependencies", get(fetch_dependencies))
 }
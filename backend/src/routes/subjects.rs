@@ -1,16 +1,55 @@
-use super::{Json, Path, Query, RouteResult, RouteState};
-use crate::{fail, models::Subject, AppState};
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    dependencies::{self, Dependency, DependencyKind, Remove, Source},
+    not_null, nullable,
+    pagination::{Order, Page, Paged, Pagination, SubjectPage},
+    roles, rooms, teachers,
+    versions::{IfMatch, Tagged},
+    Json, Path, Query, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Permission, Subject},
+    AppState,
+};
 use axum::{extract::State, routing::*};
 use serde::Deserialize;
 use utoipa::{IntoParams, OpenApi, ToSchema};
 
+const DEPENDENCIES: &[Source] = &[
+    Source {
+        kind: DependencyKind::Room,
+        blocking: true,
+        query:
+            "SELECT id FROM Rooms WHERE subject_id = $1 AND school_id = $2 AND archived_at IS NULL",
+    },
+    Source {
+        kind: DependencyKind::Teacher,
+        blocking: true,
+        query: "
+            SELECT id FROM Teachers
+            JOIN Employees ON id = employee_id
+            WHERE subject_id = $1 AND Teachers.school_id = $2 AND archived_at IS NULL
+        ",
+    },
+    Source {
+        kind: DependencyKind::Mark,
+        blocking: false,
+        query: "SELECT id FROM Marks WHERE subject_id = $1 AND school_id = $2",
+    },
+];
+
 #[derive(Deserialize, IntoParams)]
 #[serde(deny_unknown_fields)]
 struct Fetch {
     name: Option<String>,
     id: Option<i32>,
+    /// Fetch archived subjects instead of active ones
+    #[serde(default)]
+    archived: bool,
     count: Option<i64>,
-    offset: Option<i64>,
+    cursor: Option<String>,
 }
 
 /// Fetches subject names and ids
@@ -19,39 +58,72 @@ struct Fetch {
     path = "/subjects",
     tag = "Subjects management",
     params(Fetch),
-    responses((status = 200, body = Vec<Subject>))
+    responses((status = 200, body = SubjectPage))
 )]
 async fn fetch(
     State(state): RouteState,
+    claims: Claims,
     Query(query): Query<Fetch>,
-) -> RouteResult<Json<Vec<Subject>>> {
+) -> RouteResult<Json<Page<Subject>>> {
     let Fetch {
         name,
         id,
+        archived,
         count,
-        offset,
+        cursor,
     } = query;
 
-    let count = count.unwrap_or(50).clamp(0, 50);
-    let offset = offset.unwrap_or(0).clamp(0, 5000);
+    let school_id = claims.school()?;
+    let pagination = Pagination::new(count, cursor, Order::ID)?;
 
-    let subjects = sqlx::query_as::<_, Subject>(
+    let query = pagination.query(
         r#"
-        SELECT * FROM Subjects
-        WHERE
-            coalesce(subject ILIKE ('%' || $3 || '%'), true) AND
-            coalesce(id = $4, true)
-        LIMIT $1 OFFSET $2
-    "#,
-    )
-    .bind(count)
-    .bind(offset)
-    .bind(name)
-    .bind(id)
-    .fetch_all(&state.db)
-    .await?;
+            SELECT * FROM Subjects
+            WHERE
+                school_id = $6 AND
+                (archived_at IS NOT NULL) = $7 AND
+                coalesce(subject ILIKE ('%' || $4 || '%'), true) AND
+                coalesce(id = $5, true)
+        "#,
+    );
+    let subjects = pagination
+        .bind(sqlx::query_as::<_, Paged<Subject>>(&query))
+        .bind(name)
+        .bind(id)
+        .bind(school_id)
+        .bind(archived)
+        .fetch_all(&state.db)
+        .await?;
 
-    Ok(Json(subjects))
+    Ok(Json(pagination.finish(subjects)))
+}
+
+/// Fetches a subject by id, the `ETag` header carries its version
+#[utoipa::path(
+    get,
+    path = "/subjects/{id}",
+    tag = "Subjects management",
+    params(("id" = i32, Path, description = "Id of the subject")),
+    responses((status = 200, body = Subject), (status = 404))
+)]
+async fn fetch_one(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Subject>> {
+    let school_id = claims.school()?;
+
+    let Some(subject) =
+        sqlx::query_as::<_, Subject>("SELECT * FROM Subjects WHERE id = $1 AND school_id = $2")
+            .bind(id)
+            .bind(school_id)
+            .fetch_optional(&state.db)
+            .await?
+    else {
+        fail!(!NOT_FOUND, "Предмета с таким ИД не существует");
+    };
+
+    Ok(Tagged(subject))
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -70,22 +142,43 @@ struct CreateOrUpdateSubjectRequest {
 )]
 async fn create(
     State(state): RouteState,
+    claims: Claims,
     Json(data): Json<CreateOrUpdateSubjectRequest>,
-) -> RouteResult<Json<Subject>> {
+) -> RouteResult<Tagged<Subject>> {
     let CreateOrUpdateSubjectRequest { name } = data;
 
-    let result =
-        sqlx::query_as::<_, Subject>("INSERT INTO Subjects(subject) VALUES($1) RETURNING *")
-            .bind(name)
-            .fetch_one(&state.db)
-            .await;
+    roles::ensure(&state.db, &claims, Permission::SubjectsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let result = sqlx::query_as::<_, Subject>(
+        "INSERT INTO Subjects(subject, school_id) VALUES($1, $2) RETURNING *",
+    )
+    .bind(name)
+    .bind(school_id)
+    .fetch_one(&mut *tx)
+    .await;
 
-    match result {
-        Ok(subject) => Ok(Json(subject)),
+    let subject = match result {
+        Ok(subject) => subject,
         Err(err) if matches!(err.as_database_error(), Some(err) if err.is_unique_violation()) =>
             fail!(!BAD_REQUEST, "Такой предмет уже существует"),
-        Err(err) => Err(err.into()),
-    }
+        Err(err) => return Err(err.into()),
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::Subject,
+        Some(subject.id),
+        None,
+        Some(&subject),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(subject))
 }
 
 /// Update a subject with specified id
@@ -94,54 +187,282 @@ async fn create(
     path = "/subjects/{id}",
     tag = "Subjects management",
     request_body = CreateOrUpdateSubjectRequest,
-    responses((status = 200, body = Subject))
+    responses((status = 200, body = Subject), (status = 412), (status = 428))
 )]
 async fn update(
     Path(id): Path<i32>,
     State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
     Json(data): Json<CreateOrUpdateSubjectRequest>,
-) -> RouteResult<Json<Subject>> {
+) -> RouteResult<Tagged<Subject>> {
     let CreateOrUpdateSubjectRequest { name } = data;
 
-    let Some(class) =
-        sqlx::query_as::<_, Subject>("UPDATE Subjects SET subject = $2 WHERE id = $1 RETURNING *")
-            .bind(id)
-            .bind(name)
-            .fetch_optional(&state.db)
-            .await?
+    roles::ensure(&state.db, &claims, Permission::SubjectsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let before = sqlx::query_as::<_, Subject>(
+        "SELECT * FROM Subjects WHERE id = $1 AND school_id = $2 FOR UPDATE",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if_match.check(before.as_ref())?;
+    let Some(subject) = sqlx::query_as::<_, Subject>(
+        "
+            UPDATE Subjects SET subject = $2
+            WHERE id = $1 AND school_id = $3 AND archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
     else {
         fail!(!BAD_REQUEST, "Предмета с таким ИД не существует")
     };
 
-    Ok(Json(class))
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Subject,
+        Some(id),
+        before.as_ref(),
+        Some(&subject),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(subject))
 }
 
-/// Deletes a subject by id
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct PatchSubjectRequest {
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    name: Option<Option<String>>,
+}
+
+/// Change some fields of a subject with specified id
+#[utoipa::path(
+    patch,
+    path = "/subjects/{id}",
+    tag = "Subjects management",
+    request_body = PatchSubjectRequest,
+    responses((status = 200, body = Subject), (status = 412), (status = 428))
+)]
+async fn patch(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+    Json(data): Json<PatchSubjectRequest>,
+) -> RouteResult<Tagged<Subject>> {
+    let PatchSubjectRequest { name } = data;
+    let name = not_null(name, "name")?;
+
+    roles::ensure(&state.db, &claims, Permission::SubjectsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let before = sqlx::query_as::<_, Subject>(
+        "SELECT * FROM Subjects WHERE id = $1 AND school_id = $2 FOR UPDATE",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if_match.check(before.as_ref())?;
+    let Some(subject) = sqlx::query_as::<_, Subject>(
+        "
+            UPDATE Subjects SET subject = coalesce($2, subject)
+            WHERE id = $1 AND school_id = $3 AND archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!BAD_REQUEST, "Предмета с таким ИД не существует")
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Subject,
+        Some(id),
+        before.as_ref(),
+        Some(&subject),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(subject))
+}
+
+/// Fetches rows referencing the subject
+#[utoipa::path(
+    get,
+    path = "/subjects/{id}/dependencies",
+    tag = "Subjects management",
+    params(("id" = i32, Path, description = "Id of the subject")),
+    responses((status = 200, body = Vec<Dependency>))
+)]
+async fn fetch_dependencies(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Json<Vec<Dependency>>> {
+    roles::ensure(&state.db, &claims, Permission::SubjectsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut conn = state.db.acquire().await?;
+    let dependencies = dependencies::collect(&mut conn, id, school_id, DEPENDENCIES).await?;
+
+    Ok(Json(dependencies))
+}
+
+/// Archives a subject by id. Fails with 409 while active rooms or teachers have the subject,
+/// unless they are moved to another subject with `reassign_to`
 #[utoipa::path(
     delete,
     path = "/subjects/{id}",
     tag = "Subjects management",
-    params(("id" = i32, Path, description = "Id of the subject to delete")),
-    responses((status = 200))
+    params(("id" = i32, Path, description = "Id of the subject to archive"), Remove),
+    responses((status = 200), (status = 409), (status = 412), (status = 428))
 )]
-async fn remove(Path(id): Path<i32>, State(state): RouteState) -> RouteResult {
-    let result = sqlx::query("DELETE FROM Subjects WHERE id = $1")
-        .bind(id)
-        .execute(&state.db)
-        .await?;
+async fn remove(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+    Query(query): Query<Remove>,
+) -> RouteResult {
+    let Remove { reassign_to } = query;
 
-    if result.rows_affected() == 0 {
+    roles::ensure(&state.db, &claims, Permission::SubjectsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    if_match
+        .lock(&mut tx, "Subjects", id, Some(school_id))
+        .await?;
+    let Some(subject) = sqlx::query_as::<_, Subject>(
+        "
+            UPDATE Subjects SET archived_at = now()
+            WHERE id = $1 AND school_id = $2 AND archived_at IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
         fail!(!BAD_REQUEST, "Такого предмета не существует");
+    };
+
+    if let Some(target) = reassign_to {
+        dependencies::ensure_target(&mut tx, "Subjects", id, target, school_id).await?;
+        rooms::reassign_subject(&mut tx, &claims, school_id, id, target).await?;
+        teachers::reassign(&mut tx, &claims, school_id, "subject_id", id, target).await?;
     }
+    let dependencies = dependencies::collect(&mut tx, id, school_id, DEPENDENCIES).await?;
+    dependencies::ensure_unblocked(dependencies)?;
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Archive,
+        AuditEntity::Subject,
+        Some(id),
+        None,
+        Some(&subject),
+    )
+    .await?;
+    tx.commit().await?;
 
     Ok(())
 }
 
+/// Restores an archived subject by id
+#[utoipa::path(
+    post,
+    path = "/subjects/{id}/restore",
+    tag = "Subjects management",
+    params(("id" = i32, Path, description = "Id of the subject to restore")),
+    responses((status = 200, body = Subject))
+)]
+async fn restore(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Subject>> {
+    roles::ensure(&state.db, &claims, Permission::SubjectsWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(subject) = sqlx::query_as::<_, Subject>(
+        "
+            UPDATE Subjects SET archived_at = NULL
+            WHERE id = $1 AND school_id = $2 AND archived_at IS NOT NULL
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!BAD_REQUEST, "Архивного предмета с таким ИД не существует");
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Restore,
+        AuditEntity::Subject,
+        Some(id),
+        None,
+        Some(&subject),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(subject))
+}
+
 pub fn openapi() -> utoipa::openapi::OpenApi {
     #[derive(OpenApi)]
     #[openapi(
-        paths(remove, fetch, update, create),
-        components(schemas(CreateOrUpdateSubjectRequest, Subject))
+        paths(
+            remove,
+            fetch,
+            fetch_one,
+            update,
+            patch,
+            create,
+            restore,
+            fetch_dependencies
+        ),
+        components(schemas(
+            CreateOrUpdateSubjectRequest,
+            PatchSubjectRequest,
+            Subject,
+            SubjectPage,
+            Dependency,
+            DependencyKind
+        ))
     )]
     struct Api;
 
@@ -151,5 +472,10 @@ pub fn openapi() -> utoipa::openapi::OpenApi {
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(fetch).post(create))
-        .route("/:subject", put(update).delete(remove))
+        .route(
+            "/:subject",
+            get(fetch_one).put(update).patch(patch).delete(remove),
+        )
+        .route("/:subject/restore", post(restore))
+        .route("/:subject/dependencies", get(fetch_dependencies))
 }
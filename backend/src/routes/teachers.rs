@@ -1,26 +1,71 @@
-use super::{Json, Path, Query, RouteResult, RouteState};
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    auth,
+    dependencies::{self, Dependency, DependencyKind, Source},
+    not_null, nullable,
+    pagination::{Order, Page, Paged, Pagination, TeacherPage},
+    passwords, roles,
+    versions::{IfMatch, Tagged},
+    Json, Path, Query, RouteResult, RouteState,
+};
 use crate::{
     fail,
-    models::{Employee, Teacher},
+    middleware::Claims,
+    models::{Employee, Permission, Role, Teacher},
     AppState,
 };
-use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
 use axum::{extract::State, routing::*};
-use rand::rngs::OsRng;
 use serde::Deserialize;
+use sqlx::PgConnection;
+use time::OffsetDateTime;
 use utoipa::{IntoParams, OpenApi, ToSchema};
 
+/// Marks given by the teacher are kept in the archive, so nothing blocks archiving a teacher
+const DEPENDENCIES: &[Source] = &[Source {
+    kind: DependencyKind::Mark,
+    blocking: false,
+    query: "SELECT id FROM Marks WHERE teacher_id = $1 AND school_id = $2",
+}];
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum TeacherSort {
+    #[default]
+    Id,
+    LastName,
+    EmployedAt,
+}
+
 #[derive(Deserialize, IntoParams)]
 #[serde(deny_unknown_fields)]
 struct Fetch {
     name: Option<String>,
     id: Option<i32>,
+    /// Exact phone number
+    phone: Option<String>,
     #[serde(default)]
     subject_ids: Vec<i32>,
     #[serde(default)]
     room_ids: Vec<i32>,
+    #[serde(default)]
+    exclude_ids: Vec<i32>,
+    #[serde(default)]
+    exclude_subject_ids: Vec<i32>,
+    #[serde(default)]
+    exclude_room_ids: Vec<i32>,
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    employed_after: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    employed_before: Option<OffsetDateTime>,
+    /// Fetch archived teachers instead of active ones
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    sort: TeacherSort,
+    #[serde(default)]
+    desc: bool,
     count: Option<i64>,
-    offset: Option<i64>,
+    cursor: Option<String>,
 }
 
 /// Fetches teachers
@@ -29,46 +74,214 @@ struct Fetch {
     path = "/teachers",
     tag = "Teachers management",
     params(Fetch),
-    responses((status = 200, body = Vec<Teacher>))
+    responses((status = 200, body = TeacherPage))
 )]
 async fn fetch(
     State(state): RouteState,
+    claims: Claims,
     Query(query): Query<Fetch>,
-) -> RouteResult<Json<Vec<Teacher>>> {
+) -> RouteResult<Json<Page<Teacher>>> {
     let Fetch {
         name,
         id,
+        phone,
         subject_ids,
         room_ids,
+        exclude_ids,
+        exclude_subject_ids,
+        exclude_room_ids,
+        employed_after,
+        employed_before,
+        archived,
+        sort,
+        desc,
         count,
-        offset,
+        cursor,
     } = query;
 
-    let count = count.unwrap_or(50).clamp(0, 100);
-    let offset = offset.unwrap_or(0).clamp(0, 2000);
+    let school_id = claims.school()?;
+    let order = match sort {
+        TeacherSort::Id => Order::ID,
+        TeacherSort::LastName => Order::by("last_name", "TEXT"),
+        TeacherSort::EmployedAt => Order::by("employed_at", "TIMESTAMPTZ"),
+    };
 
-    let teachers = sqlx::query_as::<_, Teacher>(
+    let pagination = Pagination::new(count, cursor, order.desc(desc))?;
+    let query = pagination.query(
         r#"
             SELECT * FROM Teachers
             JOIN Employees ON Employees.id = Teachers.employee_id
             WHERE
-                coalesce(id = $3, true) AND
-                coalesce(first_name || last_name || coalesce(middle_name, '') ILIKE ('%' || $4 || '%'), true) AND
-                (subject_id = any($5) OR cardinality($5) = 0) AND
-                (room_id = any($6) OR cardinality($6) = 0)
-            LIMIT $1 OFFSET $2
+                Teachers.school_id = $8 AND
+                (archived_at IS NOT NULL) = $9 AND
+                coalesce(id = $4, true) AND
+                coalesce(search_name(first_name, last_name, middle_name) LIKE ('%' || search_text($5) || '%'), true) AND
+                (subject_id = any($6) OR cardinality($6) = 0) AND
+                (room_id = any($7) OR cardinality($7) = 0) AND
+                coalesce(phone = $10, true) AND
+                NOT (id = any($11)) AND
+                NOT (subject_id = any($12)) AND
+                NOT coalesce(room_id = any($13), false) AND
+                employed_at BETWEEN
+                    coalesce($14, '-infinity'::timestamptz) AND
+                    coalesce($15, 'infinity'::timestamptz)
         "#,
+    );
+    let teachers = pagination
+        .bind(sqlx::query_as::<_, Paged<Teacher>>(&query))
+        .bind(id)
+        .bind(name)
+        .bind(subject_ids)
+        .bind(room_ids)
+        .bind(school_id)
+        .bind(archived)
+        .bind(phone)
+        .bind(exclude_ids)
+        .bind(exclude_subject_ids)
+        .bind(exclude_room_ids)
+        .bind(employed_after)
+        .bind(employed_before)
+        .fetch_all(&state.db)
+        .await?;
+
+    Ok(Json(pagination.finish(teachers)))
+}
+
+/// Fetches a teacher by id, the `ETag` header carries its version
+#[utoipa::path(
+    get,
+    path = "/teachers/{id}",
+    tag = "Teachers management",
+    params(("id" = i32, Path, description = "Id of the teacher")),
+    responses((status = 200, body = Teacher), (status = 404))
+)]
+async fn fetch_one(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Teacher>> {
+    let school_id = claims.school()?;
+
+    let Some(teacher) = sqlx::query_as::<_, Teacher>(
+        "
+            SELECT * FROM Teachers
+            JOIN Employees ON Employees.id = Teachers.employee_id
+            WHERE id = $1 AND Teachers.school_id = $2
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .fetch_optional(&state.db)
+    .await?
+    else {
+        fail!(!NOT_FOUND, "Учителя с таким ИД не существует");
+    };
+
+    Ok(Tagged(teacher))
+}
+
+/// Locks the teacher to record its state before a change
+async fn fetch_for_update(
+    db: &mut PgConnection,
+    id: i32,
+    school_id: i32,
+) -> RouteResult<Option<Teacher>> {
+    let teacher = sqlx::query_as::<_, Teacher>(
+        "
+            SELECT * FROM Teachers
+            JOIN Employees ON Employees.id = Teachers.employee_id
+            WHERE id = $1 AND Teachers.school_id = $2
+            FOR UPDATE
+        ",
     )
-    .bind(count)
-    .bind(offset)
     .bind(id)
-    .bind(name)
-    .bind(subject_ids)
-    .bind(room_ids)
-    .fetch_all(&state.db)
+    .bind(school_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(teacher)
+}
+
+/// Moves active teachers of the subject or the room to another one. `column` is either
+/// `subject_id` or `room_id`
+pub(super) async fn reassign(
+    db: &mut PgConnection,
+    claims: &Claims,
+    school_id: i32,
+    column: &'static str,
+    from: i32,
+    to: i32,
+) -> RouteResult {
+    let before = sqlx::query_as::<_, Teacher>(&format!(
+        "
+            SELECT * FROM Teachers
+            JOIN Employees ON Employees.id = Teachers.employee_id
+            WHERE {column} = $1 AND Teachers.school_id = $2 AND archived_at IS NULL
+            ORDER BY id
+            FOR UPDATE
+        "
+    ))
+    .bind(from)
+    .bind(school_id)
+    .fetch_all(&mut *db)
+    .await?;
+    let ids = before.iter().map(|t| t.employee.id).collect::<Vec<_>>();
+
+    sqlx::query(&format!(
+        "UPDATE Teachers SET {column} = $2 WHERE employee_id = any($1)"
+    ))
+    .bind(&ids)
+    .bind(to)
+    .execute(&mut *db)
     .await?;
+    let after = sqlx::query_as::<_, Teacher>(
+        "
+            SELECT * FROM Teachers
+            JOIN Employees ON Employees.id = Teachers.employee_id
+            WHERE id = any($1)
+            ORDER BY id
+        ",
+    )
+    .bind(&ids)
+    .fetch_all(&mut *db)
+    .await?;
+
+    for (before, after) in before.iter().zip(&after) {
+        audit::record(
+            db,
+            Some(claims),
+            AuditAction::Update,
+            AuditEntity::Teacher,
+            Some(after.employee.id),
+            Some(before),
+            Some(after),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Fetches rows referencing the teacher
+#[utoipa::path(
+    get,
+    path = "/teachers/{id}/dependencies",
+    tag = "Teachers management",
+    params(("id" = i32, Path, description = "Id of the teacher")),
+    responses((status = 200, body = Vec<Dependency>))
+)]
+async fn fetch_dependencies(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Json<Vec<Dependency>>> {
+    roles::ensure(&state.db, &claims, Permission::TeachersWrite).await?;
+    let school_id = claims.school()?;
 
-    Ok(Json(teachers))
+    let mut conn = state.db.acquire().await?;
+    let dependencies = dependencies::collect(&mut conn, id, school_id, DEPENDENCIES).await?;
+
+    Ok(Json(dependencies))
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -80,6 +293,7 @@ struct CreateTeacherRequest {
     subject_id: i32,
     room_id: Option<i32>,
     phone: String,
+    email: Option<String>,
     password: String,
 }
 
@@ -93,8 +307,9 @@ struct CreateTeacherRequest {
 )]
 async fn create(
     State(state): RouteState,
+    claims: Claims,
     Json(data): Json<CreateTeacherRequest>,
-) -> RouteResult<Json<Teacher>> {
+) -> RouteResult<Tagged<Teacher>> {
     let CreateTeacherRequest {
         first_name,
         last_name,
@@ -103,19 +318,23 @@ async fn create(
         room_id,
         password,
         phone,
+        email,
     } = data;
 
-    let password_hash = Argon2::default()
-        .hash_password(password.as_bytes(), &SaltString::generate(OsRng))
-        .unwrap()
-        .to_string();
+    roles::ensure(&state.db, &claims, Permission::TeachersWrite).await?;
+    let school_id = claims.school()?;
+
+    let password_hash = passwords::hash(&state.db, &password).await?;
 
     let mut tx = state.db.begin().await?;
     let result = sqlx::query_as::<_, Employee>(
         r#"
-            INSERT INTO Employees(first_name, last_name, middle_name, phone, password_hash, role)
+            INSERT INTO Employees(
+                first_name, last_name, middle_name, phone, password_hash, email, role,
+                password_change_required, school_id
+            )
             VALUES (
-                $1, $2, $3, $4, $5, 'teacher'
+                $1, $2, $3, $4, $5, $6, 'teacher', true, $7
             ) RETURNING *
         "#,
     )
@@ -124,8 +343,8 @@ async fn create(
     .bind(middle_name)
     .bind(phone)
     .bind(password_hash)
-    .bind(subject_id)
-    .bind(room_id)
+    .bind(email)
+    .bind(school_id)
     .fetch_one(&mut *tx)
     .await;
 
@@ -139,13 +358,15 @@ async fn create(
         Err(err) => return Err(err.into()),
     };
 
-    let result =
-        sqlx::query("INSERT INTO Teachers(employee_id, subject_id, room_id) VALUES($1, $2, $3)")
-            .bind(employee.id)
-            .bind(subject_id)
-            .bind(room_id)
-            .execute(&mut *tx)
-            .await;
+    let result = sqlx::query(
+        "INSERT INTO Teachers(employee_id, subject_id, room_id, school_id) VALUES($1, $2, $3, $4)",
+    )
+    .bind(employee.id)
+    .bind(subject_id)
+    .bind(room_id)
+    .bind(school_id)
+    .execute(&mut *tx)
+    .await;
     match result {
         Ok(_) => {}
         Err(err)
@@ -163,13 +384,24 @@ async fn create(
         Err(err) => return Err(err.into()),
     }
 
-    tx.commit().await?;
-
-    Ok(Json(Teacher {
+    let teacher = Teacher {
         employee,
         room_id,
         subject_id,
-    }))
+    };
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Create,
+        AuditEntity::Teacher,
+        Some(teacher.employee.id),
+        None,
+        Some(&teacher),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(teacher))
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -181,6 +413,8 @@ struct UpdateTeacherRequest {
     subject_id: i32,
     room_id: Option<i32>,
     phone: String,
+    /// Kept when omitted on update, `PATCH` with `null` clears it
+    email: Option<String>,
     password: Option<String>,
 }
 
@@ -190,13 +424,15 @@ struct UpdateTeacherRequest {
     path = "/teachers/{id}",
     tag = "Teachers management",
     params(("id" = i32, Path, description = "Id of the teacher to update")),
-    responses((status = 200, body = Teacher)))
+    responses((status = 200, body = Teacher), (status = 412), (status = 428)))
 ]
 async fn update(
     Path(id): Path<i32>,
     State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
     Json(data): Json<UpdateTeacherRequest>,
-) -> RouteResult<Json<Teacher>> {
+) -> RouteResult<Tagged<Teacher>> {
     let UpdateTeacherRequest {
         first_name,
         last_name,
@@ -204,17 +440,47 @@ async fn update(
         subject_id,
         room_id,
         phone,
+        email,
         password,
     } = data;
 
-    let password_hash = password.map(|pass| {
-        Argon2::default()
-            .hash_password(pass.as_bytes(), &SaltString::generate(OsRng))
-            .unwrap()
-            .to_string()
-    });
+    roles::ensure(&state.db, &claims, Permission::TeachersWrite).await?;
+    let school_id = claims.school()?;
+
+    let password_hash = match password {
+        Some(password) => Some(passwords::hash(&state.db, &password).await?),
+        None => None,
+    };
 
     let mut tx = state.db.begin().await?;
+    let before = fetch_for_update(&mut tx, id, school_id).await?;
+    if_match.check(before.as_ref())?;
+
+    // Teachers go first, as changing them bumps the version of the employee
+    let result =
+        sqlx::query("UPDATE Teachers SET room_id = $2, subject_id = $3 WHERE employee_id = $1")
+            .bind(id)
+            .bind(room_id)
+            .bind(subject_id)
+            .execute(&mut *tx)
+            .await;
+    match result {
+        Ok(_) => {}
+        Err(err)
+            if matches!(
+                err.as_database_error(),
+                Some(err) if err.is_foreign_key_violation() && err.constraint() == Some("teachers_subject_id_fkey")
+            ) =>
+            fail!(!BAD_REQUEST, "Предмета с таким ИД не существует"),
+        Err(err)
+            if matches!(
+                err.as_database_error(),
+                Some(err) if err.is_foreign_key_violation() && err.constraint() == Some("teachers_room_id_fkey")
+            ) =>
+            fail!(!BAD_REQUEST, "Кабинета с таким ИД не существует"),
+        Err(err) => return Err(err.into()),
+    }
+
     let result = sqlx::query_as::<_, Employee>(
         r#"
             UPDATE Employees
@@ -223,8 +489,10 @@ async fn update(
                 last_name = $3,
                 middle_name = $4,
                 phone = $5,
-                password_hash = coalesce($6, password_hash)
-            WHERE id = $1 AND role = 'teacher'
+                password_hash = coalesce($6, password_hash),
+                password_change_required = password_change_required OR $6 IS NOT NULL,
+                email = coalesce($7, email)
+            WHERE id = $1 AND role = 'teacher' AND school_id = $8 AND archived_at IS NULL
             RETURNING *
         "#,
     )
@@ -234,6 +502,8 @@ async fn update(
     .bind(middle_name)
     .bind(phone)
     .bind(password_hash)
+    .bind(email)
+    .bind(school_id)
     .fetch_optional(&mut *tx)
     .await;
 
@@ -243,15 +513,118 @@ async fn update(
         Err(err) => return Err(err.into()),
     };
 
-    let result =
-        sqlx::query("UPDATE Teachers SET room_id = $2, subject_id = $3 WHERE employee_id = $1")
-            .bind(id)
-            .bind(room_id)
-            .bind(subject_id)
-            .execute(&mut *tx)
-            .await;
-    match result {
-        Ok(_) => {}
+    let teacher = Teacher {
+        employee,
+        room_id,
+        subject_id,
+    };
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Teacher,
+        Some(id),
+        before.as_ref(),
+        Some(&teacher),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(teacher))
+}
+
+/// Only the fields present are changed, `null` clears the optional ones
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct PatchTeacherRequest {
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    first_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    last_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    middle_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<i32>)]
+    subject_id: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<i32>)]
+    room_id: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    phone: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    email: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>)]
+    password: Option<Option<String>>,
+}
+
+/// Change some fields of teacher with id
+#[utoipa::path(
+    patch,
+    path = "/teachers/{id}",
+    tag = "Teachers management",
+    params(("id" = i32, Path, description = "Id of the teacher to update")),
+    request_body = PatchTeacherRequest,
+    responses((status = 200, body = Teacher), (status = 412), (status = 428)))
+]
+async fn patch(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+    Json(data): Json<PatchTeacherRequest>,
+) -> RouteResult<Tagged<Teacher>> {
+    let PatchTeacherRequest {
+        first_name,
+        last_name,
+        middle_name,
+        subject_id,
+        room_id,
+        phone,
+        email,
+        password,
+    } = data;
+    let first_name = not_null(first_name, "first_name")?;
+    let last_name = not_null(last_name, "last_name")?;
+    let subject_id = not_null(subject_id, "subject_id")?;
+    let phone = not_null(phone, "phone")?;
+    let password = not_null(password, "password")?;
+
+    roles::ensure(&state.db, &claims, Permission::TeachersWrite).await?;
+    let school_id = claims.school()?;
+
+    let password_hash = match password {
+        Some(password) => Some(passwords::hash(&state.db, &password).await?),
+        None => None,
+    };
+
+    let mut tx = state.db.begin().await?;
+    let before = fetch_for_update(&mut tx, id, school_id).await?;
+    if_match.check(before.as_ref())?;
+
+    // Teachers go first, as changing them bumps the version of the employee
+    let result = sqlx::query_as::<_, (Option<i32>, i32)>(
+        "
+            UPDATE Teachers SET
+                room_id = CASE WHEN $2 THEN $3 ELSE room_id END,
+                subject_id = coalesce($4, subject_id)
+            WHERE employee_id = $1
+            RETURNING room_id, subject_id
+        ",
+    )
+    .bind(id)
+    .bind(room_id.is_some())
+    .bind(room_id.flatten())
+    .bind(subject_id)
+    .fetch_one(&mut *tx)
+    .await;
+    let (room_id, subject_id) = match result {
+        Ok(val) => val,
         Err(err)
             if matches!(
                 err.as_database_error(),
@@ -265,41 +638,189 @@ async fn update(
             ) =>
             fail!(!BAD_REQUEST, "Кабинета с таким ИД не существует"),
         Err(err) => return Err(err.into()),
-    }
-    tx.commit().await?;
+    };
 
-    Ok(Json(Teacher {
+    let result = sqlx::query_as::<_, Employee>(
+        r#"
+            UPDATE Employees
+            SET
+                first_name = coalesce($2, first_name),
+                last_name = coalesce($3, last_name),
+                middle_name = CASE WHEN $4 THEN $5 ELSE middle_name END,
+                phone = coalesce($6, phone),
+                password_hash = coalesce($7, password_hash),
+                password_change_required = password_change_required OR $7 IS NOT NULL,
+                email = CASE WHEN $8 THEN $9 ELSE email END
+            WHERE id = $1 AND role = 'teacher' AND school_id = $10 AND archived_at IS NULL
+            RETURNING *
+        "#,
+    )
+    .bind(id)
+    .bind(first_name)
+    .bind(last_name)
+    .bind(middle_name.is_some())
+    .bind(middle_name.flatten())
+    .bind(phone)
+    .bind(password_hash)
+    .bind(email.is_some())
+    .bind(email.flatten())
+    .bind(school_id)
+    .fetch_optional(&mut *tx)
+    .await;
+
+    let employee = match result {
+        Ok(Some(val)) => val,
+        Ok(None) => fail!(!BAD_REQUEST, "Учителя с таким ИД не существует"),
+        Err(err) => return Err(err.into()),
+    };
+
+    let teacher = Teacher {
         employee,
         room_id,
         subject_id,
-    }))
+    };
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Update,
+        AuditEntity::Teacher,
+        Some(id),
+        before.as_ref(),
+        Some(&teacher),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(teacher))
+}
+
+/// Sets or clears `archived_at` of the teacher, returns `None` if there is no such teacher
+/// in the opposite state
+async fn set_archived(
+    db: &mut PgConnection,
+    id: i32,
+    school_id: i32,
+    archived: bool,
+) -> RouteResult<Option<Teacher>> {
+    let result = sqlx::query(
+        "
+            UPDATE Employees SET archived_at = CASE WHEN $3 THEN now() END
+            WHERE id = $1 AND role = 'teacher' AND school_id = $2 AND (archived_at IS NULL) = $3
+        ",
+    )
+    .bind(id)
+    .bind(school_id)
+    .bind(archived)
+    .execute(&mut *db)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    fetch_for_update(db, id, school_id).await
 }
 
-/// Deletes a teacher by id
+/// Archives a teacher by id. Marks given by the teacher are kept and the teacher can no longer
+/// log in
 #[utoipa::path(
     delete,
     path = "/teachers/{id}",
-    params(("id" = i32, Path, description = "Id of the teacher to delete")),
+    params(("id" = i32, Path, description = "Id of the teacher to archive")),
     tag = "Teachers management",
-    responses((status = 200))
+    responses((status = 200), (status = 412), (status = 428))
 )]
-async fn remove(Path(id): Path<i32>, State(state): RouteState) -> RouteResult {
-    let result = sqlx::query("DELETE FROM Employees WHERE id = $1")
-        .bind(id)
-        .execute(&state.db)
+async fn remove(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+    if_match: IfMatch,
+) -> RouteResult {
+    roles::ensure(&state.db, &claims, Permission::TeachersWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    if_match
+        .lock(&mut tx, "Employees", id, Some(school_id))
         .await?;
-    if result.rows_affected() == 0 {
+    let Some(teacher) = set_archived(&mut tx, id, school_id, true).await? else {
         fail!(!BAD_REQUEST, "Учителя с таким ИД не существует");
-    }
+    };
+    auth::revoke_sessions(&mut *tx, Role::Teacher, id).await?;
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Archive,
+        AuditEntity::Teacher,
+        Some(id),
+        None,
+        Some(&teacher),
+    )
+    .await?;
+    tx.commit().await?;
 
     Ok(())
 }
 
+/// Restores an archived teacher by id
+#[utoipa::path(
+    post,
+    path = "/teachers/{id}/restore",
+    params(("id" = i32, Path, description = "Id of the teacher to restore")),
+    tag = "Teachers management",
+    responses((status = 200, body = Teacher))
+)]
+async fn restore(
+    Path(id): Path<i32>,
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<Tagged<Teacher>> {
+    roles::ensure(&state.db, &claims, Permission::TeachersWrite).await?;
+    let school_id = claims.school()?;
+
+    let mut tx = state.db.begin().await?;
+    let Some(teacher) = set_archived(&mut tx, id, school_id, false).await? else {
+        fail!(!BAD_REQUEST, "Архивного учителя с таким ИД не существует");
+    };
+
+    audit::record(
+        &mut tx,
+        Some(&claims),
+        AuditAction::Restore,
+        AuditEntity::Teacher,
+        Some(id),
+        None,
+        Some(&teacher),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Tagged(teacher))
+}
+
 pub fn openapi() -> utoipa::openapi::OpenApi {
     #[derive(OpenApi)]
     #[openapi(
-        paths(fetch, create, update, remove),
-        components(schemas(Teacher, CreateTeacherRequest, UpdateTeacherRequest))
+        paths(
+            fetch,
+            fetch_one,
+            create,
+            update,
+            patch,
+            remove,
+            restore,
+            fetch_dependencies
+        ),
+        components(schemas(
+            Teacher,
+            TeacherPage,
+            TeacherSort,
+            CreateTeacherRequest,
+            UpdateTeacherRequest,
+            PatchTeacherRequest,
+            Dependency,
+            DependencyKind
+        ))
     )]
     struct Api;
 
@@ -309,5 +830,10 @@ pub fn openapi() -> utoipa::openapi::OpenApi {
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(fetch).post(create))
-        .route("/:id", put(update).delete(remove))
+        .route(
+            "/:id",
+            get(fetch_one).put(update).patch(patch).delete(remove),
+        )
+        .route("/:id/restore", post(restore))
+        .route("/:id/dependencies", get(fetch_dependencies))
 }
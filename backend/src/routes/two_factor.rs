@@ -0,0 +1,377 @@
+use super::{
+    audit::{self, AuditAction, AuditEntity},
+    auth::{hash_token, random_token},
+    Json, RouteResult, RouteState,
+};
+use crate::{
+    fail,
+    middleware::Claims,
+    models::{Participant, Role},
+    AppState,
+};
+use axum::{extract::State, routing::*};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use time::OffsetDateTime;
+use totp_rs::{Algorithm, Secret, TOTP};
+use utoipa::{OpenApi, ToSchema};
+
+const ISSUER: &str = "Школа";
+const RECOVERY_CODES: usize = 10;
+const STEP: u64 = 30;
+
+fn totp(secret: &str, phone: String) -> TOTP {
+    let secret = Secret::Encoded(secret.to_owned()).to_bytes().unwrap();
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        STEP,
+        secret,
+        Some(ISSUER.into()),
+        phone,
+    )
+    .unwrap()
+}
+
+/// Finds the time step the code belongs to, allowing one step of clock drift either way
+fn matching_step(totp: &TOTP, code: &str) -> Option<i64> {
+    let current = OffsetDateTime::now_utc().unix_timestamp() as u64 / STEP;
+
+    (current - 1..=current + 1)
+        .find(|step| totp.check(code, step * STEP))
+        .map(|step| step as i64)
+}
+
+/// Accepts the step of a one-time password unless a code of the same or a later step
+/// was already used, so an intercepted code cannot be replayed
+async fn use_step(db: &mut PgConnection, employee_id: i32, step: i64) -> RouteResult<bool> {
+    let result = sqlx::query(
+        "
+            UPDATE Employees SET totp_last_step = $2
+            WHERE id = $1 AND coalesce(totp_last_step < $2, true)
+        ",
+    )
+    .bind(employee_id)
+    .bind(step)
+    .execute(&mut *db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Checks a one-time password or consumes a recovery code of the employee with 2FA enabled
+pub(super) async fn verify(
+    db: &mut PgConnection,
+    employee_id: i32,
+    code: &str,
+) -> RouteResult<bool> {
+    let code = code.trim();
+
+    if code.len() == 6 && code.bytes().all(|b| b.is_ascii_digit()) {
+        let Some((secret, phone)) = sqlx::query_as::<_, (String, String)>(
+            "SELECT totp_secret, phone FROM Employees WHERE id = $1 AND totp_enabled",
+        )
+        .bind(employee_id)
+        .fetch_optional(&mut *db)
+        .await?
+        else {
+            return Ok(false);
+        };
+
+        let Some(step) = matching_step(&totp(&secret, phone), code) else {
+            return Ok(false);
+        };
+
+        return use_step(db, employee_id, step).await;
+    }
+
+    let result = sqlx::query(
+        "
+            UPDATE RecoveryCodes SET used_at = now()
+            WHERE employee_id = $1 AND code_hash = $2 AND used_at IS NULL
+        ",
+    )
+    .bind(employee_id)
+    .bind(hash_token(code))
+    .execute(&mut *db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Replaces recovery codes of the employee, returning the new ones
+async fn generate_recovery_codes(
+    db: &mut PgConnection,
+    employee_id: i32,
+) -> RouteResult<Vec<String>> {
+    sqlx::query("DELETE FROM RecoveryCodes WHERE employee_id = $1")
+        .bind(employee_id)
+        .execute(&mut *db)
+        .await?;
+
+    let codes = (0..RECOVERY_CODES)
+        .map(|_| random_token(10).to_lowercase())
+        .collect::<Vec<_>>();
+    for code in &codes {
+        sqlx::query(
+            "
+                INSERT INTO RecoveryCodes(employee_id, code_hash) VALUES ($1, $2)
+                ON CONFLICT DO NOTHING
+            ",
+        )
+        .bind(employee_id)
+        .bind(hash_token(code))
+        .execute(&mut *db)
+        .await?;
+    }
+
+    Ok(codes)
+}
+
+/// Records enabling, disabling or regenerating recovery codes of the current employee
+async fn record(db: &mut PgConnection, claims: &Claims, action: AuditAction) -> RouteResult {
+    let employee = Participant {
+        role: claims.role,
+        user_id: claims.user_id,
+    };
+    let (before, after) = match action {
+        AuditAction::Delete => (Some(&employee), None),
+        _ => (None, Some(&employee)),
+    };
+
+    audit::record(
+        db,
+        Some(claims),
+        action,
+        AuditEntity::TwoFactor,
+        Some(claims.user_id),
+        before,
+        after,
+    )
+    .await
+}
+
+fn ensure_employee(claims: &Claims) -> RouteResult {
+    if !claims.role.is_employee() {
+        fail!(
+            !FORBIDDEN,
+            "Двухфакторная аутентификация доступна только сотрудникам"
+        );
+    }
+    if claims.impersonator_id.is_some() {
+        fail!(
+            !FORBIDDEN,
+            "Недоступно при входе от имени другого пользователя"
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, ToSchema)]
+struct SetupResponse {
+    /// Base32 encoded secret for manual entry
+    secret: String,
+    /// `otpauth://` URI to be shown as a QR code
+    otpauth_uri: String,
+}
+
+/// Generates a new secret for the authenticator app.
+/// Two-factor authentication is only enabled after the code is confirmed
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/setup",
+    tag = "Two-factor authentication",
+    responses((status = 200, body = SetupResponse))
+)]
+async fn setup(
+    State(state): RouteState,
+    claims: Claims,
+) -> RouteResult<super::Secret<SetupResponse>> {
+    ensure_employee(&claims)?;
+
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let Some(phone) = sqlx::query_scalar::<_, String>(
+        "
+            UPDATE Employees SET totp_secret = $2, totp_last_step = NULL
+            WHERE id = $1 AND NOT totp_enabled
+            RETURNING phone
+        ",
+    )
+    .bind(claims.user_id)
+    .bind(&secret)
+    .fetch_optional(&state.db)
+    .await?
+    else {
+        fail!(!BAD_REQUEST, "Двухфакторная аутентификация уже подключена");
+    };
+
+    Ok(super::Secret(SetupResponse {
+        otpauth_uri: totp(&secret, phone).get_url(),
+        secret,
+    }))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct CodeRequest {
+    code: String,
+}
+
+/// Enables two-factor authentication after checking the code from the authenticator app.
+/// Returns recovery codes, they are not shown again
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/confirm",
+    tag = "Two-factor authentication",
+    request_body = CodeRequest,
+    responses((status = 200, body = Vec<String>))
+)]
+async fn confirm(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CodeRequest>,
+) -> RouteResult<super::Secret<Vec<String>>> {
+    let CodeRequest { code } = data;
+
+    ensure_employee(&claims)?;
+
+    let mut tx = state.db.begin().await?;
+    let Some((secret, phone)) = sqlx::query_as::<_, (String, String)>(
+        "
+            SELECT totp_secret, phone FROM Employees
+            WHERE id = $1 AND totp_secret IS NOT NULL AND NOT totp_enabled
+            FOR UPDATE
+        ",
+    )
+    .bind(claims.user_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        fail!(!BAD_REQUEST, "Сначала необходимо получить секрет");
+    };
+
+    let Some(step) = matching_step(&totp(&secret, phone), code.trim()) else {
+        fail!(!BAD_REQUEST, "Неправильный код", "code");
+    };
+    if !use_step(&mut tx, claims.user_id, step).await? {
+        fail!(
+            !BAD_REQUEST,
+            "Код уже использован, дождитесь следующего",
+            "code"
+        );
+    }
+
+    sqlx::query("UPDATE Employees SET totp_enabled = true WHERE id = $1")
+        .bind(claims.user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE Sessions SET totp_pending = false WHERE role = $1 AND user_id = $2")
+        .bind(claims.role)
+        .bind(claims.user_id)
+        .execute(&mut *tx)
+        .await?;
+    let codes = generate_recovery_codes(&mut tx, claims.user_id).await?;
+    record(&mut tx, &claims, AuditAction::Create).await?;
+    tx.commit().await?;
+
+    Ok(super::Secret(codes))
+}
+
+/// Generates new recovery codes, invalidating the old ones
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/recovery-codes",
+    tag = "Two-factor authentication",
+    request_body = CodeRequest,
+    responses((status = 200, body = Vec<String>))
+)]
+async fn recovery_codes(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CodeRequest>,
+) -> RouteResult<super::Secret<Vec<String>>> {
+    let CodeRequest { code } = data;
+
+    ensure_employee(&claims)?;
+
+    let mut tx = state.db.begin().await?;
+    if !verify(&mut tx, claims.user_id, &code).await? {
+        fail!(!BAD_REQUEST, "Неправильный код", "code");
+    }
+    let codes = generate_recovery_codes(&mut tx, claims.user_id).await?;
+    record(&mut tx, &claims, AuditAction::Update).await?;
+    tx.commit().await?;
+
+    Ok(super::Secret(codes))
+}
+
+/// Disables two-factor authentication.
+/// Principals cannot disable it while the security policy requires it
+#[utoipa::path(
+    delete,
+    path = "/auth/2fa",
+    tag = "Two-factor authentication",
+    request_body = CodeRequest,
+    responses((status = 200))
+)]
+async fn disable(
+    State(state): RouteState,
+    claims: Claims,
+    Json(data): Json<CodeRequest>,
+) -> RouteResult {
+    let CodeRequest { code } = data;
+
+    ensure_employee(&claims)?;
+
+    if matches!(claims.role, Role::Principal | Role::Admin)
+        && super::policy::requires_2fa(&state.db, claims.school_id).await?
+    {
+        fail!(
+            !FORBIDDEN,
+            "Двухфакторная аутентификация обязательна для завучей"
+        );
+    }
+
+    let mut tx = state.db.begin().await?;
+    if !verify(&mut tx, claims.user_id, &code).await? {
+        fail!(!BAD_REQUEST, "Неправильный код", "code");
+    }
+    sqlx::query(
+        "
+            UPDATE Employees SET totp_enabled = false, totp_secret = NULL, totp_last_step = NULL
+            WHERE id = $1
+        ",
+    )
+    .bind(claims.user_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM RecoveryCodes WHERE employee_id = $1")
+        .bind(claims.user_id)
+        .execute(&mut *tx)
+        .await?;
+    record(&mut tx, &claims, AuditAction::Delete).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    #[derive(OpenApi)]
+    #[openapi(
+        paths(setup, confirm, recovery_codes, disable),
+        components(schemas(SetupResponse, CodeRequest))
+    )]
+    struct Api;
+
+    Api::openapi()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", delete(disable))
+        .route("/setup", post(setup))
+        .route("/confirm", post(confirm))
+        .route("/recovery-codes", post(recovery_codes))
+}
@@ -0,0 +1,127 @@
+use super::{Json, RouteResult};
+use crate::{
+    error::Error,
+    fail,
+    models::{Class, Employee, Room, Student, Subject, Teacher},
+};
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{
+        header::{ETAG, IF_MATCH},
+        request::Parts,
+    },
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sqlx::PgConnection;
+
+/// Rows with a `version` which is bumped on every update, see `versions` migration
+pub trait Versioned {
+    fn version(&self) -> i32;
+}
+
+macro_rules! versioned {
+    ($($model:ty),*) => {
+        $(impl Versioned for $model {
+            fn version(&self) -> i32 {
+                self.version
+            }
+        })*
+    };
+}
+
+versioned!(Student, Employee, Class, Subject, Room);
+
+impl Versioned for Teacher {
+    fn version(&self) -> i32 {
+        self.employee.version
+    }
+}
+
+/// Responds with the row in the body and its version in the `ETag` header
+pub struct Tagged<T>(pub T);
+
+impl<T: Versioned + Serialize> IntoResponse for Tagged<T> {
+    fn into_response(self) -> Response {
+        let tag = format!("\"{}\"", self.0.version());
+        ([(ETAG, tag)], Json(self.0)).into_response()
+    }
+}
+
+/// Versions listed in the `If-Match` header, which is required for changing versioned rows.
+/// `None` stands for `*` matching any version
+pub struct IfMatch(Option<Vec<i32>>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let header = parts
+            .headers
+            .get(IF_MATCH)
+            .ok_or(fail!(PRECONDITION_REQUIRED, "Необходим заголовок If-Match"))?
+            .to_str()
+            .unwrap_or_default()
+            .trim();
+        if header == "*" {
+            return Ok(Self(None));
+        }
+
+        let versions = header
+            .split(',')
+            .map(|tag| {
+                let tag = tag.trim().strip_prefix('"')?.strip_suffix('"')?;
+                tag.parse().ok()
+            })
+            .collect::<Option<_>>()
+            .ok_or(fail!(BAD_REQUEST, "Недействительный заголовок If-Match"))?;
+
+        Ok(Self(Some(versions)))
+    }
+}
+
+impl IfMatch {
+    fn ensure(&self, version: i32) -> RouteResult {
+        match &self.0 {
+            Some(versions) if !versions.contains(&version) => fail!(
+                !PRECONDITION_FAILED,
+                "Запись была изменена другим пользователем, обновите данные"
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if the row was changed since the client has seen it. A missing row passes, so the
+    /// caller reports it as usual
+    pub fn check<T: Versioned>(&self, row: Option<&T>) -> RouteResult {
+        match row {
+            Some(row) => self.ensure(row.version()),
+            None => Ok(()),
+        }
+    }
+
+    /// Locks the row of the table and checks its version, for changes which don't fetch the row
+    /// beforehand. `school_id` is not checked if not set
+    pub async fn lock(
+        &self,
+        db: &mut PgConnection,
+        table: &str,
+        id: i32,
+        school_id: Option<i32>,
+    ) -> RouteResult {
+        let version = sqlx::query_scalar::<_, i32>(&format!(
+            "SELECT version FROM {table} WHERE id = $1 AND coalesce(school_id = $2, true) FOR UPDATE"
+        ))
+        .bind(id)
+        .bind(school_id)
+        .fetch_optional(db)
+        .await?;
+
+        match version {
+            Some(version) => self.ensure(version),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,11 +1,15 @@
-import { createApi, fetchBaseQuery } from '@reduxjs/toolkit/query/react'
-import { API_BASE_URL } from '../config';
+import { createApi } from '@reduxjs/toolkit/query/react'
+import { baseQuery } from './baseQuery';
 
 export interface ILoginRequest {
     phone: string;
     password: string;
+    /** Required when several accounts share the phone, see the 409 response */
+    account?: LoginAccount;
 }
 
+export type LoginAccount = 'employee' | 'student' | 'parent';
+
 export interface ILoginResponse {
     field: string | null;
     message: string;
@@ -23,7 +27,7 @@ export interface IProfile {
 
 export const authApi = createApi({
     reducerPath: 'authApi',
-    baseQuery: fetchBaseQuery({ baseUrl: API_BASE_URL }),
+    baseQuery,
     endpoints: (builder) => ({
         login: builder.mutation<ILoginResponse, ILoginRequest>({
             query: (credentials) => ({
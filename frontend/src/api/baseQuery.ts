@@ -0,0 +1,39 @@
+import { fetchBaseQuery } from '@reduxjs/toolkit/query/react';
+import type { BaseQueryFn, FetchArgs, FetchBaseQueryError } from '@reduxjs/toolkit/query/react';
+import { API_BASE_URL } from '../config';
+
+// Page of a list endpoint, pass `next` or `prev` as `cursor` to fetch the neighbouring pages
+export interface IPage<T> {
+    items: T[];
+    total: number;
+    next: string | null;
+    prev: string | null;
+}
+
+// Changes of a row must carry the `version` it was read with, the server rejects stale ones
+export const ifMatch = (version: number) => ({ 'If-Match': `"${version}"` });
+
+const rawBaseQuery = fetchBaseQuery({ baseUrl: API_BASE_URL });
+
+// Refresh token is single-use, so concurrent requests must share one refresh
+let refreshing: Promise<boolean> | null = null;
+
+export const baseQuery: BaseQueryFn<string | FetchArgs, unknown, FetchBaseQueryError> = async (args, api, extraOptions) => {
+    let result = await rawBaseQuery(args, api, extraOptions);
+
+    if (result.error?.status === 401) {
+        if (!refreshing) {
+            refreshing = Promise.resolve(rawBaseQuery({ url: 'auth/refresh', method: 'POST' }, api, extraOptions))
+                .then((refresh) => !refresh.error)
+                .finally(() => {
+                    refreshing = null;
+                });
+        }
+
+        if (await refreshing) {
+            result = await rawBaseQuery(args, api, extraOptions);
+        }
+    }
+
+    return result;
+};
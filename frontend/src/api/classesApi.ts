@@ -1,13 +1,15 @@
-import { createApi, fetchBaseQuery } from '@reduxjs/toolkit/query/react';
-import { API_BASE_URL } from '../config';
+import { createApi } from '@reduxjs/toolkit/query/react';
+import { baseQuery, ifMatch } from './baseQuery';
+import type { IPage } from './baseQuery';
 
 export interface IClass {
     id: number;
     name: string;
+    version: number;
 }
 
 export interface IClassResponse {
-    data: IClass[];
+    data: IPage<IClass>;
     success: boolean;
 }
 
@@ -17,9 +19,9 @@ export interface ICreateOrUpdateClassRequest {
 
 export const classesApi = createApi({
     reducerPath: 'classesApi',
-    baseQuery: fetchBaseQuery({ baseUrl: API_BASE_URL }),
+    baseQuery,
     endpoints: (builder) => ({
-        getClasses: builder.query<IClassResponse, { name?: string; id?: number; count?: number; offset?: number }>({
+        getClasses: builder.query<IClassResponse, { name?: string; id?: number; count?: number; cursor?: string }>({
             query: (params) => ({
                 url: 'classes',
                 params,
@@ -32,17 +34,19 @@ export const classesApi = createApi({
                 body: newClass,
             }),
         }),
-        updateClass: builder.mutation<IClass, { id: number; data: ICreateOrUpdateClassRequest }>({
-            query: ({ id, data }) => ({
+        updateClass: builder.mutation<IClass, { id: number; version: number; data: ICreateOrUpdateClassRequest }>({
+            query: ({ id, version, data }) => ({
                 url: `classes/${id}`,
                 method: 'PUT',
+                headers: ifMatch(version),
                 body: data,
             }),
         }),
-        deleteClass: builder.mutation<{ success: boolean }, number>({
-            query: (id) => ({
+        deleteClass: builder.mutation<{ success: boolean }, { id: number; version: number }>({
+            query: ({ id, version }) => ({
                 url: `classes/${id}`,
                 method: 'DELETE',
+                headers: ifMatch(version),
             }),
         }),
     }),
@@ -1,6 +1,7 @@
 // src/api/marksApi.ts
-import { createApi, fetchBaseQuery } from '@reduxjs/toolkit/query/react';
-import { API_BASE_URL } from '../config';
+import { createApi } from '@reduxjs/toolkit/query/react';
+import { baseQuery } from './baseQuery';
+import type { IPage } from './baseQuery';
 
 export interface IMark {
     id: number;
@@ -12,7 +13,7 @@ export interface IMark {
 }
 
 export interface IMarkResponse {
-    data: IMark[];
+    data: IPage<IMark>;
     success: boolean;
 }
 
@@ -25,7 +26,7 @@ export interface ICreateMarkRequest {
 
 export const marksApi = createApi({
     reducerPath: 'marksApi',
-    baseQuery: fetchBaseQuery({ baseUrl: API_BASE_URL }),
+    baseQuery,
     endpoints: (builder) => ({
         getMarks: builder.query<IMarkResponse, {
             student_ids?: number[];
@@ -36,7 +37,7 @@ export const marksApi = createApi({
             after?: string;
             before?: string;
             count?: number;
-            offset?: number;
+            cursor?: string;
         }>({
             query: (params) => {
                 const queryParams = new URLSearchParams();
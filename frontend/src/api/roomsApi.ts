@@ -1,14 +1,16 @@
 // src/api/roomsApi.ts
-import { createApi, fetchBaseQuery } from '@reduxjs/toolkit/query/react';
-import { API_BASE_URL } from '../config';
+import { createApi } from '@reduxjs/toolkit/query/react';
+import { baseQuery, ifMatch } from './baseQuery';
+import type { IPage } from './baseQuery';
 
 export interface IRoom {
     id: number;
     name: string;
+    version: number;
 }
 
 export interface IRoomResponse {
-    data: IRoom[];
+    data: IPage<IRoom>;
     success: boolean;
 }
 
@@ -18,9 +20,9 @@ export interface ICreateOrUpdateRoomRequest {
 
 export const roomsApi = createApi({
     reducerPath: 'roomsApi',
-    baseQuery: fetchBaseQuery({ baseUrl: API_BASE_URL }),
+    baseQuery,
     endpoints: (builder) => ({
-        getRooms: builder.query<IRoomResponse, { name?: string; id?: number; subject_ids?: number[]; count?: number; offset?: number }>({
+        getRooms: builder.query<IRoomResponse, { name?: string; id?: number; subject_ids?: number[]; count?: number; cursor?: string }>({
             query: (params) => ({
                 url: 'rooms',
                 params,
@@ -33,17 +35,19 @@ export const roomsApi = createApi({
                 body: newRoom,
             }),
         }),
-        updateRoom: builder.mutation<IRoom, { id: number; data: ICreateOrUpdateRoomRequest }>({
-            query: ({ id, data }) => ({
+        updateRoom: builder.mutation<IRoom, { id: number; version: number; data: ICreateOrUpdateRoomRequest }>({
+            query: ({ id, version, data }) => ({
                 url: `rooms/${id}`,
                 method: 'PUT',
+                headers: ifMatch(version),
                 body: data,
             }),
         }),
-        deleteRoom: builder.mutation<{ success: boolean }, number>({
-            query: (id) => ({
+        deleteRoom: builder.mutation<{ success: boolean }, { id: number; version: number }>({
+            query: ({ id, version }) => ({
                 url: `rooms/${id}`,
                 method: 'DELETE',
+                headers: ifMatch(version),
             }),
         }),
     }),
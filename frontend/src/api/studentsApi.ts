@@ -1,6 +1,7 @@
 // src/api/studentsApi.ts
-import { createApi, fetchBaseQuery } from '@reduxjs/toolkit/query/react';
-import { API_BASE_URL } from '../config';
+import { createApi } from '@reduxjs/toolkit/query/react';
+import { baseQuery, ifMatch } from './baseQuery';
+import type { IPage } from './baseQuery';
 
 export interface IStudent {
     id: number;
@@ -9,10 +10,11 @@ export interface IStudent {
     middle_name: string;
     class_id: number;
     phone: string;
+    version: number;
 }
 
 export interface IStudentResponse {
-    data: IStudent[];
+    data: IPage<IStudent>;
     success: boolean;
 }
 
@@ -27,9 +29,9 @@ export interface ICreateOrUpdateStudentRequest {
 
 export const studentsApi = createApi({
     reducerPath: 'studentsApi',
-    baseQuery: fetchBaseQuery({ baseUrl: API_BASE_URL }),
+    baseQuery,
     endpoints: (builder) => ({
-        getStudents: builder.query<IStudentResponse, { name?: string; id?: number; class_ids?: number[]; count?: number; offset?: number }>({
+        getStudents: builder.query<IStudentResponse, { name?: string; id?: number; class_ids?: number[]; count?: number; cursor?: string }>({
             query: (params) => ({
                 url: 'students',
                 params,
@@ -42,17 +44,19 @@ export const studentsApi = createApi({
                 body: newStudent,
             }),
         }),
-        updateStudent: builder.mutation<IStudent, { id: number; data: ICreateOrUpdateStudentRequest }>({
-            query: ({ id, data }) => ({
+        updateStudent: builder.mutation<IStudent, { id: number; version: number; data: ICreateOrUpdateStudentRequest }>({
+            query: ({ id, version, data }) => ({
                 url: `students/${id}`,
                 method: 'PUT',
+                headers: ifMatch(version),
                 body: data,
             }),
         }),
-        deleteStudent: builder.mutation<{ success: boolean }, number>({
-            query: (id) => ({
+        deleteStudent: builder.mutation<{ success: boolean }, { id: number; version: number }>({
+            query: ({ id, version }) => ({
                 url: `students/${id}`,
                 method: 'DELETE',
+                headers: ifMatch(version),
             }),
         }),
     }),
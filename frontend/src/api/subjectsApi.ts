@@ -1,13 +1,15 @@
-import { createApi, fetchBaseQuery } from '@reduxjs/toolkit/query/react';
-import { API_BASE_URL } from '../config';
+import { createApi } from '@reduxjs/toolkit/query/react';
+import { baseQuery, ifMatch } from './baseQuery';
+import type { IPage } from './baseQuery';
 
 export interface ISubject {
     id: number;
     name: string;
+    version: number;
 }
 
 export interface ISubjectResponse {
-    data: ISubject[];
+    data: IPage<ISubject>;
     success: boolean;
 }
 
@@ -17,9 +19,9 @@ export interface ICreateOrUpdateSubjectRequest {
 
 export const subjectsApi = createApi({
     reducerPath: 'subjectsApi',
-    baseQuery: fetchBaseQuery({ baseUrl: API_BASE_URL }),
+    baseQuery,
     endpoints: (builder) => ({
-        getSubjects: builder.query<ISubjectResponse, { name?: string; id?: number; count?: number; offset?: number }>({
+        getSubjects: builder.query<ISubjectResponse, { name?: string; id?: number; count?: number; cursor?: string }>({
             query: (params) => ({
                 url: 'subjects',
                 params,
@@ -32,17 +34,19 @@ export const subjectsApi = createApi({
                 body: newSubject,
             }),
         }),
-        updateSubject: builder.mutation<ISubject, { id: number; data: ICreateOrUpdateSubjectRequest }>({
-            query: ({ id, data }) => ({
+        updateSubject: builder.mutation<ISubject, { id: number; version: number; data: ICreateOrUpdateSubjectRequest }>({
+            query: ({ id, version, data }) => ({
                 url: `subjects/${id}`,
                 method: 'PUT',
+                headers: ifMatch(version),
                 body: data,
             }),
         }),
-        deleteSubject: builder.mutation<{ success: boolean }, number>({
-            query: (id) => ({
+        deleteSubject: builder.mutation<{ success: boolean }, { id: number; version: number }>({
+            query: ({ id, version }) => ({
                 url: `subjects/${id}`,
                 method: 'DELETE',
+                headers: ifMatch(version),
             }),
         }),
     }),
@@ -1,6 +1,7 @@
 // src/api/teachersApi.ts
-import { createApi, fetchBaseQuery } from '@reduxjs/toolkit/query/react';
-import { API_BASE_URL } from '../config';
+import { createApi } from '@reduxjs/toolkit/query/react';
+import { baseQuery, ifMatch } from './baseQuery';
+import type { IPage } from './baseQuery';
 
 export interface ITeacher {
     id: number;
@@ -12,10 +13,11 @@ export interface ITeacher {
     subject_id: number;
     employed_at: string;
     role: string;
+    version: number;
 }
 
 export interface ITeacherResponse {
-    data: ITeacher[];
+    data: IPage<ITeacher>;
     success: boolean;
 }
 
@@ -30,9 +32,9 @@ export interface ICreateOrUpdateTeacherRequest {
 
 export const teachersApi = createApi({
     reducerPath: 'teachersApi',
-    baseQuery: fetchBaseQuery({ baseUrl: API_BASE_URL }),
+    baseQuery,
     endpoints: (builder) => ({
-        getTeachers: builder.query<ITeacherResponse, { name?: string; id?: number; subject_ids?: number[]; room_ids?: number[]; count?: number; offset?: number }>({
+        getTeachers: builder.query<ITeacherResponse, { name?: string; id?: number; subject_ids?: number[]; room_ids?: number[]; count?: number; cursor?: string }>({
             query: (params) => ({
                 url: 'teachers',
                 params,
@@ -45,17 +47,19 @@ export const teachersApi = createApi({
                 body: newTeacher,
             }),
         }),
-        updateTeacher: builder.mutation<ITeacher, { id: number; data: ICreateOrUpdateTeacherRequest }>({
-            query: ({ id, data }) => ({
+        updateTeacher: builder.mutation<ITeacher, { id: number; version: number; data: ICreateOrUpdateTeacherRequest }>({
+            query: ({ id, version, data }) => ({
                 url: `teachers/${id}`,
                 method: 'PUT',
+                headers: ifMatch(version),
                 body: data,
             }),
         }),
-        deleteTeacher: builder.mutation<{ success: boolean }, number>({
-            query: (id) => ({
+        deleteTeacher: builder.mutation<{ success: boolean }, { id: number; version: number }>({
+            query: ({ id, version }) => ({
                 url: `teachers/${id}`,
                 method: 'DELETE',
+                headers: ifMatch(version),
             }),
         }),
     }),
@@ -1,6 +1,6 @@
 // src/components/AuthComponent.tsx
 import React, { useState } from "react";
-import { useLoginMutation } from "../../api/authApi";
+import { LoginAccount, useLoginMutation } from "../../api/authApi";
 import { useAppDispatch } from "../../store/store";
 import {
   Alert,
@@ -19,18 +19,26 @@ const AuthComponent: React.FC = () => {
   const [password, setPassword] = useState("");
   const [login, { isLoading }] = useLoginMutation();
   const [error, setError] = useState<string | null>(null);
+  const [accounts, setAccounts] = useState<LoginAccount[]>([]);
   const [snackbarOpen, setSnackbarOpen] = useState(false);
   const dispatch = useAppDispatch();
   const navigate = useNavigate();
-  const handleLogin = async () => {
+  const handleLogin = async (account?: LoginAccount) => {
     try {
-      const userData = await login({ phone, password }).unwrap();
+      const userData = await login({ phone, password, account }).unwrap();
       console.log(userData);
       setError(null);
+      setAccounts([]);
       setSnackbarOpen(true);
       dispatch(setUser({ phone, role: "Teacher" }));
       navigate("/");
     } catch (error) {
+      // Several accounts share the phone, the user picks one and logs in again
+      const details = (error as { data?: { details?: { accounts?: LoginAccount[] } } })
+        ?.data?.details;
+      if (details?.accounts) {
+        setAccounts(details.accounts);
+      }
       if ((error as { data: { message: string } })?.data?.message) {
         setError((error as { data: { message: string } }).data.message);
       } else {
@@ -87,11 +95,24 @@ const AuthComponent: React.FC = () => {
             fullWidth
             variant="contained"
             sx={{ mt: 3, mb: 2 }}
-            onClick={handleLogin}
+            onClick={() => handleLogin()}
             disabled={isLoading}
           >
             Sign In
           </Button>
+          {accounts.map((account) => (
+            <Button
+              key={account}
+              type="button"
+              fullWidth
+              variant="outlined"
+              sx={{ mb: 1 }}
+              onClick={() => handleLogin(account)}
+              disabled={isLoading}
+            >
+              Sign in as {account}
+            </Button>
+          ))}
         </Box>
       </Box>
       <Snackbar
@@ -33,7 +33,7 @@ const ClassesComponent: React.FC = () => {
     isSuccess,
     refetch,
   } = useGetClassesQuery({});
-  const classes = response?.data || [];
+  const classes = response?.data.items || [];
   const [trigger, { data: data1, isLoading: classInfoisLoading }] =
     useLazyGetStudentsQuery();
   const [newClassName, setNewClassName] = useState("");
@@ -82,9 +82,9 @@ const ClassesComponent: React.FC = () => {
     }
   };
 
-  const handleUpdateClass = async (id: number) => {
+  const handleUpdateClass = async (id: number, version: number) => {
     try {
-      await updateClass({ id, data: { name: editClassNames[id] } }).unwrap();
+      await updateClass({ id, version, data: { name: editClassNames[id] } }).unwrap();
       setIsEditing({ ...isEditing, [id]: false });
       refetch();
       setError(null);
@@ -95,9 +95,9 @@ const ClassesComponent: React.FC = () => {
     }
   };
 
-  const handleDeleteClass = async (id: number) => {
+  const handleDeleteClass = async (id: number, version: number) => {
     try {
-      await deleteClass(id).unwrap();
+      await deleteClass({ id, version }).unwrap();
       refetch();
       setError(null);
       setSnackbarOpen(true);
@@ -213,7 +213,7 @@ const ClassesComponent: React.FC = () => {
                       {isEditing[classItem.id] ? (
                         <>
                           <Button
-                            onClick={() => handleUpdateClass(classItem.id)}
+                            onClick={() => handleUpdateClass(classItem.id, classItem.version)}
                             variant="contained"
                             color="primary"
                             sx={{ marginRight: 1 }}
@@ -238,7 +238,7 @@ const ClassesComponent: React.FC = () => {
                             Update
                           </Button>
                           <Button
-                            onClick={() => handleDeleteClass(classItem.id)}
+                            onClick={() => handleDeleteClass(classItem.id, classItem.version)}
                             variant="contained"
                             color="secondary"
                           >
@@ -263,9 +263,9 @@ const ClassesComponent: React.FC = () => {
         </TableContainer>
       </div>
       <Popup isOpen={isPopupOpen} onClose={handleClosePopup}>
-        {data1 && data1.data.length > 0 ? (
+        {data1 && data1.data.items.length > 0 ? (
           <div>
-            {data1.data.map((studentInfo) => (
+            {data1.data.items.map((studentInfo) => (
               <>
                 <p>Имя:{studentInfo.first_name}</p>
                 <p>Фамилия:{studentInfo.middle_name}</p>
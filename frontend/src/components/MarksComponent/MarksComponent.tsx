@@ -18,16 +18,16 @@ const MarksComponent: React.FC = () => {
     });
 
     const [getMarks, { data: marksResponse, isLoading: marksLoading, isError: marksError }] = useLazyGetMarksQuery();
-    const marks = marksResponse?.data || [];
+    const marks = marksResponse?.data.items || [];
 
     const { data: studentsResponse } = useGetStudentsQuery({});
-    const students = studentsResponse?.data || [];
+    const students = studentsResponse?.data.items || [];
 
     const { data: teachersResponse } = useGetTeachersQuery({});
-    const teachers = teachersResponse?.data || [];
+    const teachers = teachersResponse?.data.items || [];
 
     const { data: subjectsResponse } = useGetSubjectsQuery({});
-    const subjects = subjectsResponse?.data || [];
+    const subjects = subjectsResponse?.data.items || [];
 
     const [error, setError] = useState<string | null>(null);
     const [snackbarOpen, setSnackbarOpen] = useState(false);
@@ -9,13 +9,13 @@ import { DataGrid, GridColDef } from '@mui/x-data-grid';
 const MarksTableComponent: React.FC = () => {
     const { data: profile, isLoading: profileLoading, isError: profileError } = useMeQuery();
     const [getMarks, { data: marksResponse, isLoading: marksLoading, isError: marksError }] = useLazyGetMarksQuery();
-    const marks = marksResponse?.data || [];
+    const marks = marksResponse?.data.items || [];
 
     const { data: studentsResponse, isLoading: studentsLoading, isError: studentsError } = useGetStudentsQuery({});
-    const students = studentsResponse?.data || [];
+    const students = studentsResponse?.data.items || [];
 
     const [getSubjects, { data: subjectsResponse, isLoading: subjectsLoading, isError: subjectsError }] = useLazyGetSubjectsQuery();
-    const subjects = subjectsResponse?.data || [];
+    const subjects = subjectsResponse?.data.items || [];
 
     const [createMark] = useCreateMarkMutation();
     const [selectedSubject, setSelectedSubject] = useState<number | null>(null);
@@ -32,10 +32,10 @@ import { Edit, Delete, Save, Cancel } from '@mui/icons-material';
 
 const RoomsComponent: React.FC = () => {
     const { data: response, isLoading: roomsLoading, isError: roomsError, isSuccess: roomsSuccess, refetch } = useGetRoomsQuery({ });
-    const rooms = response?.data || [];
+    const rooms = response?.data.items || [];
 
     const { data: subjectsResponse, isLoading: subjectsLoading, isError: subjectsError } = useGetSubjectsQuery();
-    const subjects = subjectsResponse?.data || [];
+    const subjects = subjectsResponse?.data.items || [];
 
     const [newRoomName, setNewRoomName] = useState('');
     const [newRoomSubjectId, setNewRoomSubjectId] = useState<number | null>(null);
@@ -85,11 +85,11 @@ const RoomsComponent: React.FC = () => {
         }
     };
 
-    const handleUpdateRoom = async (id: number) => {
+    const handleUpdateRoom = async (id: number, version: number) => {
         if (editRoomSubjects[id] === undefined) return;
 
         try {
-            await updateRoom({ id, data: { name: editRoomNames[id], subject_id: editRoomSubjects[id] } }).unwrap();
+            await updateRoom({ id, version, data: { name: editRoomNames[id], subject_id: editRoomSubjects[id] } }).unwrap();
             setIsEditing({ ...isEditing, [id]: false });
             refetch();
             setError(null);
@@ -100,9 +100,9 @@ const RoomsComponent: React.FC = () => {
         }
     };
 
-    const handleDeleteRoom = async (id: number) => {
+    const handleDeleteRoom = async (id: number, version: number) => {
         try {
-            await deleteRoom(id).unwrap();
+            await deleteRoom({ id, version }).unwrap();
             refetch();
             setError(null);
             setSnackbarOpen(true);
@@ -214,7 +214,7 @@ const RoomsComponent: React.FC = () => {
                             <ListItemSecondaryAction>
                                 {isEditing[room.id] ? (
                                     <>
-                                        <IconButton onClick={() => handleUpdateRoom(room.id)} color="primary">
+                                        <IconButton onClick={() => handleUpdateRoom(room.id, room.version)} color="primary">
                                             <Save />
                                         </IconButton>
                                         <IconButton onClick={() => handleCancelClick(room.id)} color="secondary">
@@ -226,7 +226,7 @@ const RoomsComponent: React.FC = () => {
                                         <IconButton onClick={() => handleEditClick(room.id)} color="primary">
                                             <Edit />
                                         </IconButton>
-                                        <IconButton onClick={() => handleDeleteRoom(room.id)} color="secondary">
+                                        <IconButton onClick={() => handleDeleteRoom(room.id, room.version)} color="secondary">
                                             <Delete />
                                         </IconButton>
                                     </>
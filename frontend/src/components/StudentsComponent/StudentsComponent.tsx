@@ -37,10 +37,10 @@ import { Edit, Delete, Save, Cancel } from '@mui/icons-material';
 
 const StudentsComponent: React.FC = () => {
     const { data: response, isLoading, isError, isSuccess, refetch } = useGetStudentsQuery({});
-    const students = response?.data || [];
+    const students = response?.data.items || [];
 
     const { data: classesResponse } = useGetClassesQuery({});
-    const classes = classesResponse?.data || [];
+    const classes = classesResponse?.data.items || [];
 
     const [newStudent, setNewStudent] = useState({
         first_name: '',
@@ -104,10 +104,10 @@ const StudentsComponent: React.FC = () => {
         }
     };
 
-    const handleUpdateStudent = async (id: number) => {
+    const handleUpdateStudent = async (id: number, version: number) => {
         try {
             const { first_name, last_name, middle_name, class_id, phone } = editStudentData[id];
-            await updateStudent({ id, data: { first_name, last_name, middle_name, class_id, phone, password: '' } }).unwrap();
+            await updateStudent({ id, version, data: { first_name, last_name, middle_name, class_id, phone, password: '' } }).unwrap();
             setIsEditing({ ...isEditing, [id]: false });
             refetch();
             setError(null);
@@ -118,9 +118,9 @@ const StudentsComponent: React.FC = () => {
         }
     };
 
-    const handleDeleteStudent = async (id: number) => {
+    const handleDeleteStudent = async (id: number, version: number) => {
         try {
-            await deleteStudent(id).unwrap();
+            await deleteStudent({ id, version }).unwrap();
             refetch();
             setError(null);
             setSnackbarOpen(true);
@@ -319,7 +319,7 @@ const StudentsComponent: React.FC = () => {
                                         </Select>
                                     </FormControl>
                                     <Box sx={{ display: 'flex', justifyContent: 'flex-end', gap: 1 }}>
-                                        <Button variant="contained" color="primary" onClick={() => handleUpdateStudent(student.id)}>
+                                        <Button variant="contained" color="primary" onClick={() => handleUpdateStudent(student.id, student.version)}>
                                             Сохранить
                                         </Button>
                                         <Button variant="outlined" color="secondary" onClick={() => handleCancelClick(student.id)}>
@@ -337,7 +337,7 @@ const StudentsComponent: React.FC = () => {
                                         <IconButton onClick={() => handleEditClick(student.id)} color="primary">
                                             <Edit />
                                         </IconButton>
-                                        <IconButton onClick={() => handleDeleteStudent(student.id)} color="secondary">
+                                        <IconButton onClick={() => handleDeleteStudent(student.id, student.version)} color="secondary">
                                             <Delete />
                                         </IconButton>
                                     </ListItemSecondaryAction>
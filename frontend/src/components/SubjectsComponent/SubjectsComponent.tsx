@@ -41,7 +41,7 @@ const SubjectsComponent: React.FC = () => {
     refetch,
   } = useGetSubjectsQuery({});
   const [trigger, { data: data1 }] = useLazyGetTeachersQuery();
-  const subjects = response?.data || [];
+  const subjects = response?.data.items || [];
 
   const [newSubjectName, setNewSubjectName] = useState("");
   const [editSubjectNames, setEditSubjectNames] = useState<{ [id: number]: string }>({});
@@ -84,9 +84,9 @@ const SubjectsComponent: React.FC = () => {
     }
   };
 
-  const handleUpdateSubject = async (id: number) => {
+  const handleUpdateSubject = async (id: number, version: number) => {
     try {
-      await updateSubject({ id, data: { name: editSubjectNames[id] } }).unwrap();
+      await updateSubject({ id, version, data: { name: editSubjectNames[id] } }).unwrap();
       setIsEditing({ ...isEditing, [id]: false });
       refetch();
       setError(null);
@@ -97,9 +97,9 @@ const SubjectsComponent: React.FC = () => {
     }
   };
 
-  const handleDeleteSubject = async (id: number) => {
+  const handleDeleteSubject = async (id: number, version: number) => {
     try {
-      await deleteSubject(id).unwrap();
+      await deleteSubject({ id, version }).unwrap();
       refetch();
       setError(null);
       setSnackbarOpen(true);
@@ -193,7 +193,7 @@ const SubjectsComponent: React.FC = () => {
               <ListItemSecondaryAction>
                 {isEditing[subject.id] ? (
                   <>
-                    <IconButton onClick={() => handleUpdateSubject(subject.id)} color="primary">
+                    <IconButton onClick={() => handleUpdateSubject(subject.id, subject.version)} color="primary">
                       <Save />
                     </IconButton>
                     <IconButton onClick={() => handleCancelClick(subject.id)} color="secondary">
@@ -205,7 +205,7 @@ const SubjectsComponent: React.FC = () => {
                     <IconButton onClick={() => handleEditClick(subject.id)} color="primary">
                       <Edit />
                     </IconButton>
-                    <IconButton onClick={() => handleDeleteSubject(subject.id)} color="secondary">
+                    <IconButton onClick={() => handleDeleteSubject(subject.id, subject.version)} color="secondary">
                       <Delete />
                     </IconButton>
                   </>
@@ -225,9 +225,9 @@ const SubjectsComponent: React.FC = () => {
         />
       </Paper>
       <Popup isOpen={isPopupOpen} onClose={handleClosePopup}>
-        {data1 && data1.data.length > 0 ? (
+        {data1 && data1.data.items.length > 0 ? (
           <Box>
-            {data1.data.map((studentInfo) => (
+            {data1.data.items.map((studentInfo) => (
               <Box key={studentInfo.id}>
                 <Typography>Имя: {studentInfo.first_name}</Typography>
                 <Typography>Фамилия: {studentInfo.middle_name}</Typography>
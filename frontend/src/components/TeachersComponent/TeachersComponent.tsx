@@ -38,13 +38,13 @@ import { Edit, Delete, Save, Cancel } from '@mui/icons-material';
 
 const TeachersComponent: React.FC = () => {
     const { data: response, isLoading, isError, isSuccess, refetch } = useGetTeachersQuery({});
-    const teachers = response?.data || [];
+    const teachers = response?.data.items || [];
 
     const { data: roomsResponse } = useGetRoomsQuery({});
-    const rooms = roomsResponse?.data || [];
+    const rooms = roomsResponse?.data.items || [];
 
     const { data: subjectsResponse } = useGetSubjectsQuery({});
-    const subjects = subjectsResponse?.data || [];
+    const subjects = subjectsResponse?.data.items || [];
 
     const [newTeacher, setNewTeacher] = useState({
         first_name: '',
@@ -111,10 +111,10 @@ const TeachersComponent: React.FC = () => {
         }
     };
 
-    const handleUpdateTeacher = async (id: number) => {
+    const handleUpdateTeacher = async (id: number, version: number) => {
         try {
             const { first_name, last_name, middle_name, phone, subject_id, room_id } = editTeacherData[id];
-            await updateTeacher({ id, data: { first_name, last_name, middle_name, phone, subject_id, room_id } }).unwrap();
+            await updateTeacher({ id, version, data: { first_name, last_name, middle_name, phone, subject_id, room_id } }).unwrap();
             setIsEditing({ ...isEditing, [id]: false });
             refetch();
             setError(null);
@@ -125,9 +125,9 @@ const TeachersComponent: React.FC = () => {
         }
     };
 
-    const handleDeleteTeacher = async (id: number) => {
+    const handleDeleteTeacher = async (id: number, version: number) => {
         try {
-            await deleteTeacher(id).unwrap();
+            await deleteTeacher({ id, version }).unwrap();
             refetch();
             setError(null);
             setSnackbarOpen(true);
@@ -355,7 +355,7 @@ const TeachersComponent: React.FC = () => {
                                         </Select>
                                     </FormControl>
                                     <Box sx={{ display: 'flex', justifyContent: 'flex-end', gap: 1 }}>
-                                        <Button variant="contained" color="primary" onClick={() => handleUpdateTeacher(teacher.id)}>
+                                        <Button variant="contained" color="primary" onClick={() => handleUpdateTeacher(teacher.id, teacher.version)}>
                                             Save
                                         </Button>
                                         <Button variant="outlined" color="secondary" onClick={() => handleCancelClick(teacher.id)}>
@@ -373,7 +373,7 @@ const TeachersComponent: React.FC = () => {
                                         <IconButton onClick={() => handleEditClick(teacher.id)} color="primary">
                                             <Edit />
                                         </IconButton>
-                                        <IconButton onClick={() => handleDeleteTeacher(teacher.id)} color="secondary">
+                                        <IconButton onClick={() => handleDeleteTeacher(teacher.id, teacher.version)} color="secondary">
                                             <Delete />
                                         </IconButton>
                                     </ListItemSecondaryAction>